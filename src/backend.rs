@@ -0,0 +1,195 @@
+use crossterm::event::Event;
+use crossterm::Result;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::display;
+use crate::keys;
+use crate::screen::{Screen, Style};
+
+/// Everything the editor needs from a terminal.
+pub trait Backend: Debug {
+    /// Terminal size as (columns, rows).
+    fn size(&self) -> (usize, usize);
+    fn move_cursor(&mut self, x: usize, y: usize) -> Result<()>;
+    fn show_cursor(&mut self, visible: bool) -> Result<()>;
+    fn clear(&mut self) -> Result<()>;
+    fn set_style(&mut self, style: Style) -> Result<()>;
+    /// Prints a grapheme at the cursor and moves it right by its width.
+    fn print(&mut self, symbol: &str) -> Result<()>;
+    fn flush(&mut self) -> Result<()>;
+    /// Puts `text` on the system clipboard, or the primary selection.
+    fn set_clipboard(&mut self, text: &str, primary: bool) -> Result<()>;
+    /// Blocks for the next event. `None` means there will be no more.
+    fn read_event(&mut self) -> Result<Option<Event>>;
+}
+
+/// Sends the cells of `screen` that changed since `prev` was drawn, then
+/// places the cursor. Everything goes out in a single flush.
+pub fn draw_screen(backend: &mut dyn Backend, screen: &Screen, prev: &Screen) -> Result<()> {
+    backend.show_cursor(false)?;
+    let mut style: Option<Style> = None;
+    let mut next_pos = None;
+    for (x, y, cell) in screen.changes(prev) {
+        if cell.is_continuation() {
+            // filled by the wide grapheme to its left
+            continue;
+        }
+        if next_pos != Some((x, y)) {
+            backend.move_cursor(x, y)?;
+        }
+        if style != Some(cell.style) {
+            backend.set_style(cell.style)?;
+            style = Some(cell.style);
+        }
+        backend.print(&cell.symbol)?;
+        next_pos = Some((x + display::str_width(&cell.symbol), y));
+    }
+    backend.set_style(Style::default())?;
+    let (x, y) = screen.cursor;
+    backend.move_cursor(x, y)?;
+    backend.show_cursor(true)?;
+    backend.flush()
+}
+
+#[derive(Debug)]
+struct VirtualState {
+    screen: Screen,
+    style: Style,
+    events: VecDeque<Event>,
+    flushes: usize,
+    clipboard: Option<String>,
+}
+
+/// In memory terminal for running the editor headless.
+///
+/// Clones share the same state, so a test can keep one handle while the
+/// editor owns another, queue key presses and then inspect what was drawn.
+#[derive(Debug, Clone)]
+pub struct VirtualBackend {
+    state: Rc<RefCell<VirtualState>>,
+}
+
+impl VirtualBackend {
+    pub fn new(width: usize, height: usize) -> VirtualBackend {
+        VirtualBackend {
+            state: Rc::new(RefCell::new(VirtualState {
+                screen: Screen::new(width, height),
+                style: Style::default(),
+                events: VecDeque::new(),
+                flushes: 0,
+                clipboard: None,
+            })),
+        }
+    }
+
+    pub fn push_event(&self, event: Event) {
+        self.state.borrow_mut().events.push_back(event);
+    }
+
+    /// Queues key presses written in vim notation, e.g. `ihello<Esc>`.
+    pub fn push_keys(&self, keys: &str) {
+        for key in keys::parse_keys(keys) {
+            self.push_event(Event::Key(key));
+        }
+    }
+
+    /// Changes the size reported to the editor and queues a resize event.
+    /// What was drawn is kept where it still fits, like a real terminal.
+    pub fn resize(&self, width: usize, height: usize) {
+        let mut state = self.state.borrow_mut();
+        let mut screen = Screen::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                if let Some(cell) = state.screen.get(x, y) {
+                    screen.put_cell(x, y, cell.clone());
+                }
+            }
+        }
+        state.screen = screen;
+        state
+            .events
+            .push_back(Event::Resize(width as u16, height as u16));
+    }
+
+    /// Copy of everything drawn so far.
+    pub fn screen(&self) -> Screen {
+        self.state.borrow().screen.clone()
+    }
+
+    /// Text of screen row `y`, trailing spaces removed.
+    pub fn row_text(&self, y: usize) -> String {
+        let state = self.state.borrow();
+        let row: String = (0..state.screen.width)
+            .filter_map(|x| state.screen.get(x, y).map(|c| c.symbol.as_str()))
+            .collect();
+        row.trim_end().to_string()
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        self.state.borrow().screen.cursor
+    }
+
+    /// Number of times the editor flushed output.
+    pub fn flushes(&self) -> usize {
+        self.state.borrow().flushes
+    }
+
+    /// Text last put on the clipboard through the terminal.
+    pub fn clipboard(&self) -> Option<String> {
+        self.state.borrow().clipboard.clone()
+    }
+}
+
+impl Backend for VirtualBackend {
+    fn size(&self) -> (usize, usize) {
+        let state = self.state.borrow();
+        (state.screen.width, state.screen.height)
+    }
+
+    fn move_cursor(&mut self, x: usize, y: usize) -> Result<()> {
+        self.state.borrow_mut().screen.set_cursor(x, y);
+        Ok(())
+    }
+
+    fn show_cursor(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        let (width, height) = (state.screen.width, state.screen.height);
+        state.screen = Screen::new(width, height);
+        Ok(())
+    }
+
+    fn set_style(&mut self, style: Style) -> Result<()> {
+        self.state.borrow_mut().style = style;
+        Ok(())
+    }
+
+    fn print(&mut self, symbol: &str) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        let (x, y) = state.screen.cursor;
+        let style = state.style;
+        let next = state.screen.put_str(x, y, symbol, style);
+        state.screen.set_cursor(next, y);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.state.borrow_mut().flushes += 1;
+        Ok(())
+    }
+
+    fn set_clipboard(&mut self, text: &str, _primary: bool) -> Result<()> {
+        self.state.borrow_mut().clipboard = Some(text.to_string());
+        Ok(())
+    }
+
+    fn read_event(&mut self) -> Result<Option<Event>> {
+        Ok(self.state.borrow_mut().events.pop_front())
+    }
+}
@@ -0,0 +1,493 @@
+use ropey::iter::{Chars, Chunks};
+use ropey::{Rope, RopeBuilder};
+use std::io;
+use std::ops::Range;
+
+use crate::mark::{LineShift, Marks};
+use crate::motion::Pos;
+use crate::undo::{Edit, UndoStep, UndoTree};
+
+/// Bytes [`Buffer::from_reader`] reads at a time.
+const READ_CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// Text storage for a single file.
+///
+/// Backed by a rope so edits in the middle of large files stay cheap. Line
+/// endings are normalised to `\n` on load and the final line ending is not
+/// stored; `line_ending` and `trailing_newline` remember what the file had so
+/// it can be written back unchanged. Line indexes are zero based and never
+/// include the line ending. Char offsets count unicode scalar values from the
+/// start of the buffer.
+///
+/// Every insert and remove is recorded in the buffer's undo tree. Edits made
+/// between `begin_undo_group` and `end_undo_group` undo as a single change.
+#[derive(Debug)]
+pub struct Buffer {
+    text: Rope,
+    pub line_ending: LineEnding,
+    pub trailing_newline: bool,
+    pub modified: bool,
+    undo: UndoTree,
+    /// Undo tree node the file on disk matches.
+    saved: usize,
+    /// Lines set aside by `:global`, sorted. Edits move them along with
+    /// their text and drop them when their line is deleted.
+    line_marks: Vec<usize>,
+    /// Marks, jump list and change list, which edits move the same way.
+    pub marks: Marks,
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Buffer::new()
+    }
+}
+
+impl Buffer {
+    pub fn new() -> Buffer {
+        Buffer {
+            text: Rope::new(),
+            line_ending: LineEnding::Lf,
+            trailing_newline: true,
+            modified: false,
+            undo: UndoTree::new(),
+            saved: 0,
+            line_marks: Vec::new(),
+            marks: Marks::new(),
+        }
+    }
+
+    /// Reads a buffer a chunk at a time, so that a large file is only held
+    /// once, in the rope. `\r\n` line endings become `\n` as they are read.
+    pub fn from_reader<R: io::Read>(mut reader: R) -> io::Result<Buffer> {
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+        let mut builder = RopeBuilder::new();
+        let mut line_ending = None;
+        let mut bytes = vec![0; READ_CHUNK];
+        // bytes at the start of `bytes` left over from the last chunk: an
+        // unfinished char, or a `\r` that may start a `\r\n`
+        let mut carried = 0;
+        loop {
+            let read = match reader.read(&mut bytes[carried..]) {
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let end = carried + read;
+            let done = read == 0;
+            let valid = match std::str::from_utf8(&bytes[..end]) {
+                Ok(text) => text.len(),
+                Err(e) if e.error_len().is_none() && !done => e.valid_up_to(),
+                Err(e) => return Err(invalid(e)),
+            };
+            let text = std::str::from_utf8(&bytes[..valid]).map_err(invalid)?;
+            let text = match text.strip_suffix('\r') {
+                Some(text) if !done => text,
+                _ => text,
+            };
+            if line_ending.is_none() {
+                if let Some(i) = text.find('\n') {
+                    line_ending = Some(if text[..i].ends_with('\r') {
+                        LineEnding::CrLf
+                    } else {
+                        LineEnding::Lf
+                    });
+                }
+            }
+            if line_ending == Some(LineEnding::CrLf) {
+                for (i, part) in text.split("\r\n").enumerate() {
+                    if i > 0 {
+                        builder.append("\n");
+                    }
+                    builder.append(part);
+                }
+            } else {
+                builder.append(text);
+            }
+            if done {
+                break;
+            }
+            let used = text.len();
+            bytes.copy_within(used..end, 0);
+            carried = end - used;
+        }
+        let mut text = builder.finish();
+        let len = text.len_chars();
+        let trailing_newline = len > 0 && text.char(len - 1) == '\n';
+        if trailing_newline {
+            text.remove(len - 1..);
+        }
+        Ok(Buffer {
+            text,
+            line_ending: line_ending.unwrap_or(LineEnding::Lf),
+            trailing_newline,
+            modified: false,
+            undo: UndoTree::new(),
+            saved: 0,
+            line_marks: Vec::new(),
+            marks: Marks::new(),
+        })
+    }
+
+    pub fn from_string(mut contents: String) -> Buffer {
+        let line_ending = match contents.find('\n') {
+            Some(i) if i > 0 && contents.as_bytes()[i - 1] == b'\r' => LineEnding::CrLf,
+            _ => LineEnding::Lf,
+        };
+        if line_ending == LineEnding::CrLf {
+            contents = contents.replace("\r\n", "\n");
+        }
+        let trailing_newline = contents.ends_with('\n');
+        if trailing_newline {
+            contents.pop();
+        }
+        Buffer {
+            text: Rope::from_str(&contents),
+            line_ending,
+            trailing_newline,
+            modified: false,
+            undo: UndoTree::new(),
+            saved: 0,
+            line_marks: Vec::new(),
+            marks: Marks::new(),
+        }
+    }
+
+    /// Number of lines. An empty buffer still has one (empty) line.
+    pub fn line_count(&self) -> usize {
+        self.text.len_lines()
+    }
+
+    /// Contents of line `idx` without its line ending.
+    pub fn line(&self, idx: usize) -> String {
+        let line = self.text.line(idx);
+        line.slice(..line_len(line)).to_string()
+    }
+
+    /// Chars from offset `idx` on. `.reversed()` turns the iterator round to
+    /// go back from `idx` instead.
+    pub fn chars_at(&self, idx: usize) -> Chars<'_> {
+        self.text.chars_at(idx)
+    }
+
+    /// Contents of line `idx` as rope chunks, without the line ending.
+    pub fn line_chunks(&self, idx: usize) -> Chunks<'_> {
+        let line = self.text.line(idx);
+        line.slice(..line_len(line)).chunks()
+    }
+
+    /// Length of line `idx` in chars, not counting its line ending.
+    pub fn line_len(&self, idx: usize) -> usize {
+        line_len(self.text.line(idx))
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.text.len_chars()
+    }
+
+    /// Size of the file on disk in bytes, including line endings.
+    pub fn len_bytes(&self) -> usize {
+        let breaks = self.line_count() - 1 + self.trailing_newline as usize;
+        self.text.len_bytes()
+            + breaks * (self.line_ending.as_str().len() - 1)
+            + self.trailing_newline as usize
+    }
+
+    pub fn line_to_char(&self, line: usize) -> usize {
+        self.text.line_to_char(line)
+    }
+
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        self.text.char_to_line(char_idx)
+    }
+
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        self.text.char_to_byte(char_idx)
+    }
+
+    pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+        self.text.byte_to_char(byte_idx)
+    }
+
+    /// Char at offset `idx`, `None` past the end.
+    pub fn char(&self, idx: usize) -> Option<char> {
+        self.text.get_char(idx)
+    }
+
+    /// Text between two char offsets.
+    pub fn slice(&self, range: Range<usize>) -> String {
+        self.text.slice(range).to_string()
+    }
+
+    /// Line and column of char offset `char_idx`.
+    pub fn char_to_pos(&self, char_idx: usize) -> Pos {
+        let line = self.char_to_line(char_idx);
+        (line, char_idx - self.line_to_char(line))
+    }
+
+    /// Char offset of column `col` on line `line`.
+    pub fn pos_to_char(&self, line: usize, col: usize) -> usize {
+        self.line_to_char(line) + col
+    }
+
+    pub fn insert_char(&mut self, char_idx: usize, c: char) {
+        self.insert(char_idx, c.encode_utf8(&mut [0; 4]));
+    }
+
+    pub fn insert(&mut self, char_idx: usize, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+        let edit = Edit::Insert {
+            at: char_idx,
+            text: s.to_string(),
+        };
+        let shift = self.line_shift(&edit);
+        self.text.insert(char_idx, s);
+        self.shift_marks(&shift);
+        self.note_change(char_idx, char_idx + s.chars().count() - 1);
+        self.undo.record(edit);
+        self.modified = true;
+    }
+
+    pub fn remove(&mut self, range: Range<usize>) {
+        if range.start >= range.end {
+            return;
+        }
+        let edit = Edit::Remove {
+            at: range.start,
+            text: self.text.slice(range.clone()).to_string(),
+        };
+        let shift = self.line_shift(&edit);
+        self.text.remove(range.clone());
+        self.shift_marks(&shift);
+        self.note_change(range.start, range.start);
+        self.undo.record(edit);
+        self.modified = true;
+    }
+
+    /// Removes whole lines, including the line ending that joins them to the
+    /// rest of the buffer.
+    pub fn remove_lines(&mut self, lines: Range<usize>) {
+        let count = self.line_count();
+        let mut start = self.line_to_char(lines.start);
+        let end = if lines.end < count {
+            self.line_to_char(lines.end)
+        } else {
+            if lines.start > 0 {
+                start -= 1;
+            }
+            self.len_chars()
+        };
+        self.remove(start..end);
+    }
+
+    pub fn insert_byte(&mut self, byte_idx: usize, s: &str) {
+        let char_idx = self.byte_to_char(byte_idx);
+        self.insert(char_idx, s);
+    }
+
+    pub fn remove_bytes(&mut self, range: Range<usize>) {
+        let start = self.byte_to_char(range.start);
+        let end = self.byte_to_char(range.end);
+        self.remove(start..end);
+    }
+}
+
+impl Buffer {
+    /// Remembers that the file on disk now matches the buffer.
+    pub fn mark_saved(&mut self) {
+        self.saved = self.undo.current();
+        self.modified = false;
+    }
+
+    pub fn begin_undo_group(&mut self) {
+        self.undo.begin_group();
+    }
+
+    pub fn end_undo_group(&mut self) {
+        self.undo.end_group();
+        self.update_modified();
+    }
+
+    /// Reverts the most recent change. Returns the char offset the change
+    /// started at, or `None` when there is nothing to undo.
+    pub fn undo(&mut self) -> Option<usize> {
+        let step = self.undo.undo()?;
+        Some(self.apply_steps(vec![step]))
+    }
+
+    /// Reapplies the most recently undone change, see [`Buffer::undo`].
+    pub fn redo(&mut self) -> Option<usize> {
+        let step = self.undo.redo()?;
+        Some(self.apply_steps(vec![step]))
+    }
+
+    /// Moves through changes in the order they were made (`g-` and `g+`),
+    /// crossing undo branches when needed.
+    pub fn undo_travel(&mut self, forward: bool) -> Option<usize> {
+        let current = self.undo.current();
+        let target = if forward {
+            current + 1
+        } else {
+            current.checked_sub(1)?
+        };
+        if target >= self.undo.node_count() {
+            return None;
+        }
+        let steps = self.undo.travel(target);
+        Some(self.apply_steps(steps))
+    }
+
+    /// Number of the change the buffer is at.
+    pub fn undo_seq(&self) -> usize {
+        self.undo.current()
+    }
+
+    pub fn undo_leaves(&self) -> Vec<(usize, usize)> {
+        self.undo.leaves()
+    }
+
+    fn apply_steps(&mut self, steps: Vec<UndoStep>) -> usize {
+        let mut pos = 0;
+        for step in steps {
+            match step {
+                UndoStep::Undo(edits) => {
+                    for edit in edits.iter().rev() {
+                        self.apply(&edit.inverse());
+                    }
+                    pos = edits.iter().map(Edit::at).min().unwrap_or(0);
+                }
+                UndoStep::Redo(edits) => {
+                    for edit in &edits {
+                        self.apply(edit);
+                    }
+                    pos = edits.iter().map(Edit::at).min().unwrap_or(0);
+                }
+            }
+        }
+        self.update_modified();
+        pos
+    }
+
+    fn apply(&mut self, edit: &Edit) {
+        let shift = self.line_shift(edit);
+        match edit {
+            Edit::Insert { at, text } => self.text.insert(*at, text),
+            Edit::Remove { at, text } => self.text.remove(*at..*at + text.chars().count()),
+        }
+        self.shift_marks(&shift);
+    }
+
+    /// Marks `lines` for [`Buffer::next_line_mark`], replacing any others.
+    pub fn set_line_marks(&mut self, mut lines: Vec<usize>) {
+        lines.sort_unstable();
+        lines.dedup();
+        self.line_marks = lines;
+    }
+
+    /// Unmarks and returns the first marked line left.
+    pub fn next_line_mark(&mut self) -> Option<usize> {
+        if self.line_marks.is_empty() {
+            return None;
+        }
+        Some(self.line_marks.remove(0))
+    }
+
+    /// How `edit`, which is about to be applied, moves lines. Text
+    /// inserted at the start of a line and ending in a line break pushes
+    /// the line down, and removing text from the start of a line to the
+    /// start of another, or from the end of one line to the end of another,
+    /// deletes lines. Other removals join lines.
+    fn line_shift(&self, edit: &Edit) -> LineShift {
+        let (at, text) = match edit {
+            Edit::Insert { at, text } | Edit::Remove { at, text } => (*at, text),
+        };
+        let breaks = text.matches('\n').count();
+        if breaks == 0 {
+            return LineShift::None;
+        }
+        let line = self.char_to_line(at);
+        let col = at - self.line_to_char(line);
+        let whole = col == 0 && text.ends_with('\n');
+        if let Edit::Insert { .. } = edit {
+            let before = if whole { line } else { line + 1 };
+            return LineShift::Insert {
+                before,
+                count: breaks,
+            };
+        }
+        let joined = line + breaks;
+        let skip = at + text.chars().count() - self.line_to_char(joined);
+        if whole {
+            LineShift::Delete {
+                lines: line..joined,
+            }
+        } else if col == self.line_len(line) && skip == self.line_len(joined) {
+            LineShift::Delete {
+                lines: line + 1..joined + 1,
+            }
+        } else {
+            LineShift::Join {
+                line,
+                col,
+                joined,
+                skip,
+            }
+        }
+    }
+
+    fn shift_marks(&mut self, shift: &LineShift) {
+        if *shift == LineShift::None {
+            return;
+        }
+        self.marks.shift(shift);
+        let lines = std::mem::take(&mut self.line_marks);
+        self.line_marks = lines.into_iter().filter_map(|l| shift.line(l)).collect();
+    }
+
+    /// Sets the `[` and `]` marks around the chars from `start` to `end`,
+    /// just changed, and notes the change for `.` and the change list.
+    /// Later edits of the same undo step widen the marks.
+    fn note_change(&mut self, start: usize, end: usize) {
+        let (mut first, mut last) = (self.char_to_pos(start), self.char_to_pos(end));
+        let last_pos = last;
+        if self.undo.has_pending() {
+            if let (Some(a), Some(b)) = (self.marks.get('['), self.marks.get(']')) {
+                first = first.min(a);
+                last = last.max(b);
+            }
+        }
+        self.marks.set('[', first);
+        self.marks.set(']', last);
+        self.marks.push_change(last_pos);
+    }
+
+    fn update_modified(&mut self) {
+        self.modified = self.undo.current() != self.saved || self.undo.has_pending();
+    }
+}
+
+fn line_len(line: ropey::RopeSlice) -> usize {
+    let len = line.len_chars();
+    if len > 0 && line.char(len - 1) == '\n' {
+        len - 1
+    } else {
+        len
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::register::{Register, RegisterKind};
+
+/// Runs `command` through the shell with `text` on its stdin, the way
+/// helpers like `xclip -i`, `wl-copy` or `pbcopy` take what to copy.
+pub fn copy(command: &str, text: &str) -> Result<(), String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("{}: {}", command, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|e| format!("{}: {}", command, e))?;
+    }
+    let status = child.wait().map_err(|e| format!("{}: {}", command, e))?;
+    if !status.success() {
+        return Err(format!("{}: {}", command, status));
+    }
+    Ok(())
+}
+
+/// Runs `command` through the shell and returns what it prints, the way
+/// helpers like `xclip -o`, `wl-paste -n` or `pbpaste` give the clipboard.
+pub fn paste(command: &str) -> Result<String, String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| format!("{}: {}", command, e))?;
+    if !output.status.success() {
+        return Err(format!("{}: {}", command, output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).replace("\r\n", "\n"))
+}
+
+/// Register holding `text` read back from the clipboard. Text fred put
+/// there itself keeps the type it had in `last`, anything else is linewise
+/// when it ends in a line break.
+pub fn register_for(text: String, last: Option<&Register>) -> Register {
+    match last {
+        Some(last) if last.text == text => last.clone(),
+        _ if text.ends_with('\n') => Register::new(text, RegisterKind::Line),
+        _ => Register::new(text, RegisterKind::Char),
+    }
+}
@@ -0,0 +1,97 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::display;
+
+/// What a key press did to the command line.
+pub enum CommandLineEvent {
+    Edited,
+    Cancelled,
+    Submitted(String),
+}
+
+/// The editable line drawn on the bottom row while typing a `:` command.
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    pub prompt: char,
+    pub text: Vec<char>,
+    pub cursor: usize,
+    history: Vec<String>,
+    history_pos: usize,
+}
+
+impl CommandLine {
+    pub fn start(&mut self, prompt: char) {
+        self.prompt = prompt;
+        self.text.clear();
+        self.cursor = 0;
+        self.history_pos = self.history.len();
+    }
+
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    /// Column of the terminal cursor, counting the prompt.
+    pub fn cursor_col(&self) -> usize {
+        let before: String = self.text[..self.cursor].iter().collect();
+        1 + display::str_width(&before)
+    }
+
+    /// Edits the line for `key`. Keys held with Ctrl or Alt do nothing.
+    /// Moves and deletes go a grapheme at a time, as in the buffer.
+    pub fn handle_key(&mut self, key: KeyEvent) -> CommandLineEvent {
+        let modified = key
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Char(_) if modified => {}
+            KeyCode::Esc => return CommandLineEvent::Cancelled,
+            KeyCode::Enter => {
+                let text = self.text();
+                if !text.is_empty() && self.history.last() != Some(&text) {
+                    self.history.push(text.clone());
+                }
+                return CommandLineEvent::Submitted(text);
+            }
+            KeyCode::Backspace => {
+                if self.text.is_empty() {
+                    return CommandLineEvent::Cancelled;
+                }
+                let start = display::prev_grapheme(&self.text(), self.cursor);
+                self.text.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            KeyCode::Delete => {
+                let end = display::next_grapheme(&self.text(), self.cursor);
+                self.text.drain(self.cursor..end.min(self.text.len()));
+            }
+            KeyCode::Left => self.cursor = display::prev_grapheme(&self.text(), self.cursor),
+            KeyCode::Right => {
+                let next = display::next_grapheme(&self.text(), self.cursor);
+                self.cursor = next.min(self.text.len());
+            }
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.len(),
+            KeyCode::Up if self.history_pos > 0 => {
+                self.history_pos -= 1;
+                self.set_text(&self.history[self.history_pos].clone());
+            }
+            KeyCode::Down if self.history_pos < self.history.len() => {
+                self.history_pos += 1;
+                let text = self.history.get(self.history_pos).cloned();
+                self.set_text(&text.unwrap_or_default());
+            }
+            KeyCode::Char(c) => {
+                self.text.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            _ => {}
+        }
+        CommandLineEvent::Edited
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.text = text.chars().collect();
+        self.cursor = self.text.len();
+    }
+}
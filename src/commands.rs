@@ -0,0 +1,368 @@
+use crate::editor::Editor;
+use crate::ex::{self, CommandRegistry, ExCommand, ExResult};
+use crate::keys;
+use crate::register::{Register, RegisterKind};
+use crate::search;
+use crate::substitute;
+
+/// Registry holding every built-in `:` command.
+pub fn builtins() -> CommandRegistry {
+    let mut registry = CommandRegistry::new();
+    registry.register("w[rite]", write);
+    registry.register("wq", write_quit);
+    registry.register("x[it]", exit);
+    registry.register("exi[t]", exit);
+    registry.register("sav[eas]", save_as);
+    registry.register("q[uit]", quit);
+    registry.register("e[dit]", edit);
+    registry.register("d[elete]", delete);
+    registry.register("m[ove]", move_lines);
+    registry.register("u[ndo]", undo);
+    registry.register("red[o]", redo);
+    registry.register("undol[ist]", undo_list);
+    registry.register("se[t]", set);
+    registry.register("y[ank]", yank);
+    registry.register("pu[t]", put);
+    registry.register("reg[isters]", registers);
+    registry.register("di[splay]", registers);
+    registry.register("noh[lsearch]", nohlsearch);
+    registry.register("marks", marks);
+    registry.register("delm[arks]", delete_marks);
+    registry.register("ma[rk]", mark);
+    registry.register("norm[al]", normal);
+    registry.register("s[ubstitute]", substitute);
+    registry.register("&", substitute);
+    registry.register("&&", substitute);
+    registry.register("g[lobal]", global);
+    registry.register("v[global]", global);
+    registry
+}
+
+fn path_arg(cmd: &ExCommand) -> Option<String> {
+    if cmd.args.is_empty() {
+        None
+    } else {
+        Some(cmd.args.clone())
+    }
+}
+
+fn write(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    ed.write_file(path_arg(cmd), cmd.bang)
+}
+
+fn write_quit(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    ed.write_file(path_arg(cmd), cmd.bang)?;
+    ed.quit = true;
+    Ok(())
+}
+
+fn exit(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    if ed.buffer.modified {
+        ed.write_file(path_arg(cmd), cmd.bang)?;
+    }
+    ed.quit = true;
+    Ok(())
+}
+
+fn save_as(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    let path = path_arg(cmd).ok_or("E471: Argument required")?;
+    ed.write_file(Some(path.clone()), cmd.bang)?;
+    ed.file_name = Some(path);
+    ed.buffer.mark_saved();
+    Ok(())
+}
+
+fn quit(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    if ed.buffer.modified && !cmd.bang {
+        return Err("E37: No write since last change (add ! to override)".into());
+    }
+    ed.quit = true;
+    Ok(())
+}
+
+fn edit(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    if ed.buffer.modified && !cmd.bang {
+        return Err("E37: No write since last change (add ! to override)".into());
+    }
+    let path = path_arg(cmd)
+        .or_else(|| ed.file_name.clone())
+        .ok_or("E32: No file name")?;
+    ed.read_from_file(path)
+}
+
+/// Register named by the argument of `:d`, `:y` and `:pu`, `"` when there
+/// is none.
+fn register_arg(cmd: &ExCommand) -> ExResult<char> {
+    let mut chars = cmd.args.trim().chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Ok('"'),
+        (Some(c), None) if crate::normal::is_register_name(c) => Ok(c),
+        _ => Err(format!("E488: Trailing characters: {}", cmd.args.trim())),
+    }
+}
+
+/// Lines `start..=end` as a linewise register.
+fn lines_register(ed: &Editor, start: usize, end: usize) -> Register {
+    let text: String = (start..=end)
+        .map(|idx| format!("{}\n", ed.buffer.line(idx)))
+        .collect();
+    Register::new(text, RegisterKind::Line)
+}
+
+fn delete(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    let (start, end) = ed.line_range(cmd)?;
+    let name = register_arg(cmd)?;
+    let lines = lines_register(ed, start, end);
+    ed.delete_into(name, lines);
+    ed.delete_lines(start, end);
+    Ok(())
+}
+
+/// `:m {address}`, the range's lines below line `address`.
+fn move_lines(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    let range = ed.line_range(cmd)?;
+    let to = ed.resolve_address(&ex::parse_address(&cmd.args)?)?;
+    ed.move_lines(range, to)
+}
+
+fn yank(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    let (start, end) = ed.line_range(cmd)?;
+    let name = register_arg(cmd)?;
+    let lines = lines_register(ed, start, end);
+    ed.yank_into(name, lines);
+    Ok(())
+}
+
+/// `:put`, the register's text as whole lines below the range's last line,
+/// or above it with `!`.
+fn put(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    let (_, end) = ed.line_range(cmd)?;
+    let name = register_arg(cmd)?;
+    // `:0put` puts above the first line
+    let line0 = matches!(&cmd.range, Some(r) if ed.resolve_address(&r.end) == Ok(0));
+    let at = if cmd.bang || line0 { end } else { end + 1 };
+    ed.put_as_lines(name, at)
+}
+
+/// `:s/pattern/replacement/flags count`. `:s` and `:&` without a pattern
+/// repeat the last one, `:&&` with its flags too.
+fn substitute(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    let args = match cmd.name.as_str() {
+        "&&" => format!("&{}", cmd.args),
+        _ => cmd.args.clone(),
+    };
+    let args = substitute::parse(&args)?;
+    let last_search = ed.last_search.as_ref().map(|s| s.pattern.as_str());
+    let sub = substitute::resolve(&args, ed.last_substitute.as_ref(), last_search)?;
+    let (mut start, mut end) = ed.line_range(cmd)?;
+    if let Some(count) = args.count {
+        start = end;
+        end = (start + count - 1).min(ed.buffer.line_count() - 1);
+    }
+    ed.substitute((start, end), sub)
+}
+
+/// `:g/pattern/command`, the command on every line of the range matching
+/// the pattern, by default the whole file. `:g!` and `:v` take the lines not
+/// matching.
+fn global(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    let invert = cmd.bang || cmd.name.starts_with('v');
+    let delim = match cmd.args.chars().next() {
+        Some(c) if search::is_delimiter(c) => c,
+        _ => return Err("E146: Regular expressions can't be delimited by letters".into()),
+    };
+    let (pattern, command) = search::split_delimited(&cmd.args[delim.len_utf8()..], delim);
+    let range = match cmd.range {
+        Some(_) => ed.line_range(cmd)?,
+        None => (0, ed.buffer.line_count() - 1),
+    };
+    ed.global(range, &pattern, invert, command)
+}
+
+/// `:normal {keys}`, the keys typed in Normal mode on each line of the
+/// range, or at the cursor.
+fn normal(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    if cmd.args.is_empty() {
+        return Err("E471: Argument required".into());
+    }
+    let lines = match cmd.range {
+        Some(_) => Some(ed.line_range(cmd)?),
+        None => None,
+    };
+    ed.normal_keys(lines, &keys::literal_keys(&cmd.args));
+    Ok(())
+}
+
+fn nohlsearch(ed: &mut Editor, _cmd: &ExCommand) -> ExResult<()> {
+    ed.search_highlight = false;
+    Ok(())
+}
+
+/// `:registers`, the type, name and text of every register holding some,
+/// or of the ones named in the argument.
+fn registers(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    let wanted: Vec<char> = cmd.args.chars().filter(|c| !c.is_whitespace()).collect();
+    ed.read_clipboard('+');
+    ed.read_clipboard('*');
+    let mut listed: Vec<(char, Register)> = ed
+        .registers
+        .list()
+        .into_iter()
+        .map(|(name, register)| (name, register.clone()))
+        .collect();
+    if let Some(file) = ed.read_register('%') {
+        listed.push(('%', file));
+    }
+    let mut lines = vec!["Type Name Content".to_string()];
+    for (name, register) in listed {
+        if !wanted.is_empty() && !wanted.contains(&name) {
+            continue;
+        }
+        let text: String = register.text.chars().map(printable).collect();
+        lines.push(format!(
+            "  {}  \"{}   {}",
+            register.kind.letter(),
+            name,
+            text
+        ));
+    }
+    ed.show_output(lines);
+    Ok(())
+}
+
+/// `c` as `:registers` shows it, control chars like the line break as `^J`.
+fn printable(c: char) -> String {
+    match c {
+        '\u{7f}' => "^?".to_string(),
+        c if (c as u32) < 0x20 => format!("^{}", (c as u8 + b'@') as char),
+        c => c.to_string(),
+    }
+}
+
+fn marks(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    let wanted: Vec<char> = cmd.args.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut listed: Vec<(char, (usize, usize), String)> = ed
+        .buffer
+        .marks
+        .list()
+        .into_iter()
+        .filter(|&(_, (line, _))| line < ed.buffer.line_count())
+        .map(|(name, pos)| (name, pos, ed.buffer.line(pos.0)))
+        .collect();
+    for (&name, (file, pos)) in &ed.file_marks {
+        listed.push((name, *pos, file.clone()));
+    }
+    listed.sort_unstable_by_key(|&(name, _, _)| name);
+    listed.retain(|(name, _, _)| wanted.is_empty() || wanted.contains(name));
+    if listed.is_empty() {
+        return Err("E283: No marks matching".into());
+    }
+    let mut lines = vec!["mark line  col file/text".to_string()];
+    for (name, (line, col), text) in listed {
+        let text: String = text.chars().map(printable).collect();
+        lines.push(format!(" {} {:>6} {:>4} {}", name, line + 1, col, text));
+    }
+    ed.show_output(lines);
+    Ok(())
+}
+
+/// `:delm[arks] {marks}` deletes the marks named, where `a-d` names a run of
+/// them. `:delm!` deletes every lowercase mark.
+fn delete_marks(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    let mut names = Vec::new();
+    if cmd.bang {
+        if !cmd.args.trim().is_empty() {
+            return Err("E474: Invalid argument".into());
+        }
+        names.extend('a'..='z');
+    }
+    let chars: Vec<char> = cmd.args.chars().filter(|c| !c.is_whitespace()).collect();
+    if chars.is_empty() && !cmd.bang {
+        return Err("E471: Argument required".into());
+    }
+    let mut i = 0;
+    while i < chars.len() {
+        let from = chars[i];
+        if !from.is_ascii_alphanumeric() && !"\"^.[]<>".contains(from) {
+            return Err(format!("E475: Invalid argument: {}", cmd.args.trim()));
+        }
+        match chars.get(i + 1..i + 3) {
+            Some(&['-', to]) => {
+                let same_kind = (from.is_ascii_lowercase() && to.is_ascii_lowercase())
+                    || (from.is_ascii_uppercase() && to.is_ascii_uppercase())
+                    || (from.is_ascii_digit() && to.is_ascii_digit());
+                if !same_kind || to < from {
+                    return Err(format!("E475: Invalid argument: {}", cmd.args.trim()));
+                }
+                names.extend(from..=to);
+                i += 3;
+            }
+            _ => {
+                names.push(from);
+                i += 1;
+            }
+        }
+    }
+    for name in names {
+        ed.buffer.marks.remove(name);
+        ed.file_marks.remove(&name);
+    }
+    Ok(())
+}
+
+/// `:[range]ma[rk] {mark}` sets a mark at the start of the last line of the
+/// range.
+fn mark(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    let (_, end) = ed.line_range(cmd)?;
+    let mut chars = cmd.args.trim().chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Err("E471: Argument required".into()),
+        (Some(name), None) if name.is_ascii_alphabetic() || "'`".contains(name) => {
+            ed.set_mark(name, (end, 0));
+            Ok(())
+        }
+        (Some(_), None) => Err("E191: Argument must be a letter or forward/backward quote".into()),
+        _ => Err(format!("E488: Trailing characters: {}", cmd.args.trim())),
+    }
+}
+
+fn undo(ed: &mut Editor, _cmd: &ExCommand) -> ExResult<()> {
+    ed.undo();
+    Ok(())
+}
+
+fn redo(ed: &mut Editor, _cmd: &ExCommand) -> ExResult<()> {
+    ed.redo();
+    Ok(())
+}
+
+fn undo_list(ed: &mut Editor, _cmd: &ExCommand) -> ExResult<()> {
+    let leaves = ed.buffer.undo_leaves();
+    if leaves.is_empty() {
+        return Err("Nothing to undo".into());
+    }
+    let mut lines = vec!["number changes".to_string()];
+    for (number, changes) in leaves {
+        lines.push(format!("{:>6} {:>7}", number, changes));
+    }
+    ed.show_output(lines);
+    Ok(())
+}
+
+fn set(ed: &mut Editor, cmd: &ExCommand) -> ExResult<()> {
+    let mut shown = Vec::new();
+    if cmd.args.trim().is_empty() {
+        shown = ed.settings.changed();
+    }
+    for arg in cmd.args.split_whitespace() {
+        if let Some(text) = ed.settings.apply(arg)? {
+            shown.push(text);
+        }
+    }
+    match shown.len() {
+        0 => {}
+        1 => ed.set_message(shown.remove(0)),
+        _ => ed.show_output(shown),
+    }
+    Ok(())
+}
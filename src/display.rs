@@ -0,0 +1,153 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Screen columns grapheme `g` takes up when it starts at display column
+/// `vcol`. Wide east asian chars take two, tabs reach the next tabstop,
+/// other control chars take two for their caret notation and anything zero
+/// width, like a stray combining mark, still gets a column so the cursor
+/// can sit on it.
+pub fn grapheme_width(g: &str, vcol: usize, tabstop: usize) -> usize {
+    if g == "\t" {
+        tabstop - vcol % tabstop
+    } else if caret(g).is_some() {
+        2
+    } else {
+        g.width().max(1)
+    }
+}
+
+/// The char shown after `^` for a control char other than tab, like `[`
+/// for ESC or `?` for DEL, so it never reaches the terminal as is.
+pub fn caret(g: &str) -> Option<char> {
+    let mut chars = g.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c != '\t' && (c < ' ' || c == '\x7f') => Some((c as u8 ^ 0x40) as char),
+        _ => None,
+    }
+}
+
+/// Columns `s` takes up when drawn from the left edge, for text without tabs
+/// like messages and the command line.
+pub fn str_width(s: &str) -> usize {
+    s.graphemes(true).map(|g| grapheme_width(g, 0, 1)).sum()
+}
+
+/// Graphemes of `line` with the char index each one starts at.
+fn graphemes(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    line.graphemes(true).scan(0, |idx, g| {
+        let start = *idx;
+        *idx += g.chars().count();
+        Some((start, g))
+    })
+}
+
+/// Display column where char `idx` of `line` starts. Indices past the end
+/// count as one column each, like the cursor after the last char in insert
+/// mode.
+pub fn display_col(line: &str, idx: usize, tabstop: usize) -> usize {
+    let mut vcol = 0;
+    let mut end = 0;
+    for (start, g) in graphemes(line) {
+        if start >= idx {
+            return vcol;
+        }
+        vcol += grapheme_width(g, vcol, tabstop);
+        end = start + g.chars().count();
+    }
+    vcol + idx.saturating_sub(end)
+}
+
+/// Number of screen columns `line` takes up.
+pub fn display_width(line: &str, tabstop: usize) -> usize {
+    display_col(line, line.chars().count(), tabstop)
+}
+
+/// Char index of the grapheme shown at display column `vcol`, or the line
+/// length when `vcol` is past the end.
+pub fn char_at_display(line: &str, vcol: usize, tabstop: usize) -> usize {
+    let mut col = 0;
+    for (start, g) in graphemes(line) {
+        col += grapheme_width(g, col, tabstop);
+        if vcol < col {
+            return start;
+        }
+    }
+    line.chars().count()
+}
+
+/// Grapheme starting at char `idx` of `line`.
+pub fn grapheme_at(line: &str, idx: usize) -> Option<&str> {
+    graphemes(line)
+        .find(|(start, _)| *start == idx)
+        .map(|(_, g)| g)
+}
+
+/// Char index of the grapheme after the one at `idx`.
+pub fn next_grapheme(line: &str, idx: usize) -> usize {
+    graphemes(line)
+        .map(|(start, g)| start + g.chars().count())
+        .find(|end| *end > idx)
+        .unwrap_or(idx + 1)
+}
+
+/// Char index of the grapheme before the one at `idx`.
+pub fn prev_grapheme(line: &str, idx: usize) -> usize {
+    graphemes(line)
+        .map(|(start, _)| start)
+        .take_while(|start| *start < idx)
+        .last()
+        .unwrap_or(0)
+}
+
+/// Start of the grapheme containing char `idx`, so the cursor never lands
+/// inside one. Indices past the last grapheme are left alone.
+pub fn grapheme_start(line: &str, idx: usize) -> usize {
+    if idx >= line.chars().count() {
+        return idx;
+    }
+    graphemes(line)
+        .map(|(start, _)| start)
+        .take_while(|start| *start <= idx)
+        .last()
+        .unwrap_or(0)
+}
+
+/// `line` as it appears on screen, one entry per column. Tabs become spaces,
+/// other control chars caret notation like `^[`, and a wide grapheme is
+/// followed by an empty entry for its second column.
+pub fn expand(line: &str, tabstop: usize) -> Vec<String> {
+    let mut cells = Vec::new();
+    for g in line.graphemes(true) {
+        let width = grapheme_width(g, cells.len(), tabstop);
+        if g == "\t" {
+            cells.extend(std::iter::repeat_n(" ".to_string(), width));
+        } else if let Some(c) = caret(g) {
+            cells.push("^".to_string());
+            cells.push(c.to_string());
+        } else {
+            cells.push(g.to_string());
+            cells.extend(std::iter::repeat_n(String::new(), width - 1));
+        }
+    }
+    cells
+}
+
+/// Text for columns `from..to` of expanded `cells`. A wide grapheme cut in
+/// half at either edge shows as a space.
+pub fn visible(cells: &[String], from: usize, to: usize) -> String {
+    let to = to.min(cells.len());
+    let mut text = String::new();
+    let mut col = from;
+    while col < to {
+        let cell = &cells[col];
+        let width = 1 + cells[col + 1..].iter().take_while(|c| c.is_empty()).count();
+        if cell.is_empty() || col + width > to {
+            text.push(' ');
+            col += 1;
+        } else {
+            text.push_str(cell);
+            col += width;
+        }
+    }
+    text
+}
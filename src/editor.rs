@@ -1,204 +1,732 @@
 use crossterm::{
-    cursor,
-    event::{read, Event, KeyCode, KeyEvent, KeyModifiers},
-    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
-    terminal::{self, ClearType},
-    QueueableCommand, Result,
+    event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    style::Color,
+    Result,
 };
-use serde::{Deserialize, Serialize};
-use std::io::{stdout, Write};
+use regex::Regex;
+use std::collections::HashMap;
+use std::convert::TryFrom;
 
-use crate::{fred_file, term};
+use crate::backend::{self, Backend};
+use crate::command_line::{CommandLine, CommandLineEvent};
+use crate::display;
+use crate::ex::{self, AddressBase, CommandRegistry, ExCommand, ExResult};
+use crate::keys;
+use crate::motion;
+use crate::normal::{
+    self, Action, CommandKind, Motion, MotionKind, NormalCommand, Operator, Target, VisualKind,
+};
+use crate::register::{Register, RegisterKind, Registers};
+use crate::screen::{Screen, Style};
+use crate::search::{self, Search};
+use crate::settings::Settings;
+use crate::substitute::{self, Substitute};
+use crate::text_object::{self, Selection, TextObject};
+use crate::{buffer::Buffer, clipboard, commands, fred_file};
 
-const TABASSPACES: u16 = 4;
+/// How deep `@` can run registers from inside registers.
+const MAX_PLAY_DEPTH: usize = 100;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct MyConfig {
-    tab_spaces: u16,
-}
+/// Drawn in the line number gutter on rows that continue a wrapped line.
+const WRAP_MARKER: char = '↪';
 
-impl ::std::default::Default for MyConfig {
-    fn default() -> Self {
-        Self { tab_spaces: 4 }
-    }
-}
+/// Highlight of search matches.
+const SEARCH_STYLE: Style = Style {
+    fg: Some(Color::Black),
+    bg: Some(Color::Yellow),
+};
+
+/// Highlight of the match `:s` with the `c` flag asks about.
+const CONFIRM_STYLE: Style = Style {
+    fg: Some(Color::Black),
+    bg: Some(Color::Cyan),
+};
+
+/// Highlight of the Visual mode selection.
+const VISUAL_STYLE: Style = Style {
+    fg: None,
+    bg: Some(Color::DarkGrey),
+};
 
 #[derive(Debug)]
 pub enum EditorMode {
     Normal,
     Insert,
     Visual,
+    Command,
+}
+
+/// Position of the cursor in the buffer. `col` is a char index into the
+/// line, `want_col` is the display column vertical moves try to return to
+/// after passing through shorter lines.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Cursor {
+    pub line: usize,
+    pub col: usize,
+    pub want_col: usize,
+}
+
+/// Text typed by `I` or `A` on a Visual block, which goes into every line
+/// of the block when Insert mode ends.
+#[derive(Debug, Clone, Copy)]
+struct BlockInsert {
+    first: usize,
+    last: usize,
+    /// Display column the text goes in at, `usize::MAX` for line ends.
+    vcol: usize,
+    /// Where the typing started on the first line, and that line's length.
+    col: usize,
+    len: usize,
+    /// Lines too short to reach `vcol` get padded for `A` and skipped for `I`.
+    append: bool,
+}
+
+/// Size of a Visual selection, which `.` selects again from the cursor to
+/// repeat a change made on it.
+#[derive(Debug, Clone, Copy)]
+struct VisualSize {
+    kind: VisualKind,
+    /// Lines below the first one.
+    lines: usize,
+    /// Chars on a single line, or the end column when the selection spans
+    /// lines. Display columns for a block, `usize::MAX` after `$`.
+    cols: usize,
+}
+
+/// Cursor and view when the search prompt opened, which incremental search
+/// starts from and cancelling goes back to.
+#[derive(Debug, Clone, Copy)]
+struct SearchOrigin {
+    cursor: Cursor,
+    v_draw_region: (usize, usize),
+    h_draw_region: (usize, usize),
 }
 
+/// A `:s` working through its range, stopped at `current` while the `c`
+/// flag waits for an answer.
 #[derive(Debug)]
-pub enum KeyState {
-    Waiting(char),
-    _WaitingForCommand(String),
-    Inactive,
+struct Substitution {
+    run: substitute::Run,
+    pattern: String,
+    confirm: bool,
+    count_only: bool,
+    current: Option<substitute::Match>,
+    /// Whether the prompt came up, so finding nothing isn't an error.
+    asked: bool,
+    /// Where the cursor goes back to when nothing was substituted.
+    cursor: Cursor,
+}
+
+/// The last change, as `.` replays it.
+#[derive(Debug, Clone)]
+struct Change {
+    cmd: NormalCommand,
+    visual: Option<VisualSize>,
+    /// Text typed in the Insert mode the command started.
+    inserted: String,
 }
 
 #[derive(Debug)]
 pub struct Editor {
-    pub lines: Vec<Line>,
+    pub backend: Box<dyn Backend>,
+    pub buffer: Buffer,
     pub status: String,
     pub mode: EditorMode,
     pub v_draw_region: (usize, usize),
     pub h_draw_region: (usize, usize),
-    pub draw_line: usize,
-    pub key_state: KeyState,
+    pub cursor: Cursor,
+    /// Keys of a Normal mode command typed so far.
+    pub pending_keys: Vec<KeyEvent>,
     pub line_num_buf: Vec<char>,
+    pub file_name: Option<String>,
+    pub message: String,
+    pub quit: bool,
+    pub command_line: CommandLine,
+    pub commands: CommandRegistry,
+    pub output: Vec<String>,
+    /// The frame currently shown on the terminal.
+    pub screen: Screen,
+    pub settings: Settings,
+    pub registers: Registers,
+    /// Text typed since Insert mode started, for the `.` register.
+    inserted: String,
+    /// The last `f`, `F`, `t` or `T`, for `;` and `,`.
+    pub last_find: Option<motion::Find>,
+    /// Where the Visual mode selection started, the cursor is its other end.
+    pub visual_start: motion::Pos,
+    pub visual_kind: VisualKind,
+    /// Both ends and kind of the last Visual selection, for `gv`.
+    pub last_visual: Option<(motion::Pos, motion::Pos, VisualKind)>,
+    block_insert: Option<BlockInsert>,
+    last_change: Option<Change>,
+    /// The last `/`, `?`, `*` or `#` search, for `n` and `N`.
+    pub last_search: Option<Search>,
+    /// Whether its matches are highlighted, until `:noh`.
+    pub search_highlight: bool,
+    search_origin: Option<SearchOrigin>,
+    /// The last `:s`, for `:&`, `:&&` and `g&`.
+    pub last_substitute: Option<Substitute>,
+    substitution: Option<Substitution>,
+    /// Whether a `:global` is running its command.
+    in_global: bool,
+    /// Register `q` is recording into and the keys typed since it started.
+    recording: Option<(char, Vec<KeyEvent>)>,
+    /// The register `@@` runs again.
+    last_played: Option<char>,
+    /// Set when a command fails, which stops keys run by `@` and `:normal`.
+    failed: bool,
+    /// How many `@` and `:normal` runs are inside each other.
+    play_depth: usize,
+    /// Uppercase marks in files other than the current one, with the file.
+    /// Those in the current file are kept with its buffer's marks.
+    pub file_marks: HashMap<char, (String, motion::Pos)>,
 }
 
-#[derive(Debug)]
-pub struct Line {
-    pub line_chars: Vec<char>,
-}
+impl Editor {
+    pub fn new(backend: Box<dyn Backend>, settings: Settings) -> Editor {
+        let (width, height) = backend.size();
+        Editor {
+            backend,
+            buffer: Buffer::new(),
+            status: "Normal".to_string(),
+            mode: EditorMode::Normal,
+            v_draw_region: (0, height.saturating_sub(1).max(1)),
+            h_draw_region: (0, width.saturating_sub(3).max(1)),
+            cursor: Cursor::default(),
+            pending_keys: Vec::new(),
+            line_num_buf: Vec::new(),
+            file_name: None,
+            message: String::new(),
+            quit: false,
+            command_line: CommandLine::default(),
+            commands: commands::builtins(),
+            output: Vec::new(),
+            screen: Screen::default(),
+            settings,
+            registers: Registers::new(),
+            inserted: String::new(),
+            last_find: None,
+            visual_start: (0, 0),
+            visual_kind: VisualKind::Char,
+            last_visual: None,
+            block_insert: None,
+            last_change: None,
+            last_search: None,
+            search_highlight: false,
+            search_origin: None,
+            last_substitute: None,
+            substitution: None,
+            in_global: false,
+            recording: None,
+            last_played: None,
+            failed: false,
+            play_depth: 0,
+            file_marks: HashMap::new(),
+        }
+    }
+
+    /// Draws line number `ln` at `row`, or the wrap marker when the row
+    /// continues the line above.
+    fn draw_line_numbers(&self, screen: &mut Screen, row: usize, ln: usize, continued: bool) {
+        if !self.settings.number {
+            return;
+        }
+        let number = if continued {
+            format!("{:>width$} ", WRAP_MARKER, width = self.ln_pad())
+        } else {
+            format!("{:>width$} ", ln, width = self.ln_pad())
+        };
+        screen.put_str(0, row, &number, Style::default());
+    }
 
-impl Line {
-    fn insert_char_at_cursor(&mut self, i: usize, c: char) {
-        self.line_chars.insert(i, c);
+    fn update_line_num_buff(&mut self, n: usize) {
+        let ln_as_string = n.to_string();
+        self.line_num_buf = ln_as_string.chars().collect()
     }
 
-    fn insert_tab_at_cursor(&mut self, i: usize) {
-        for n in 0..TABASSPACES {
-            self.line_chars.insert(i + n as usize, ' ');
+    /// Opens `f_name`, or starts an empty buffer for it when there is no
+    /// such file. A file that can't be read is an error and leaves the
+    /// current buffer and file name as they were.
+    pub fn read_from_file(&mut self, f_name: String) -> ExResult<()> {
+        let buffer = match fred_file::read_buffer(&f_name) {
+            Ok(buffer) => buffer,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.set_message(format!("\"{}\" [New]", f_name));
+                Buffer::new()
+            }
+            Err(e) => return Err(format!("E484: Can't open file {}: {}", f_name, e)),
+        };
+        if let Some(old) = self.file_name.take() {
+            for (name, pos) in self.buffer.marks.list() {
+                if name.is_ascii_uppercase() {
+                    self.file_marks.insert(name, (old.clone(), pos));
+                }
+            }
         }
+        self.file_name = Some(f_name);
+        self.load_buffer(buffer);
+        Ok(())
     }
 
-    fn remove_char_at(&mut self, i: usize) {
-        self.line_chars.remove(i - 1);
+    /// Makes `buffer` the one being edited, with the uppercase marks that
+    /// were set in its file.
+    pub fn load_buffer(&mut self, buffer: Buffer) {
+        self.buffer = buffer;
+        self.cursor = Cursor::default();
+        self.update_line_num_buff(self.buffer.line_count());
+        let Some(file) = self.file_name.clone() else {
+            return;
+        };
+        let names: Vec<char> = self
+            .file_marks
+            .iter()
+            .filter(|(_, (f, _))| *f == file)
+            .map(|(&name, _)| name)
+            .collect();
+        for name in names {
+            if let Some((_, pos)) = self.file_marks.remove(&name) {
+                self.buffer.marks.set(name, pos);
+            }
+        }
     }
-}
 
-impl Editor {
-    pub fn new() -> Editor {
-        Editor {
-            lines: Vec::new(),
-            status: "Normal".to_string(),
-            mode: EditorMode::Normal,
-            v_draw_region: (0, term::get_term_size().1),
-            h_draw_region: (0, term::get_term_size().0),
-            draw_line: 1,
-            key_state: KeyState::Inactive,
-            line_num_buf: Vec::new(),
+    /// Draws the current state into a new frame and sends the cells that
+    /// changed since the last one to the terminal.
+    pub fn render(&mut self) -> Result<()> {
+        self.scroll_to_cursor();
+        self.update_status();
+        let (width, height) = self.backend.size();
+        if (self.screen.width, self.screen.height) != (width, height) {
+            // the terminal may have reflowed what was on it, start clean
+            self.backend.clear()?;
+            self.screen = Screen::new(width, height);
         }
+        let mut screen = Screen::new(width, height);
+        self.draw_lines(&mut screen);
+        self.draw_output(&mut screen);
+        self.draw_status(&mut screen);
+        backend::draw_screen(self.backend.as_mut(), &screen, &self.screen)?;
+        self.screen = screen;
+        Ok(())
     }
 
-    fn draw_line_numbers(&self, ln: usize) {
-        let mut stdout = stdout();
-        let pad = self.ln_pad() - ln.to_string().len();
-        for _ in 0..pad {
-            stdout.queue(Print(' ')).unwrap();
+    fn draw_lines(&self, screen: &mut Screen) {
+        if self.settings.wrap {
+            self.draw_wrapped_lines(screen);
+            return;
+        }
+        let (top, bottom) = self.v_draw_region;
+        let end = bottom.min(self.buffer.line_count());
+        let (left, right) = self.h_draw_region;
+        let matches = self.highlight_regex();
+        for (row, idx) in (top..end).enumerate() {
+            self.draw_line_numbers(screen, row, idx + 1, false);
+            let start = self.gutter_width();
+            let cells = display::expand(&self.buffer.line(idx), self.settings.tabstop);
+            let visible = display::visible(&cells, left, right);
+            screen.put_str(start, row, &visible, Style::default());
+            self.draw_highlights(screen, row, idx, (left, right), matches.as_ref());
         }
-        stdout.queue(Print(ln)).unwrap();
+    }
 
-        stdout.queue(Print(' ')).unwrap();
-        stdout.flush().unwrap();
+    /// Draws lines from the top of the view split into rows of the text
+    /// width, until the screen is full.
+    fn draw_wrapped_lines(&self, screen: &mut Screen) {
+        let cols = self.text_cols();
+        let start = self.gutter_width();
+        let rows = self.text_rows();
+        let mut row = 0;
+        let mut idx = self.v_draw_region.0;
+        let matches = self.highlight_regex();
+        while row < rows && idx < self.buffer.line_count() {
+            let line = display::expand(&self.buffer.line(idx), self.settings.tabstop);
+            for part in 0..self.line_rows(idx) {
+                if row >= rows {
+                    break;
+                }
+                self.draw_line_numbers(screen, row, idx + 1, part > 0);
+                let from = (part * cols).min(line.len());
+                let text = display::visible(&line, from, from + cols);
+                screen.put_str(start, row, &text, Style::default());
+                let shown = (part * cols, part * cols + cols);
+                self.draw_highlights(screen, row, idx, shown, matches.as_ref());
+                row += 1;
+            }
+            idx += 1;
+        }
     }
 
-    fn update_line_num_buff(&mut self, n: usize) {
-        let ln_as_string = n.to_string();
-        self.line_num_buf = ln_as_string.chars().collect()
+    /// Highlights the search matches, the match `:s` is asking about and the
+    /// selected part of line `idx`, drawn at `row` showing display columns
+    /// `left..right`.
+    fn draw_highlights(
+        &self,
+        screen: &mut Screen,
+        row: usize,
+        idx: usize,
+        (left, right): (usize, usize),
+        matches: Option<&Regex>,
+    ) {
+        let mut spans = Vec::new();
+        if let Some(regex) = matches {
+            let ts = self.settings.tabstop;
+            let line = self.buffer.line(idx);
+            for m in search::line_matches(regex, &line) {
+                let from = display::display_col(&line, m.start, ts);
+                spans.push((from, display::display_col(&line, m.end, ts), SEARCH_STYLE));
+            }
+        }
+        if let Some(m) = self.substitution.as_ref().and_then(|s| s.current.as_ref()) {
+            if m.line == idx {
+                let ts = self.settings.tabstop;
+                let line = self.buffer.line(idx);
+                let from = display::display_col(&line, m.range.start, ts);
+                let to = display::display_col(&line, m.range.end, ts).max(from + 1);
+                spans.push((from, to, CONFIRM_STYLE));
+            }
+        }
+        if let Some((from, to)) = self.selected_vcols(idx) {
+            spans.push((from, to, VISUAL_STYLE));
+        }
+        for (from, to, style) in spans {
+            let (from, to) = (from.max(left), to.min(right));
+            if from < to {
+                screen.set_style(self.gutter_width() + from - left, row, to - from, style);
+            }
+        }
     }
 
-    pub fn read_from_file(&mut self, f_name: String) {
-        if let Ok(lines) = fred_file::read_lines(f_name) {
-            for row in lines {
-                let mut line: Line = Line {
-                    line_chars: Vec::new(),
+    /// Display columns of line `idx` inside the Visual selection. The line
+    /// break counts as a column so selected empty lines show.
+    fn selected_vcols(&self, idx: usize) -> Option<(usize, usize)> {
+        if !matches!(self.mode, EditorMode::Visual) {
+            return None;
+        }
+        let (from, to) = self.visual_bounds();
+        if idx < from.0 || idx > to.0 {
+            return None;
+        }
+        let ts = self.settings.tabstop;
+        let line = self.buffer.line(idx);
+        let width = display::display_width(&line, ts);
+        match self.visual_kind {
+            VisualKind::Line => Some((0, width + 1)),
+            VisualKind::Char => {
+                let start = if idx == from.0 {
+                    display::display_col(&line, from.1, ts)
+                } else {
+                    0
                 };
-                if let Ok(r) = row {
-                    for c in r.chars() {
-                        line.line_chars.push(c);
-                    }
-                }
-                self.lines.push(line);
+                let end = if idx == to.0 {
+                    self.vcol_span(to).1
+                } else {
+                    width + 1
+                };
+                Some((start, end))
+            }
+            VisualKind::Block => {
+                let (left, right) = self.block_cols();
+                Some((left, right.min(width.max(left + 1))))
             }
-            self.update_line_num_buff(self.lines.len());
         }
     }
 
-    pub fn draw_editor(&self, redraw: bool) -> Result<()> {
-        let mut stdout = stdout();
-        let region = self.v_draw_region;
-        let mut region_end = region.1;
-        if region_end > self.lines.len() {
-            region_end = self.lines.len();
+    /// Number of screen rows line `idx` takes up when wrapping. The cursor
+    /// line gets an extra row in insert mode when the cursor sits just past
+    /// a full last row.
+    fn line_rows(&self, idx: usize) -> usize {
+        let mut len = display::display_width(&self.buffer.line(idx), self.settings.tabstop);
+        if idx == self.cursor.line {
+            len = len.max(self.cursor_screen_vcol() + 1);
         }
-        let iter = self.lines[region.0..region_end].iter().enumerate();
-        for (pos, l) in iter {
-            // let ln = iter.position(|x| x.1 == l);
-            if pos >= region.1 - 1 {
-                break;
-            };
-            stdout.queue(cursor::MoveToColumn(0))?;
-            let ln = (region.0) + 1 + pos;
-            self.draw_line_numbers(ln);
-            for lc in &l.line_chars {
-                stdout.queue(Print(lc))?;
+        len.div_ceil(self.text_cols()).max(1)
+    }
+
+    /// Display column the cursor char starts at.
+    fn cursor_vcol(&self) -> usize {
+        let line = self.buffer.line(self.cursor.line);
+        display::display_col(&line, self.cursor.col, self.settings.tabstop)
+    }
+
+    /// Display column the cursor is drawn at. In normal mode it sits on the
+    /// last column of a tab, in insert mode on the first, like vim.
+    fn cursor_screen_vcol(&self) -> usize {
+        let line = self.buffer.line(self.cursor.line);
+        let vcol = self.cursor_vcol();
+        match (&self.mode, display::grapheme_at(&line, self.cursor.col)) {
+            (EditorMode::Insert, _) | (_, None) => vcol,
+            (_, Some("\t")) => {
+                vcol + display::grapheme_width("\t", vcol, self.settings.tabstop) - 1
             }
-            stdout.queue(Print('\n'))?;
-            stdout.flush()?;
+            (_, Some(_)) => vcol,
+        }
+    }
+
+    /// Char index of the char at display column `vcol` of the cursor line.
+    fn col_at_vcol(&self, vcol: usize) -> usize {
+        let line = self.buffer.line(self.cursor.line);
+        display::char_at_display(&line, vcol, self.settings.tabstop)
+    }
+
+    /// Screen row and text column of the cursor, relative to the view.
+    fn cursor_screen_pos(&self) -> (usize, usize) {
+        let vcol = self.cursor_screen_vcol();
+        if !self.settings.wrap {
+            return (
+                self.cursor.line - self.v_draw_region.0,
+                vcol - self.h_draw_region.0,
+            );
         }
+        let cols = self.text_cols();
+        let above: usize = (self.v_draw_region.0..self.cursor.line)
+            .map(|idx| self.line_rows(idx))
+            .sum();
+        (above + vcol / cols, vcol % cols)
+    }
 
-        self.draw_status();
-        if !redraw {
-            let pad = self.ln_pad() + 1;
-            term::set_cursor_pos(pad as u16, 0);
+    fn draw_output(&self, screen: &mut Screen) {
+        let top = self.text_rows().saturating_sub(self.output.len());
+        for (i, line) in self.output.iter().enumerate() {
+            screen.fill_row(top + i, Style::default());
+            screen.put_str(0, top + i, line, Style::default());
         }
-        Ok(())
     }
 
-    fn draw_status(&self) {
-        let draw_line = term::get_term_size().1;
-        term::set_cursor_pos(0, draw_line as u16);
-        let status_message = self.get_status_message();
-        let mut stdout = stdout();
-        stdout
-            .queue(terminal::Clear(ClearType::CurrentLine))
-            .unwrap();
-        stdout.flush().unwrap();
-        stdout
-            .queue(Print(SetBackgroundColor(Color::DarkMagenta)))
-            .unwrap();
-        stdout
-            .queue(Print(SetForegroundColor(Color::Black)))
-            .unwrap();
-        stdout.queue(Print(&status_message)).unwrap();
-        stdout.queue(Print(ResetColor)).unwrap();
-        stdout.flush().unwrap();
-    }
-
-    pub fn redraw(&self) -> Result<()> {
-        let mut stdout = stdout();
-        stdout.queue(terminal::Clear(ClearType::All))?;
-        stdout.flush()?;
-        self.draw_editor(true)
+    /// Draws the status line and places the cursor where typing goes.
+    fn draw_status(&self, screen: &mut Screen) {
+        let row = self.text_rows();
+        if let EditorMode::Command = self.mode {
+            let cl = &self.command_line;
+            screen.put_str(
+                0,
+                row,
+                &format!("{}{}", cl.prompt, cl.text()),
+                Style::default(),
+            );
+            screen.set_cursor(cl.cursor_col(), row);
+            return;
+        }
+        if !self.message.is_empty() {
+            screen.put_str(0, row, &self.message, Style::default());
+        } else {
+            let (fg, bg) = self.settings.status_style();
+            let style = Style {
+                fg: Some(fg),
+                bg: Some(bg),
+            };
+            screen.fill_row(row, style);
+            screen.put_str(0, row, &self.status, style);
+        }
+        if let Some((name, _)) = self.recording {
+            let text = format!("recording @{} ", name);
+            let x = screen.width.saturating_sub(display::str_width(&text) + 12);
+            screen.put_str(x, row, &text, Style::default());
+        }
+        if !self.pending_keys.is_empty() {
+            // like vim's showcmd, in the last columns of the bottom row
+            let keys = self.get_pending_keys_text();
+            let x = screen.width.saturating_sub(display::str_width(&keys) + 1);
+            screen.put_str(x, row, &format!("{} ", keys), Style::default());
+        }
+        let (cursor_row, cursor_col) = self.cursor_screen_pos();
+        screen.set_cursor(self.gutter_width() + cursor_col, cursor_row);
     }
 
     fn set_normal_mode(&mut self) {
-        term::save_cursor_pos();
+        if let EditorMode::Visual = self.mode {
+            let end = (self.cursor.line, self.cursor.col);
+            self.last_visual = Some((self.visual_start, end, self.visual_kind));
+            let (from, to) = self.visual_bounds();
+            self.buffer.marks.set('<', from);
+            self.buffer.marks.set('>', to);
+        }
+        if let EditorMode::Insert = self.mode {
+            let pos = (self.cursor.line, self.cursor.col);
+            self.buffer.marks.set('^', pos);
+            self.finish_block_insert();
+            self.buffer.end_undo_group();
+            let inserted = std::mem::take(&mut self.inserted);
+            if let Some(change) = self.last_change.as_mut() {
+                change.inserted = inserted.clone();
+            }
+            self.registers
+                .set('.', Register::new(inserted, RegisterKind::Char));
+            self.mode = EditorMode::Normal;
+            self.move_left();
+            self.clamp_col();
+        }
         self.mode = EditorMode::Normal;
-        self.draw_status();
-        term::restore_cursor_pos();
     }
 
     fn set_insert_mode(&mut self) {
-        term::save_cursor_pos();
+        self.buffer.begin_undo_group();
+        self.inserted.clear();
         self.mode = EditorMode::Insert;
-        self.draw_status();
-        term::restore_cursor_pos();
     }
 
-    fn set_visual_mode(&mut self) {
-        term::save_cursor_pos();
+    fn set_visual_mode(&mut self, kind: VisualKind) {
+        self.visual_start = (self.cursor.line, self.cursor.col);
+        self.visual_kind = kind;
+        self.mode = EditorMode::Visual;
+    }
+
+    /// `v`, `V` and `Ctrl-v`: start Visual mode, change its kind, or leave
+    /// it when it already has this kind.
+    fn toggle_visual_mode(&mut self, kind: VisualKind) {
+        match self.mode {
+            EditorMode::Visual if self.visual_kind == kind => self.set_normal_mode(),
+            EditorMode::Visual => self.visual_kind = kind,
+            _ => self.set_visual_mode(kind),
+        }
+    }
+
+    /// Selects the last Visual area again (`gv`).
+    fn reselect(&mut self) {
+        let Some((start, end, kind)) = self.last_visual else {
+            return;
+        };
+        let last = self.buffer.line_count() - 1;
+        self.set_normal_mode();
         self.mode = EditorMode::Visual;
-        self.draw_status();
-        term::restore_cursor_pos();
+        self.visual_kind = kind;
+        self.visual_start = (start.0.min(last), start.1);
+        self.goto_pos(end.0, end.1);
+    }
+
+    /// Moves the cursor to the other end of the selection (`o`).
+    fn swap_visual_ends(&mut self) {
+        let other = self.visual_start;
+        self.visual_start = (self.cursor.line, self.cursor.col);
+        self.goto_pos(other.0, other.1);
+    }
+
+    /// Start and end of the selection in buffer order.
+    fn visual_bounds(&self) -> (motion::Pos, motion::Pos) {
+        let cursor = (self.cursor.line, self.cursor.col);
+        (self.visual_start.min(cursor), self.visual_start.max(cursor))
+    }
+
+    /// Display columns the grapheme at `pos` covers, one column past the
+    /// end of the line.
+    fn vcol_span(&self, (line, col): motion::Pos) -> (usize, usize) {
+        let ts = self.settings.tabstop;
+        let text = self.buffer.line(line);
+        let start = display::display_col(&text, col, ts);
+        let width =
+            display::grapheme_at(&text, col).map_or(1, |g| display::grapheme_width(g, start, ts));
+        (start, start + width)
+    }
+
+    /// Display columns `left..right` of a Visual block. After `$` the block
+    /// reaches the end of every line and `right` is `usize::MAX`.
+    fn block_cols(&self) -> (usize, usize) {
+        let (a, b) = (
+            self.vcol_span(self.visual_start),
+            self.vcol_span((self.cursor.line, self.cursor.col)),
+        );
+        let right = if self.cursor.want_col == usize::MAX {
+            usize::MAX
+        } else {
+            a.1.max(b.1)
+        };
+        (a.0.min(b.0), right)
     }
 
-    fn set_draw_line(&mut self, dl: usize) {
-        self.draw_line = dl;
+    /// Char offsets of the part of line `idx` in block columns `cols`.
+    fn block_range(&self, idx: usize, (left, right): (usize, usize)) -> std::ops::Range<usize> {
+        let ts = self.settings.tabstop;
+        let line = self.buffer.line(idx);
+        let from = display::char_at_display(&line, left, ts);
+        let to = display::char_at_display(&line, right, ts).max(from);
+        let offset = self.buffer.line_to_char(idx);
+        offset + from..offset + to
+    }
+
+    /// Column of display column `vcol` on line `idx`, adding spaces to the
+    /// end of the line when it is too short. `usize::MAX` is the line end.
+    fn pad_to_vcol(&mut self, idx: usize, vcol: usize) -> usize {
+        let line = self.buffer.line(idx);
+        let len = line.chars().count();
+        let width = display::display_width(&line, self.settings.tabstop);
+        if vcol == usize::MAX {
+            return len;
+        }
+        if width < vcol {
+            let end = self.buffer.line_to_char(idx) + len;
+            self.buffer.insert(end, &" ".repeat(vcol - width));
+            return len + vcol - width;
+        }
+        display::char_at_display(&line, vcol, self.settings.tabstop)
+    }
+
+    /// `I` and `A`. On a Visual block they insert on every line of the
+    /// block, otherwise before the selection or first non-blank and after
+    /// the selection or line.
+    fn insert_at_edge(&mut self, append: bool) {
+        let visual = matches!(self.mode, EditorMode::Visual);
+        let (from, to) = self.visual_bounds();
+        if visual && self.visual_kind == VisualKind::Block {
+            let (left, right) = self.block_cols();
+            let vcol = if append { right } else { left };
+            self.set_normal_mode();
+            self.set_insert_mode();
+            let col = self.pad_to_vcol(from.0, vcol);
+            self.block_insert = Some(BlockInsert {
+                first: from.0,
+                last: to.0,
+                vcol,
+                col,
+                len: self.buffer.line_len(from.0),
+                append,
+            });
+            self.goto_pos(from.0, col);
+            return;
+        }
+        let (line, col) = match (visual, self.visual_kind, append) {
+            (true, VisualKind::Char, false) => from,
+            (true, VisualKind::Char, true) => {
+                let text = self.buffer.line(to.0);
+                (
+                    to.0,
+                    display::next_grapheme(&text, to.1).min(text.chars().count()),
+                )
+            }
+            (true, _, false) => motion::first_non_blank(&self.buffer, from.0),
+            (true, _, true) => (to.0, self.buffer.line_len(to.0)),
+            (false, _, false) => motion::first_non_blank(&self.buffer, self.cursor.line),
+            (false, _, true) => (self.cursor.line, self.buffer.line_len(self.cursor.line)),
+        };
+        self.set_normal_mode();
+        self.set_insert_mode();
+        self.goto_pos(line, col);
+    }
+
+    /// Copies the text typed on the first line of a block insert to the
+    /// other lines of the block.
+    fn finish_block_insert(&mut self) {
+        let Some(block) = self.block_insert.take() else {
+            return;
+        };
+        let len = self.buffer.line_len(block.first);
+        if self.cursor.line != block.first || len <= block.len {
+            return;
+        }
+        let text: String = self
+            .buffer
+            .line(block.first)
+            .chars()
+            .skip(block.col)
+            .take(len - block.len)
+            .collect();
+        for idx in block.first + 1..=block.last {
+            let width = display::display_width(&self.buffer.line(idx), self.settings.tabstop);
+            if !block.append && width <= block.vcol {
+                continue;
+            }
+            let col = self.pad_to_vcol(idx, block.vcol);
+            let at = self.buffer.pos_to_char(idx, col);
+            self.buffer.insert(at, &text);
+        }
     }
 
     fn update_status(&mut self) {
@@ -209,65 +737,335 @@ impl Editor {
         self.v_draw_region = (start, end)
     }
 
-    fn update_key_state(&mut self, ks: KeyState) {
-        term::save_cursor_pos();
-        self.key_state = ks;
-        self.draw_status();
-        term::restore_cursor_pos();
+    pub fn set_message<S: Into<String>>(&mut self, msg: S) {
+        self.message = msg.into();
     }
 
-    fn get_key_state_text(&self) -> String {
-        match self.key_state {
-            KeyState::Waiting(c) => {
-                format!("WAITING - {}", c)
+    fn set_command_mode(&mut self) {
+        self.message.clear();
+        self.mode = EditorMode::Command;
+        self.command_line.start(':');
+    }
+
+    fn leave_command_mode(&mut self) {
+        self.search_origin = None;
+        self.mode = EditorMode::Normal;
+    }
+
+    fn handle_command_key(&mut self, key: KeyEvent) {
+        let prompt = self.command_line.prompt;
+        match self.command_line.handle_key(key) {
+            CommandLineEvent::Edited if prompt != ':' => self.incremental_search(),
+            CommandLineEvent::Edited => {}
+            CommandLineEvent::Cancelled => {
+                self.restore_search_origin();
+                self.leave_command_mode();
+            }
+            CommandLineEvent::Submitted(text) if prompt != ':' => {
+                self.restore_search_origin();
+                self.leave_command_mode();
+                self.search(&text, prompt == '/');
+            }
+            CommandLineEvent::Submitted(text) => {
+                self.leave_command_mode();
+                if !text.trim().is_empty() {
+                    let command = Register::new(text.as_str(), RegisterKind::Char);
+                    self.registers.set(':', command);
+                }
+                self.run_ex(&text);
             }
-            _ => "INACTIVE".to_string(),
         }
     }
 
-    #[allow(unused_assignments)]
-    fn get_status_message(&self) -> String {
-        let ln_addend = if self.v_draw_region.0 > 0 {
-            self.v_draw_region.0 + 1
+    /// Parses and runs a `:` command, showing any error in the status line.
+    pub fn run_ex(&mut self, line: &str) {
+        if let Err(e) = self.execute_ex(line) {
+            self.failed = true;
+            self.set_message(e);
+        }
+    }
+
+    /// Parses and runs a `:` command.
+    pub fn execute_ex(&mut self, line: &str) -> ExResult<()> {
+        ex::parse(line).and_then(|cmd| {
+            if cmd.name.is_empty() {
+                if let Some(range) = &cmd.range {
+                    let line = self.resolve_address(&range.end)?.max(1) - 1;
+                    let col = first_non_blank(&self.buffer.line(line));
+                    self.buffer
+                        .marks
+                        .push_jump((self.cursor.line, self.cursor.col));
+                    self.goto_pos(line, col);
+                }
+                return Ok(());
+            }
+            match self.commands.lookup(&cmd.name) {
+                Some(handler) => handler(self, &cmd),
+                None => Err(format!("E492: Not an editor command: {}", line.trim())),
+            }
+        })
+    }
+
+    /// Resolves an address to a one based line number. Line `0` is only
+    /// produced when it was asked for explicitly.
+    pub fn resolve_address(&self, address: &ex::Address) -> ExResult<usize> {
+        let base = match address.base {
+            AddressBase::Line(n) => n,
+            AddressBase::Current => self.cursor.line + 1,
+            AddressBase::Last => self.buffer.line_count(),
+            AddressBase::Mark(name) => match self.mark_pos(name) {
+                Some((line, _)) => line + 1,
+                None => return Err("E20: Mark not set".into()),
+            },
+        };
+        let line = isize::try_from(base)
+            .ok()
+            .and_then(|base| base.checked_add(address.offset))
+            .and_then(|line| usize::try_from(line).ok())
+            .filter(|&line| line <= self.buffer.line_count());
+        line.ok_or_else(|| "E16: Invalid range".into())
+    }
+
+    /// Zero based, inclusive line range of `cmd`, defaulting to the cursor line.
+    pub fn line_range(&self, cmd: &ExCommand) -> ExResult<(usize, usize)> {
+        let (start, end) = match &cmd.range {
+            Some(range) => (
+                self.resolve_address(&range.start)?,
+                self.resolve_address(&range.end)?,
+            ),
+            None => {
+                let current = self.cursor.line + 1;
+                (current, current)
+            }
+        };
+        let (start, end) = if start > end {
+            (end, start)
         } else {
-            self.v_draw_region.0
+            (start, end)
+        };
+        Ok((start.max(1) - 1, end.max(1) - 1))
+    }
+
+    /// Moves lines `start..=end` below line `to`, one based with `0` for
+    /// above the first line, and puts the cursor on the last one moved.
+    pub fn move_lines(&mut self, (start, end): (usize, usize), to: usize) -> ExResult<()> {
+        if to > start && to <= end {
+            return Err("E134: Cannot move a range of lines into itself".into());
+        }
+        let count = end - start + 1;
+        let mut first = start;
+        if to != start && to != end + 1 {
+            let text: String = (start..=end)
+                .map(|i| format!("{}\n", self.buffer.line(i)))
+                .collect();
+            first = if to > end { to - count } else { to };
+            self.buffer.begin_undo_group();
+            self.buffer.remove_lines(start..end + 1);
+            self.put_lines(first, &text, false);
+            self.buffer.end_undo_group();
+        }
+        let (line, col) = motion::first_non_blank(&self.buffer, first + count - 1);
+        self.goto_pos(line, col);
+        Ok(())
+    }
+
+    /// Runs `command` on each line in `first..=last` matching `pattern`,
+    /// or not matching when `invert`. The lines are all marked before the
+    /// first run, so commands that delete or add lines neither skip nor
+    /// repeat any, and everything undoes as one change. Without a command
+    /// the lines are listed.
+    pub fn global(
+        &mut self,
+        (first, last): (usize, usize),
+        pattern: &str,
+        invert: bool,
+        command: &str,
+    ) -> ExResult<()> {
+        if self.in_global {
+            return Err("E147: Cannot do :global recursive".into());
+        }
+        let pattern = match (pattern, &self.last_search) {
+            ("", Some(search)) => search.pattern.clone(),
+            ("", None) => return Err("E35: No previous regular expression".into()),
+            (pattern, _) => pattern.to_string(),
         };
-        let term_size = term::get_term_size();
+        let regex = self.compile_search(&pattern)?;
+        self.set_search(pattern.clone(), true);
+        let lines: Vec<usize> = (first..=last)
+            .filter(|&i| regex.is_match(&self.buffer.line(i)) != invert)
+            .collect();
+        if lines.is_empty() {
+            return Err(match invert {
+                true => format!("Pattern found in every line: {}", pattern),
+                false => format!("E486: Pattern not found: {}", pattern),
+            });
+        }
+        if command.trim().is_empty() {
+            let listed = lines.iter().map(|&i| self.buffer.line(i)).collect();
+            self.show_output(listed);
+            return Ok(());
+        }
+        self.buffer.set_line_marks(lines);
+        self.in_global = true;
+        self.buffer.begin_undo_group();
+        let mut result = Ok(());
+        while let Some(line) = self.buffer.next_line_mark() {
+            self.goto_pos(line, 0);
+            result = self.execute_ex(command);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.buffer.set_line_marks(Vec::new());
+        self.buffer.end_undo_group();
+        self.in_global = false;
+        self.update_line_num_buff(self.buffer.line_count());
+        if self.output.is_empty() {
+            self.message.clear();
+        }
+        result
+    }
+
+    pub fn delete_lines(&mut self, start: usize, end: usize) {
+        self.buffer.remove_lines(start..end + 1);
+        self.update_line_num_buff(self.buffer.line_count());
+        let line = start.min(self.buffer.line_count() - 1);
+        let col = first_non_blank(&self.buffer.line(line));
+        self.goto_pos(line, col);
+    }
+
+    pub fn undo(&mut self) {
+        let seq = self.buffer.undo_seq();
+        match self.buffer.undo() {
+            Some(pos) => self.after_undo(pos, format!("before #{}", seq)),
+            None => self.set_message("Already at oldest change"),
+        }
+    }
+
+    pub fn redo(&mut self) {
+        match self.buffer.redo() {
+            Some(pos) => {
+                let seq = self.buffer.undo_seq();
+                self.after_undo(pos, format!("after #{}", seq))
+            }
+            None => self.set_message("Already at newest change"),
+        }
+    }
+
+    fn undo_travel(&mut self, forward: bool) {
+        match self.buffer.undo_travel(forward) {
+            Some(pos) => {
+                let seq = self.buffer.undo_seq();
+                self.after_undo(pos, format!("at #{}", seq))
+            }
+            None if forward => self.set_message("Already at newest change"),
+            None => self.set_message("Already at oldest change"),
+        }
+    }
+
+    fn after_undo(&mut self, pos: usize, msg: String) {
+        self.update_line_num_buff(self.buffer.line_count());
+        let pos = pos.min(self.buffer.len_chars());
+        let line = self.buffer.char_to_line(pos);
+        let col = pos - self.buffer.line_to_char(line);
+        self.goto_pos(line, col);
+        self.set_message(msg);
+    }
+
+    /// Moves the cursor to `line`, `col`, scrolling when the line is off screen.
+    fn goto_pos(&mut self, line: usize, col: usize) {
+        self.cursor.line = line.min(self.buffer.line_count() - 1);
+        self.cursor.col = col;
+        self.clamp_col();
+        self.cursor.want_col = self.cursor_screen_vcol();
+    }
+
+    /// Shows several lines of output above the status line until the next key.
+    pub fn show_output(&mut self, lines: Vec<String>) {
+        self.output = lines;
+        self.message = "Press ENTER or type command to continue".into();
+    }
+
+    fn dismiss_output(&mut self) {
+        self.output.clear();
+        self.message.clear();
+    }
+
+    /// Writes the buffer to `path`, or to the current file name when `path` is
+    /// `None`. An existing file other than the current one is only written
+    /// over with `force`.
+    pub fn write_file(&mut self, path: Option<String>, force: bool) -> ExResult<()> {
+        let path = path
+            .or_else(|| self.file_name.clone())
+            .ok_or("E32: No file name")?;
+        let other_file = self.file_name.as_ref() != Some(&path);
+        if !force && other_file && std::path::Path::new(&path).exists() {
+            return Err("E13: File exists (add ! to override)".into());
+        }
+        let bytes = fred_file::write_buffer(&path, &self.buffer)
+            .map_err(|e| format!("E212: Can't open file for writing: {}", e))?;
+        if self.file_name.is_none() {
+            self.file_name = Some(path.clone());
+        }
+        if self.file_name.as_ref() == Some(&path) {
+            self.buffer.mark_saved();
+        }
+        self.set_message(format!(
+            "\"{}\" {}L, {}B written",
+            path,
+            self.buffer.line_count(),
+            bytes
+        ));
+        Ok(())
+    }
+
+    fn get_pending_keys_text(&self) -> String {
+        self.pending_keys.iter().map(keys::key_to_string).collect()
+    }
+
+    #[allow(unused_assignments)]
+    fn get_status_message(&self) -> String {
+        let term_size = self.backend.size();
         let mut status_text = String::new();
-        let ln = self.draw_line + ln_addend;
-        let ks = self.get_key_state_text();
+        let ln = self.cursor.line + 1;
+        let col = self.cursor.col + 1;
         match self.mode {
-            EditorMode::Normal => {
+            EditorMode::Normal | EditorMode::Command => {
                 status_text = format!(
-                    " NORMAL | Line: {}/{} | v_draw: {:?} h_draw: {:?} | DrawLine: {} | TermSize: {:?} | KeyState: {}",
+                    " NORMAL | Line: {}/{} Col: {} | v_draw: {:?} h_draw: {:?} | TermSize: {:?}",
                     ln,
-                    self.lines.len(),
+                    self.buffer.line_count(),
+                    col,
                     self.v_draw_region,
                     self.h_draw_region,
-                    self.draw_line,
                     term_size,
-                    ks,
                 )
             }
             EditorMode::Insert => {
                 status_text = format!(
-                    " INSERT | Line: {}/{} | v_draw: {:?} h_draw {:?}| DrawLine: {} | TermSize: {:?}",
+                    " INSERT | Line: {}/{} Col: {} | v_draw: {:?} h_draw {:?}| TermSize: {:?}",
                     ln,
-                    self.lines.len(),
+                    self.buffer.line_count(),
+                    col,
                     self.v_draw_region,
                     self.h_draw_region,
-                    self.draw_line,
                     term_size,
                 )
             }
             EditorMode::Visual => {
+                let name = match self.visual_kind {
+                    VisualKind::Char => "VISUAL",
+                    VisualKind::Line => "VISUAL LINE",
+                    VisualKind::Block => "VISUAL BLOCK",
+                };
                 status_text = format!(
-                    " VISUAL | Line: {}/{} | v_draw: {:?} h_draw {:?} | DrawLine: {} | TermSize: {:?}",
+                    " {} | Line: {}/{} Col: {} | v_draw: {:?} h_draw {:?} | TermSize: {:?}",
+                    name,
                     ln,
-                    self.lines.len(),
+                    self.buffer.line_count(),
+                    col,
                     self.v_draw_region,
                     self.h_draw_region,
-                    self.draw_line,
                     term_size,
                 )
             }
@@ -288,259 +1086,1694 @@ impl Editor {
         " ".repeat(term_width - status_len)
     }
 
-    fn move_down(&mut self) {
-        if self.v_draw_region.1 < self.lines.len() || self.draw_line < term::get_term_size().1 - 1 {
-            let mut pos = cursor::position().unwrap().1 + 1;
-            let term_size = term::get_term_size().1 as u16;
-            let mut redraw_status_only = true;
-            if pos < term_size - 1 {
-                let mut stdout = stdout();
-                stdout.queue(cursor::MoveDown(1)).unwrap();
-                stdout.flush().unwrap();
-                pos += 1;
-            } else {
-                let region = self.v_draw_region;
-                self.update_v_draw_region(region.0 + 1, region.1 + 1);
-                redraw_status_only = false;
-            }
-            self.set_draw_line(pos as usize);
-            self.update_status();
-            term::save_cursor_pos();
-            if redraw_status_only {
-                self.draw_status();
-            } else {
-                self.redraw().unwrap();
-            }
-            term::restore_cursor_pos();
+    /// Number of screen rows available for text, the last row is the status line.
+    fn text_rows(&self) -> usize {
+        self.backend.size().1.saturating_sub(1).max(1)
+    }
+
+    /// Number of screen columns available for text, right of the line numbers.
+    fn text_cols(&self) -> usize {
+        let width = self.backend.size().0;
+        width.saturating_sub(self.gutter_width()).max(1)
+    }
+
+    /// Reflows the view after the terminal changed size, keeping the cursor
+    /// on screen.
+    fn resize(&mut self, _width: usize, height: usize) {
+        let top = self.v_draw_region.0;
+        self.update_v_draw_region(top, top + height.saturating_sub(1).max(1));
+        self.scroll_to_cursor();
+    }
 
-            self.clamp_to_end_of_line();
+    /// Last column the cursor may sit on, the start of the last grapheme.
+    /// Insert mode may sit one past the end of the line.
+    fn max_col(&self) -> usize {
+        let len = self.buffer.line_len(self.cursor.line);
+        match self.mode {
+            EditorMode::Insert => len,
+            _ => display::prev_grapheme(&self.buffer.line(self.cursor.line), len),
         }
     }
 
-    fn move_up(&mut self) {
-        if self.v_draw_region.0 > 0 || self.draw_line > 1 {
-            let mut pos = cursor::position().unwrap().1 + 1;
-            let mut redraw_status_only = true;
-            if pos > 1 {
-                let mut stdout = stdout();
-                stdout.queue(cursor::MoveUp(1)).unwrap();
-                stdout.flush().unwrap();
-                pos -= 1;
-            } else {
-                let region = self.v_draw_region;
-                self.update_v_draw_region(region.0 - 1, region.1 - 1);
-                let mut stdout = stdout();
-                stdout.queue(cursor::MoveUp(1)).unwrap();
-                stdout.flush().unwrap();
-                pos -= 1;
-                redraw_status_only = false;
-            }
-            self.set_draw_line(pos as usize);
-            self.update_status();
-            term::save_cursor_pos();
-            if redraw_status_only {
-                self.draw_status();
-            } else {
-                self.redraw().unwrap();
-            }
-            term::restore_cursor_pos();
+    fn clamp_col(&mut self) {
+        let col = self.cursor.col.min(self.max_col());
+        self.cursor.col = display::grapheme_start(&self.buffer.line(self.cursor.line), col);
+    }
+
+    /// Scrolls the view so the cursor is visible, vertically and
+    /// horizontally. Returns whether it moved.
+    fn scroll_to_cursor(&mut self) -> bool {
+        if self.settings.wrap {
+            return self.scroll_to_cursor_wrapped();
+        }
+        let rows = self.text_rows();
+        let so = self.scrolloff();
+        let line = self.cursor.line;
+        let (mut top, _) = self.v_draw_region;
+        if line < top + so {
+            top = line.saturating_sub(so);
+        } else if line + so >= top + rows {
+            let last_top = self.buffer.line_count().saturating_sub(rows);
+            top = (line + so + 1 - rows).min(last_top).max(top.min(line));
+        }
+        let cols = self.text_cols();
+        let vcol = self.cursor_screen_vcol();
+        let (mut left, _) = self.h_draw_region;
+        if vcol < left {
+            left = vcol;
+        } else if vcol >= left + cols {
+            left = vcol + 1 - cols;
+        }
+        let changed =
+            self.v_draw_region != (top, top + rows) || self.h_draw_region != (left, left + cols);
+        self.update_v_draw_region(top, top + rows);
+        self.h_draw_region = (left, left + cols);
+        changed
+    }
+
+    /// `scrolloff`, limited so the cursor can still reach the middle row.
+    fn scrolloff(&self) -> usize {
+        self.settings.scrolloff.min((self.text_rows() - 1) / 2)
+    }
 
-            self.clamp_to_end_of_line();
+    /// Like `scroll_to_cursor` for wrapped lines, where `v_draw_region` ends
+    /// after the last line that starts on screen.
+    fn scroll_to_cursor_wrapped(&mut self) -> bool {
+        let rows = self.text_rows();
+        let so = self.scrolloff();
+        let line = self.cursor.line;
+        let (mut top, _) = self.v_draw_region;
+        if line < top + so {
+            top = line.saturating_sub(so);
         }
+        let below = (line + so).min(self.buffer.line_count() - 1);
+        let mut used: usize = (top..line).map(|i| self.line_rows(i)).sum::<usize>()
+            + self.cursor_screen_vcol() / self.text_cols()
+            + 1
+            + (line + 1..=below).map(|i| self.line_rows(i)).sum::<usize>();
+        while used > rows && top < self.cursor.line {
+            used -= self.line_rows(top);
+            top += 1;
+        }
+        let mut end = top;
+        let mut row = 0;
+        while row < rows && end < self.buffer.line_count() {
+            row += self.line_rows(end);
+            end += 1;
+        }
+        let region = (top, end);
+        let changed = self.v_draw_region != region;
+        self.update_v_draw_region(top, end);
+        self.h_draw_region = (0, self.text_cols());
+        changed
     }
 
-    fn move_right(&mut self) {
-        let pos = cursor::position().unwrap();
-        let line = &self.get_line_from_cursor().line_chars;
-        let line_len = line.len();
-        if line_len > 0 {
-            let stop_point = if line_len >= 2 {
-                // this is len() - 2 because it assumes the last char in the vec is a new line character
-                line_len - 2
+    /// Position one screen row down, or up when `down` is false, `count`
+    /// times, staying in the same screen column (`gj`, `gk`). Without `wrap`
+    /// this is the same as `j` and `k`.
+    fn display_row_target(&self, count: usize, down: bool) -> Option<motion::Pos> {
+        if !self.settings.wrap {
+            let line = if down {
+                motion::down(&self.buffer, self.cursor.line, count)?
             } else {
-                line_len - 1
+                motion::up(self.cursor.line, count)?
             };
-            let pad = self.ln_pad() + 1;
-            if pos.0 as usize <= (stop_point + pad) {
-                let mut stdout = stdout();
-                stdout.queue(cursor::MoveRight(1)).unwrap();
-                stdout.flush().unwrap();
+            return Some(self.pos_at_want_col(line));
+        }
+        let cols = self.text_cols();
+        let x = self.cursor.want_col % cols;
+        let mut line = self.cursor.line;
+        let mut part = self.cursor_screen_vcol() / cols;
+        for i in 0..count {
+            if down && part + 1 < self.line_rows(line) {
+                part += 1;
+            } else if down && line + 1 < self.buffer.line_count() {
+                line += 1;
+                part = 0;
+            } else if !down && part > 0 {
+                part -= 1;
+            } else if !down && line > 0 {
+                line -= 1;
+                part = self.line_rows(line) - 1;
+            } else if i == 0 {
+                return None;
+            } else {
+                break;
             }
         }
+        let text = self.buffer.line(line);
+        Some((
+            line,
+            display::char_at_display(&text, part * cols + x, self.settings.tabstop),
+        ))
     }
 
-    fn move_left(&self) {
-        let pos = cursor::position().unwrap().0 as usize;
-        let start_pos = self.ln_pad() + 1;
-
-        if pos > start_pos {
-            let mut stdout = stdout();
-            stdout.queue(cursor::MoveLeft(1)).unwrap();
-            stdout.flush().unwrap();
+    /// Scrolls the view `n` columns right, or left when `n` is negative, and
+    /// moves the cursor along if it would leave the screen (`zl`, `zh`).
+    fn scroll_cols(&mut self, n: isize) {
+        if self.settings.wrap {
+            return;
+        }
+        let cols = self.text_cols();
+        let left = if n < 0 {
+            self.h_draw_region.0.saturating_sub(n.unsigned_abs())
+        } else {
+            self.h_draw_region.0 + n as usize
+        };
+        self.h_draw_region = (left, left + cols);
+        let vcol = self.cursor_screen_vcol();
+        if vcol < left {
+            self.cursor.col = self.col_at_vcol(left);
+        } else if vcol >= left + cols {
+            self.cursor.col = self.col_at_vcol(left + cols - 1);
         }
+        self.clamp_col();
+        self.cursor.want_col = self.cursor_screen_vcol();
     }
 
-    fn clamp_to_start_of_line(&mut self) {
-        let start_pos = self.ln_pad() + 2;
-        term::move_to_column(start_pos as u16);
+    /// Scrolls the view so the cursor is in the first (`zs`) or last (`ze`)
+    /// text column.
+    fn scroll_cursor_to_edge(&mut self, start: bool) {
+        if self.settings.wrap {
+            return;
+        }
+        let cols = self.text_cols();
+        let left = if start {
+            self.cursor_vcol()
+        } else {
+            (self.cursor_screen_vcol() + 1).saturating_sub(cols)
+        };
+        self.h_draw_region = (left, left + cols);
     }
 
-    fn clamp_to_end_of_line(&mut self) {
-        let new_pos = cursor::position().unwrap();
-        let line_len = self.get_line_from_cursor().line_chars.len();
-        let line_len_offset = self.ln_pad() + 1;
-        if new_pos.0 as usize > line_len {
-            term::move_to_column(line_len_offset as u16 + 1);
+    fn move_left(&mut self) {
+        if self.cursor.col > 0 {
+            let line = self.buffer.line(self.cursor.line);
+            self.cursor.col = display::prev_grapheme(&line, self.cursor.col);
+            self.cursor.want_col = self.cursor_screen_vcol();
         }
     }
 
-    pub fn get_line_from_cursor(&mut self) -> &mut Line {
-        let addend = if self.v_draw_region.0 == 0 {
-            self.draw_line - 1
+    /// Char offset of the cursor in the buffer.
+    fn cursor_char(&self) -> usize {
+        self.buffer.pos_to_char(self.cursor.line, self.cursor.col)
+    }
+
+    /// Updates state that depends on the text after it changed under the cursor.
+    fn after_edit(&mut self) {
+        self.update_line_num_buff(self.buffer.line_count());
+        self.cursor.want_col = self.cursor_screen_vcol();
+    }
+
+    fn insert_text(&mut self, s: &str) {
+        let idx = self.cursor_char();
+        self.buffer.insert(idx, s);
+        self.inserted.push_str(s);
+        self.cursor.col += s.chars().count();
+        self.after_edit();
+    }
+
+    fn insert_newline(&mut self) {
+        let idx = self.cursor_char();
+        self.buffer.insert_char(idx, '\n');
+        self.inserted.push('\n');
+        self.cursor.line += 1;
+        self.cursor.col = 0;
+        self.after_edit();
+    }
+
+    fn backspace(&mut self) {
+        let idx = self.cursor_char();
+        if idx == 0 {
+            return;
+        }
+        let start = if self.cursor.col == 0 {
+            self.cursor.line -= 1;
+            self.cursor.col = self.buffer.line_len(self.cursor.line);
+            idx - 1
         } else {
-            self.draw_line
+            let line = self.buffer.line(self.cursor.line);
+            let col = display::prev_grapheme(&line, self.cursor.col);
+            let start = idx - (self.cursor.col - col);
+            self.cursor.col = col;
+            start
         };
-        let point = addend + self.v_draw_region.0;
-        &mut self.lines[point]
+        self.buffer.remove(start..idx);
+        // the typed text ends at the cursor, so drop as much of it as was
+        // removed, and nothing once backspacing goes back past where it began
+        let cut = self.inserted.char_indices().rev().nth(idx - start - 1);
+        self.inserted.truncate(cut.map_or(0, |(i, _)| i));
+        self.after_edit();
     }
 
     fn ln_pad(&self) -> usize {
-        self.lines.len().to_string().len() + 1
+        self.buffer.line_count().to_string().len() + 1
     }
 
-    pub fn handle_input(&mut self) -> Result<()> {
-        loop {
-            match self.mode {
-                EditorMode::Insert => {
-                    if let Event::Key(KeyEvent { code, modifiers: _ }) = read()? {
-                        match code {
-                            KeyCode::Esc => {
-                                self.set_normal_mode();
-                            }
-                            KeyCode::Tab => {
-                                let pos = cursor::position()?;
-                                let pad = self.ln_pad() + 1;
-                                let line = self.get_line_from_cursor();
-                                line.insert_tab_at_cursor(pos.0 as usize - pad);
-                                term::save_cursor_pos();
-                                self.redraw()?;
-                                term::restore_cursor_pos();
-                                term::set_cursor_pos(pos.0 + TABASSPACES, pos.1);
-                            }
-                            KeyCode::Backspace => {
-                                let pos = cursor::position()?;
-                                let pad = self.ln_pad() + 1;
-                                if pos.0 as usize - pad > 0 {
-                                    let line = self.get_line_from_cursor();
-                                    line.remove_char_at(pos.0 as usize - pad);
-                                    term::save_cursor_pos();
-                                    self.redraw()?;
-                                    term::restore_cursor_pos();
-                                    self.move_left();
-                                }
-                            }
-                            KeyCode::Char(c) => {
-                                let pos = cursor::position()?;
-                                let pad = self.ln_pad() + 1;
-                                let line = self.get_line_from_cursor();
-                                line.insert_char_at_cursor(pos.0 as usize - pad, c);
-                                term::save_cursor_pos();
-                                self.redraw()?;
-                                term::restore_cursor_pos();
-                                term::set_cursor_pos(pos.0 + 1, pos.1);
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-                _ => {
-                    match read()? {
-                        Event::Key(KeyEvent {
-                            code: _,
-                            modifiers: KeyModifiers::CONTROL,
-                        }) => {}
-                        Event::Key(KeyEvent { code, modifiers: _ }) => {
-                            match code {
-                                KeyCode::Char(c) => match c {
-                                    'h' => {
-                                        self.move_left();
-                                    }
-                                    'j' => {
-                                        self.move_down();
-                                    }
-                                    'k' => {
-                                        self.move_up();
-                                    }
-                                    'l' => {
-                                        self.move_right();
-                                    }
-                                    'i' => {
-                                        self.set_insert_mode();
-                                    }
-                                    'v' => {
-                                        self.set_visual_mode();
-                                    }
-                                    'a' => if let EditorMode::Insert = self.mode {},
-                                    'g' => match self.key_state {
-                                        KeyState::Inactive => {
-                                            self.update_key_state(KeyState::Waiting(c));
-                                        }
-                                        KeyState::Waiting(_) => {
-                                            let x = cursor::position()?.0;
-                                            self.update_v_draw_region(0, term::get_term_size().1);
-                                            self.set_draw_line(1);
-                                            self.redraw()?;
-                                            term::set_cursor_pos(x, 0);
-                                            self.update_key_state(KeyState::Inactive);
-                                        }
-                                        _ => {}
-                                    },
-                                    'G' => {
-                                        // TODO this will break on files with less lines than the
-                                        // terminal size.
-                                        let x = cursor::position().unwrap().0;
-                                        let ts = term::get_term_size();
-                                        let draw_region_start = self.lines.len() - ts.1;
-                                        self.update_v_draw_region(
-                                            draw_region_start,
-                                            self.lines.len(),
-                                        );
-                                        self.set_draw_line(ts.1 - 1);
-                                        self.redraw()?;
-                                        term::set_cursor_pos(x, (ts.1 - 2) as u16);
-                                        self.update_key_state(KeyState::Inactive);
-                                    }
-                                    'q' => {
-                                        if let KeyState::Waiting(cmd) = self.key_state {
-                                            if cmd == ':' {
-                                                //TODO prompt user before exiting
-                                                //TODO expect enter to follow like vim?
-                                                break;
-                                            }
-                                        }
-                                    }
-                                    ':' => match self.key_state {
-                                        KeyState::Inactive => {
-                                            self.update_key_state(KeyState::Waiting(c));
-                                        }
-                                        KeyState::Waiting(_) => {
-                                            self.update_key_state(KeyState::Inactive);
-                                        }
-                                        _ => {}
-                                    },
-                                    '0' => self.clamp_to_start_of_line(),
-                                    _ => {}
-                                },
-                                KeyCode::Enter => {}
-                                _ => {}
-                            };
-                        }
-                        Event::Mouse(_event) => {}
-                        Event::Resize(_width, _height) => {}
-                    }
-                }
-            }
+    /// Columns left of the text, taken by line numbers when they are on.
+    fn gutter_width(&self) -> usize {
+        if self.settings.number {
+            self.ln_pad() + 1
+        } else {
+            0
         }
-        Ok(())
     }
+
+    /// Adds a key to the pending Normal mode command and runs the command
+    /// once it is complete.
+    fn handle_normal_key(&mut self, key: KeyEvent) {
+        if self.pending_keys.is_empty() {
+            self.message.clear();
+            if let (EditorMode::Visual, KeyCode::Esc) = (&self.mode, key.code) {
+                self.set_normal_mode();
+                return;
+            }
+            if self.recording.is_some() && key.code == KeyCode::Char('q') {
+                self.stop_recording();
+                return;
+            }
+        }
+        self.pending_keys.push(key);
+        let visual = matches!(self.mode, EditorMode::Visual);
+        match normal::parse(&self.pending_keys, visual) {
+            normal::Parse::Pending => {}
+            normal::Parse::Done(cmd) => {
+                self.pending_keys.clear();
+                self.run_normal(cmd);
+            }
+            normal::Parse::Invalid => {
+                let keys = self.get_pending_keys_text();
+                self.pending_keys.clear();
+                self.failed = true;
+                self.set_message(format!("Unknown command: {}", keys));
+            }
+            normal::Parse::Cancelled => self.pending_keys.clear(),
+        }
+    }
+
+    fn run_normal(&mut self, cmd: NormalCommand) {
+        match cmd.motion() {
+            Some(Motion::Find(find)) => self.last_find = Some(find),
+            Some(Motion::Mark { name, .. }) => {
+                let open = matches!(cmd.kind, CommandKind::Motion(_));
+                if let Err(e) = self.prepare_mark(name, open) {
+                    self.failed = true;
+                    self.set_message(e);
+                    return;
+                }
+            }
+            Some(motion @ Motion::SearchNext { .. }) | Some(motion @ Motion::SearchWord { .. }) => {
+                if let Err(e) = self.prepare_search(motion, cmd.motion_count()) {
+                    self.failed = true;
+                    self.set_message(e);
+                    return;
+                }
+            }
+            _ => {}
+        }
+        let visual = self.visual_size();
+        match cmd.kind {
+            CommandKind::Motion(motion) => {
+                let from = (self.cursor.line, self.cursor.col);
+                if !self.run_motion(motion, cmd.count) {
+                    self.failed = true;
+                } else if motion.is_jump() {
+                    self.buffer.marks.push_jump(from);
+                }
+            }
+            CommandKind::Action(action) => {
+                let register = cmd.register.unwrap_or('"');
+                self.run_action(action, cmd.count, register)
+            }
+            CommandKind::Select(object) => self.select_object(object, cmd.count),
+            CommandKind::Operator(op, target) => {
+                let register = cmd.register.unwrap_or('"');
+                self.run_operator(op, target, cmd.motion_count(), register);
+            }
+        }
+        if cmd.is_change() {
+            self.last_change = Some(Change {
+                cmd,
+                visual,
+                inserted: String::new(),
+            });
+        }
+    }
+
+    /// `.`: runs the last change again at the cursor, with `count` in place
+    /// of its own. A change made in Visual mode works on a selection of the
+    /// same size, and one that started Insert mode types the same text.
+    /// Puts from `"1` to `"8` move on to the next numbered register, so
+    /// repeating them goes back through the delete history.
+    fn repeat_change(&mut self, count: Option<usize>) {
+        let Some(change) = self.last_change.clone() else {
+            return;
+        };
+        let mut cmd = match count {
+            Some(n) => change.cmd.with_count(n),
+            None => change.cmd,
+        };
+        if let (CommandKind::Action(Action::Put { .. }), Some(c @ '1'..='8')) =
+            (cmd.kind, cmd.register)
+        {
+            cmd.register = std::char::from_digit(c.to_digit(10).unwrap_or(0) + 1, 10);
+        }
+        if let Some(size) = change.visual {
+            self.select_size(size);
+        }
+        self.run_normal(cmd);
+        if let EditorMode::Insert = self.mode {
+            for (i, line) in change.inserted.split('\n').enumerate() {
+                if i > 0 {
+                    self.insert_newline();
+                }
+                self.insert_text(line);
+            }
+            self.set_normal_mode();
+        }
+    }
+
+    /// Size of the Visual selection, `None` outside Visual mode.
+    fn visual_size(&self) -> Option<VisualSize> {
+        if !matches!(self.mode, EditorMode::Visual) {
+            return None;
+        }
+        let (from, to) = self.visual_bounds();
+        let lines = to.0 - from.0;
+        let cols = match self.visual_kind {
+            VisualKind::Char if lines == 0 => to.1 - from.1,
+            VisualKind::Char => to.1,
+            VisualKind::Line => 0,
+            VisualKind::Block => match self.block_cols() {
+                (_, usize::MAX) => usize::MAX,
+                (left, right) => right - left,
+            },
+        };
+        Some(VisualSize {
+            kind: self.visual_kind,
+            lines,
+            cols,
+        })
+    }
+
+    /// Starts Visual mode at the cursor with a selection of `size`.
+    fn select_size(&mut self, size: VisualSize) {
+        let start = (self.cursor.line, self.cursor.col);
+        let line = (start.0 + size.lines).min(self.buffer.line_count() - 1);
+        self.set_visual_mode(size.kind);
+        match size.kind {
+            VisualKind::Char if size.lines == 0 => self.goto_pos(line, start.1 + size.cols),
+            VisualKind::Char => self.goto_pos(line, size.cols),
+            VisualKind::Line => self.goto_pos(line, start.1),
+            VisualKind::Block if size.cols == usize::MAX => {
+                self.goto_pos(line, start.1);
+                self.cursor.want_col = usize::MAX;
+            }
+            VisualKind::Block => {
+                let vcol = self.vcol_span(start).0 + size.cols.max(1) - 1;
+                let text = self.buffer.line(line);
+                let col = display::char_at_display(&text, vcol, self.settings.tabstop);
+                self.goto_pos(line, col);
+            }
+        }
+    }
+
+    fn run_action(&mut self, action: Action, count: Option<usize>, register: char) {
+        let n = count.unwrap_or(1);
+        match action {
+            Action::Insert => self.set_insert_mode(),
+            Action::Append => {
+                self.set_insert_mode();
+                let line = self.buffer.line(self.cursor.line);
+                let col = display::next_grapheme(&line, self.cursor.col);
+                self.goto_pos(self.cursor.line, col);
+            }
+            Action::InsertLineStart => self.insert_at_edge(false),
+            Action::AppendLineEnd => self.insert_at_edge(true),
+            Action::Undo => (0..n).for_each(|_| self.undo()),
+            Action::Redo => (0..n).for_each(|_| self.redo()),
+            Action::UndoTravel { forward } => (0..n).for_each(|_| self.undo_travel(forward)),
+            Action::Visual(kind) => self.toggle_visual_mode(kind),
+            Action::Reselect => self.reselect(),
+            Action::SwapEnds => self.swap_visual_ends(),
+            Action::Join => {
+                let (first, last) = match self.mode {
+                    EditorMode::Visual => {
+                        let (from, to) = self.visual_bounds();
+                        self.set_normal_mode();
+                        (from.0, to.0.max(from.0 + 1))
+                    }
+                    _ => (self.cursor.line, self.cursor.line + n.max(2) - 1),
+                };
+                self.join_lines(first, last);
+            }
+            Action::Put {
+                before,
+                cursor_after,
+            } => self.put(register, n, before, cursor_after),
+            Action::Repeat => self.repeat_change(count),
+            Action::Search { forward } => self.start_search(forward),
+            Action::Record(name) => self.recording = Some((name, Vec::new())),
+            Action::Play(name) => self.play_register(name, n),
+            Action::RepeatSubstitute { all: false } => self.run_ex("s"),
+            Action::RepeatSubstitute { all: true } => self.run_ex("%s//~/&"),
+            Action::SetMark(name) => self.set_mark(name, (self.cursor.line, self.cursor.col)),
+            Action::Jump { forward } => {
+                let from = (self.cursor.line, self.cursor.col);
+                match self.buffer.marks.step_jump(from, n, forward) {
+                    Some((line, col)) => self.goto_pos(line, col),
+                    None => self.failed = true,
+                }
+            }
+            Action::ChangeList { forward } => match self.buffer.marks.step_change(n, forward) {
+                Ok((line, col)) => self.goto_pos(line, col),
+                Err(e) => {
+                    self.failed = true;
+                    self.set_message(e);
+                }
+            },
+            Action::CommandLine if matches!(self.mode, EditorMode::Visual) => {
+                self.set_normal_mode();
+                self.set_command_mode();
+                self.command_line.text = "'<,'>".chars().collect();
+                self.command_line.cursor = self.command_line.text.len();
+            }
+            Action::CommandLine => self.set_command_mode(),
+            Action::ScrollLeft => self.scroll_cols(-(n as isize)),
+            Action::ScrollRight => self.scroll_cols(n as isize),
+            Action::ScrollCursorStart => self.scroll_cursor_to_edge(true),
+            Action::ScrollCursorEnd => self.scroll_cursor_to_edge(false),
+        }
+    }
+
+    /// Moves the cursor. Returns false when the motion couldn't move at all,
+    /// like `j` on the last line.
+    fn run_motion(&mut self, motion: Motion, count: Option<usize>) -> bool {
+        let Some((line, col)) = self.motion_target(motion, count) else {
+            return false;
+        };
+        if let Motion::Scroll(scroll) = motion {
+            let view = (self.v_draw_region.0, self.text_rows());
+            if let Some((top, _)) = motion::scroll(&self.buffer, view, line, scroll, count) {
+                let bottom = self.v_draw_region.1 - self.v_draw_region.0 + top;
+                self.update_v_draw_region(top, bottom);
+            }
+        }
+        let before = (self.cursor.line, self.cursor.col);
+        self.cursor.line = line;
+        self.cursor.col = col;
+        self.clamp_col();
+        match motion {
+            // vertical motions aim for the column the cursor came from
+            Motion::Up
+            | Motion::Down
+            | Motion::DisplayUp
+            | Motion::DisplayDown
+            | Motion::FirstLine
+            | Motion::LastLine
+            | Motion::Scroll(_) => {}
+            Motion::LineEnd => self.cursor.want_col = usize::MAX,
+            _ => self.cursor.want_col = self.cursor_screen_vcol(),
+        }
+        // `l` and `w` may reach past the end for operators but the cursor
+        // stops short
+        let may_stop = matches!(motion, Motion::Right | Motion::WordStart { .. });
+        !may_stop || (self.cursor.line, self.cursor.col) != before
+    }
+
+    /// Where `motion` moves the cursor to, without moving it.
+    fn motion_target(&self, motion: Motion, count: Option<usize>) -> Option<motion::Pos> {
+        let buffer = &self.buffer;
+        let pos = (self.cursor.line, self.cursor.col);
+        let n = count.unwrap_or(1);
+        match motion {
+            Motion::Left => motion::left(buffer, pos, n),
+            Motion::Right => motion::right(buffer, pos, n),
+            Motion::Up => motion::up(pos.0, n).map(|line| self.pos_at_want_col(line)),
+            Motion::Down => motion::down(buffer, pos.0, n).map(|line| self.pos_at_want_col(line)),
+            Motion::DisplayUp => self.display_row_target(n, false),
+            Motion::DisplayDown => self.display_row_target(n, true),
+            Motion::LineStart => Some((pos.0, 0)),
+            Motion::FirstNonBlank => Some(motion::first_non_blank(buffer, pos.0)),
+            Motion::FirstNonBlankDown => motion::first_non_blank_down(buffer, pos.0, n),
+            Motion::LineEnd => motion::line_end(buffer, pos, n),
+            Motion::WordStart { big } => motion::word_start(buffer, pos, n, big),
+            Motion::WordEnd { big } => motion::word_end(buffer, pos, n, big),
+            Motion::WordBack { big } => motion::word_back(buffer, pos, n, big),
+            Motion::WordEndBack { big } => motion::word_end_back(buffer, pos, n, big),
+            Motion::Find(find) => motion::find_char(buffer, pos, n, find, false),
+            Motion::RepeatFind { reverse } => {
+                let find = self.repeated_find(reverse)?;
+                motion::find_char(buffer, pos, n, find, true)
+            }
+            Motion::SentenceForward => motion::sentence_forward(buffer, pos, n),
+            Motion::SentenceBackward => motion::sentence_backward(buffer, pos, n),
+            Motion::ParagraphForward => motion::paragraph_forward(buffer, pos, n),
+            Motion::ParagraphBackward => motion::paragraph_backward(buffer, pos, n),
+            Motion::MatchPair => match count {
+                Some(n) => motion::percent_line(buffer, n),
+                None => motion::matching_pair(buffer, pos),
+            },
+            Motion::ScreenLine(which) => {
+                let so = self.scrolloff();
+                Some(motion::screen_line(
+                    buffer,
+                    self.v_draw_region,
+                    so,
+                    which,
+                    n,
+                ))
+            }
+            Motion::Scroll(scroll) => {
+                let view = (self.v_draw_region.0, self.text_rows());
+                let (_, line) = motion::scroll(buffer, view, pos.0, scroll, count)?;
+                Some(self.pos_at_want_col(line))
+            }
+            Motion::FirstLine => Some(self.pos_at_want_col(n.max(1) - 1)),
+            Motion::LastLine => {
+                let line = count.unwrap_or(buffer.line_count()).max(1) - 1;
+                Some(self.pos_at_want_col(line.min(buffer.line_count() - 1)))
+            }
+            Motion::SearchNext { reverse } => Some(self.search_target(pos, reverse, n).ok()?.0),
+            Motion::SearchWord { forward } => {
+                // `#` starts from the beginning of the word so it isn't found
+                let (_, start) = search::word_pattern(&buffer.line(pos.0), pos.1)?;
+                let from = if forward { pos } else { (pos.0, start) };
+                Some(self.search_target(from, false, n).ok()?.0)
+            }
+            Motion::Mark { name, exact } => {
+                let (line, col) = self.mark_pos(name)?;
+                let line = line.min(buffer.line_count() - 1);
+                if exact {
+                    Some((line, col))
+                } else {
+                    Some(motion::first_non_blank(buffer, line))
+                }
+            }
+        }
+    }
+
+    /// Position of mark `name` in the current buffer. `` ` `` is another
+    /// name for `'`, which is the start of the buffer before any jump.
+    pub fn mark_pos(&self, name: char) -> Option<motion::Pos> {
+        match name {
+            '\'' | '`' => Some(self.buffer.marks.get('\'').unwrap_or((0, 0))),
+            name => self.buffer.marks.get(name),
+        }
+    }
+
+    /// Sets mark `name` at `pos`. Setting `'` also adds to the jump list.
+    pub fn set_mark(&mut self, name: char, pos: motion::Pos) {
+        match name {
+            '\'' | '`' => self.buffer.marks.push_jump(pos),
+            name => {
+                self.file_marks.remove(&name);
+                self.buffer.marks.set(name, pos);
+            }
+        }
+    }
+
+    /// Checks mark `name` is set before a motion goes to it. With `open`, an
+    /// uppercase mark in another file opens that file.
+    fn prepare_mark(&mut self, name: char, open: bool) -> ExResult<()> {
+        if self.mark_pos(name).is_some() {
+            return Ok(());
+        }
+        let file = match self.file_marks.get(&name) {
+            Some((file, _)) if open => file.clone(),
+            _ => return Err("E20: Mark not set".into()),
+        };
+        if self.buffer.modified {
+            return Err("E37: No write since last change (add ! to override)".into());
+        }
+        self.read_from_file(file)
+    }
+
+    /// Checks a search motion can move before it runs, making the word under
+    /// the cursor the search pattern for `*` and `#`. Reports in the status
+    /// line when the search went round the end of the buffer.
+    fn prepare_search(&mut self, motion: Motion, count: Option<usize>) -> ExResult<()> {
+        let pos = (self.cursor.line, self.cursor.col);
+        let mut from = pos;
+        if let Motion::SearchWord { forward } = motion {
+            let line = self.buffer.line(pos.0);
+            let (pattern, start) =
+                search::word_pattern(&line, pos.1).ok_or("E348: No string under cursor")?;
+            if !forward {
+                from = (pos.0, start);
+            }
+            self.set_search(pattern, forward);
+        }
+        let reverse = matches!(motion, Motion::SearchNext { reverse: true });
+        let (_, wrapped) = self.search_target(from, reverse, count.unwrap_or(1))?;
+        self.search_highlight = true;
+        let search = self
+            .last_search
+            .as_ref()
+            .ok_or("E35: No previous regular expression")?;
+        let forward = search.forward != reverse;
+        let message = match (wrapped, forward) {
+            (true, true) => "search hit BOTTOM, continuing at TOP".to_string(),
+            (true, false) => "search hit TOP, continuing at BOTTOM".to_string(),
+            (false, true) => format!("/{}", search.pattern),
+            (false, false) => format!("?{}", search.pattern),
+        };
+        self.set_message(message);
+        Ok(())
+    }
+
+    /// Where repeating the last search `count` times from `from` leads, the
+    /// other way when `reverse`, and whether it went round the end.
+    fn search_target(
+        &self,
+        from: motion::Pos,
+        reverse: bool,
+        count: usize,
+    ) -> ExResult<(motion::Pos, bool)> {
+        let search = self
+            .last_search
+            .as_ref()
+            .ok_or("E35: No previous regular expression")?;
+        let regex = self.compile_search(&search.pattern)?;
+        let forward = search.forward != reverse;
+        let wrap = self.settings.wrapscan;
+        let (mut pos, mut wrapped) = (from, false);
+        for _ in 0..count.max(1) {
+            let (next, went_round) = search::find(&self.buffer, &regex, pos, forward, wrap)
+                .ok_or_else(|| match (wrap, forward) {
+                    (true, _) => format!("E486: Pattern not found: {}", search.pattern),
+                    (false, true) => {
+                        format!(
+                            "E385: Search hit BOTTOM without match for: {}",
+                            search.pattern
+                        )
+                    }
+                    (false, false) => {
+                        format!("E384: Search hit TOP without match for: {}", search.pattern)
+                    }
+                })?;
+            pos = next;
+            wrapped |= went_round;
+        }
+        Ok((pos, wrapped))
+    }
+
+    fn compile_search(&self, pattern: &str) -> ExResult<Regex> {
+        search::compile(pattern, self.settings.ignorecase, self.settings.smartcase)
+    }
+
+    /// Makes `pattern` the one `n` repeats and the `/` register holds.
+    fn set_search(&mut self, pattern: String, forward: bool) {
+        let register = Register::new(pattern.as_str(), RegisterKind::Char);
+        self.registers.set('/', register);
+        self.last_search = Some(Search { pattern, forward });
+    }
+
+    /// `/` and `?`: starts typing a search pattern on the bottom line.
+    fn start_search(&mut self, forward: bool) {
+        self.message.clear();
+        self.mode = EditorMode::Command;
+        self.command_line.start(if forward { '/' } else { '?' });
+        self.search_origin = Some(SearchOrigin {
+            cursor: self.cursor,
+            v_draw_region: self.v_draw_region,
+            h_draw_region: self.h_draw_region,
+        });
+    }
+
+    /// Puts the cursor and view back where they were when the search
+    /// prompt opened.
+    fn restore_search_origin(&mut self) {
+        if let Some(origin) = self.search_origin {
+            self.cursor = origin.cursor;
+            self.v_draw_region = origin.v_draw_region;
+            self.h_draw_region = origin.h_draw_region;
+        }
+    }
+
+    /// With `incsearch`, shows the first match of the pattern typed so far
+    /// by moving the cursor there from where the search started.
+    fn incremental_search(&mut self) {
+        self.restore_search_origin();
+        let text = self.command_line.text();
+        if !self.settings.incsearch || text.is_empty() {
+            return;
+        }
+        let Ok(regex) = self.compile_search(&text) else {
+            return;
+        };
+        let forward = self.command_line.prompt == '/';
+        let pos = (self.cursor.line, self.cursor.col);
+        if let Some(((line, col), _)) =
+            search::find(&self.buffer, &regex, pos, forward, self.settings.wrapscan)
+        {
+            self.cursor.line = line;
+            self.cursor.col = col;
+        }
+    }
+
+    /// Searches for `pattern` like `/` or `?` does, for the last pattern
+    /// again when it is empty.
+    pub fn search(&mut self, pattern: &str, forward: bool) {
+        if pattern.is_empty() {
+            match self.last_search.as_mut() {
+                Some(search) => search.forward = forward,
+                None => {
+                    self.set_message("E35: No previous regular expression");
+                    return;
+                }
+            }
+        } else {
+            self.set_search(pattern.to_string(), forward);
+        }
+        self.run_normal(NormalCommand {
+            register: None,
+            count: None,
+            kind: CommandKind::Motion(Motion::SearchNext { reverse: false }),
+        });
+    }
+
+    /// Regex whose matches are highlighted: the pattern being typed with
+    /// `incsearch`, or the last search with `hlsearch` until `:noh`.
+    fn highlight_regex(&self) -> Option<Regex> {
+        let pattern = match self.mode {
+            EditorMode::Command if self.command_line.prompt != ':' => {
+                if !self.settings.incsearch {
+                    return None;
+                }
+                self.command_line.text()
+            }
+            _ if self.settings.hlsearch && self.search_highlight => {
+                self.last_search.as_ref()?.pattern.clone()
+            }
+            _ => return None,
+        };
+        if pattern.is_empty() {
+            return None;
+        }
+        self.compile_search(&pattern).ok()
+    }
+
+    /// Runs `sub` on lines `first..=last`. With the `c` flag it stops at
+    /// the first match and the keys that follow answer the prompt.
+    pub fn substitute(&mut self, (first, last): (usize, usize), sub: Substitute) -> ExResult<()> {
+        let pattern = match sub.flags.ignore_case {
+            Some(true) => format!("\\c{}", sub.pattern),
+            Some(false) => format!("\\C{}", sub.pattern),
+            None => sub.pattern.clone(),
+        };
+        if sub.flags.confirm && self.in_global {
+            return Err("Cannot confirm substitutions under :global".into());
+        }
+        let regex = self.compile_search(&pattern)?;
+        self.set_search(sub.pattern.clone(), true);
+        self.search_highlight = true;
+        let run = substitute::Run::new(
+            regex,
+            sub.replacement.clone(),
+            sub.flags.global,
+            (first, last),
+        );
+        if !sub.flags.count_only {
+            self.buffer.begin_undo_group();
+        }
+        self.substitution = Some(Substitution {
+            run,
+            pattern: sub.pattern.clone(),
+            confirm: sub.flags.confirm,
+            count_only: sub.flags.count_only,
+            current: None,
+            asked: false,
+            cursor: self.cursor,
+        });
+        self.last_substitute = Some(sub);
+        self.continue_substitution()
+    }
+
+    /// Substitutes matches until one needs confirming or none are left.
+    fn continue_substitution(&mut self) -> ExResult<()> {
+        let Some(mut sub) = self.substitution.take() else {
+            return Ok(());
+        };
+        while let Some(m) = sub.run.find(&self.buffer) {
+            if sub.count_only {
+                sub.run.count(&m);
+            } else if sub.confirm {
+                self.update_line_num_buff(self.buffer.line_count());
+                self.goto_pos(m.line, m.range.start);
+                self.set_message(format!(
+                    "replace with {} (y/n/a/q/l)?",
+                    sub.run.replacement()
+                ));
+                sub.current = Some(m);
+                sub.asked = true;
+                self.substitution = Some(sub);
+                return Ok(());
+            } else {
+                sub.run.replace(&mut self.buffer, &m);
+            }
+        }
+        self.finish_substitution(sub)
+    }
+
+    /// Answers the confirm prompt of a `:s` with the `c` flag: `y` to
+    /// substitute, `n` to skip, `a` to substitute this and all the rest, `l`
+    /// to substitute this one and stop, and `q` or Esc to stop.
+    fn confirm_substitution(&mut self, code: KeyCode) -> ExResult<()> {
+        let Some(mut sub) = self.substitution.take() else {
+            return Ok(());
+        };
+        let Some(m) = sub.current.take() else {
+            return self.finish_substitution(sub);
+        };
+        match code {
+            KeyCode::Char('y') => sub.run.replace(&mut self.buffer, &m),
+            KeyCode::Char('n') => sub.run.skip(&m),
+            KeyCode::Char('a') => {
+                sub.run.replace(&mut self.buffer, &m);
+                sub.confirm = false;
+            }
+            KeyCode::Char('l') => {
+                sub.run.replace(&mut self.buffer, &m);
+                return self.finish_substitution(sub);
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return self.finish_substitution(sub),
+            _ => {
+                sub.current = Some(m);
+                self.substitution = Some(sub);
+                return Ok(());
+            }
+        }
+        self.substitution = Some(sub);
+        self.continue_substitution()
+    }
+
+    /// Closes the undo step of a `:s`, moves to the last line it changed and
+    /// reports how many substitutions it made. Finding nothing is an error,
+    /// except on lines `:global` runs it on.
+    fn finish_substitution(&mut self, sub: Substitution) -> ExResult<()> {
+        if !sub.count_only {
+            self.buffer.end_undo_group();
+        }
+        self.update_line_num_buff(self.buffer.line_count());
+        let run = &sub.run;
+        match run.last_changed {
+            Some(line) if !sub.count_only => {
+                let col = first_non_blank(&self.buffer.line(line));
+                self.goto_pos(line, col);
+            }
+            _ => self.cursor = sub.cursor,
+        }
+        let count = |n: usize, one: &str, many: &str| match n {
+            1 => format!("1 {}", one),
+            n => format!("{} {}", n, many),
+        };
+        let message = match (run.substitutions, sub.count_only) {
+            (0, _) if !sub.asked && !self.in_global => {
+                return Err(format!("E486: Pattern not found: {}", sub.pattern))
+            }
+            (0, _) => String::new(),
+            (n, true) => format!(
+                "{} on {}",
+                count(n, "match", "matches"),
+                count(run.lines, "line", "lines")
+            ),
+            (n, false) => format!(
+                "{} on {}",
+                count(n, "substitution", "substitutions"),
+                count(run.lines, "line", "lines")
+            ),
+        };
+        self.set_message(message);
+        Ok(())
+    }
+
+    /// How an operator treats `motion`, once `;`, `,` and a count for `%`
+    /// are known.
+    fn motion_kind(&self, motion: Motion, count: Option<usize>) -> MotionKind {
+        match motion {
+            Motion::RepeatFind { reverse } => match self.repeated_find(reverse) {
+                Some(find) => Motion::Find(find).kind(),
+                None => motion.kind(),
+            },
+            Motion::MatchPair if count.is_some() => MotionKind::Linewise,
+            _ => motion.kind(),
+        }
+    }
+
+    /// The search `;` repeats, turned around for `,`.
+    fn repeated_find(&self, reverse: bool) -> Option<motion::Find> {
+        let find = self.last_find?;
+        Some(if reverse { find.reversed() } else { find })
+    }
+
+    /// Column on `line` at the display column vertical moves aim for.
+    fn pos_at_want_col(&self, line: usize) -> motion::Pos {
+        let text = self.buffer.line(line);
+        let col = display::char_at_display(&text, self.cursor.want_col, self.settings.tabstop);
+        (line, col)
+    }
+
+    /// Runs operator `op` over the text `target` covers from the cursor.
+    fn run_operator(&mut self, op: Operator, target: Target, count: Option<usize>, register: char) {
+        let start = self.cursor;
+        let (kind, end) = match target {
+            Target::Lines => {
+                let n = count.unwrap_or(1);
+                let last = start
+                    .line
+                    .saturating_add(n - 1)
+                    .min(self.buffer.line_count() - 1);
+                (MotionKind::Linewise, (last, 0))
+            }
+            Target::Motion(motion, _) => match self.motion_target(motion, count) {
+                Some(end) => (self.motion_kind(motion, count), end),
+                None => {
+                    self.failed = true;
+                    return;
+                }
+            },
+            Target::Object(object, _) => {
+                let pos = (start.line, start.col);
+                match text_object::select(&self.buffer, pos, count.unwrap_or(1), object) {
+                    Some(Selection::Chars(range)) => {
+                        let from = motion::char_to_pos(&self.buffer, range.start);
+                        self.operate_chars(op, from, range, register);
+                    }
+                    Some(Selection::Lines(first, last)) => {
+                        self.operate_lines(op, first, last, register)
+                    }
+                    None => self.failed = true,
+                }
+                return;
+            }
+            Target::Selection => {
+                self.operate_selection(op, register);
+                return;
+            }
+        };
+        let from = (start.line, start.col).min(end);
+        let to = (start.line, start.col).max(end);
+        if kind == MotionKind::Linewise {
+            self.operate_lines(op, from.0, to.0, register);
+            return;
+        }
+        let mut range =
+            self.buffer.pos_to_char(from.0, from.1)..self.buffer.pos_to_char(to.0, to.1);
+        if kind == MotionKind::Inclusive {
+            let line = self.buffer.line(to.0);
+            range.end += display::next_grapheme(&line, to.1).min(line.chars().count()) - to.1;
+        } else if to.0 > from.0 && matches!(target, Target::Motion(Motion::WordStart { .. }, _)) {
+            // the last word moved over ends the text, not the next line
+            range.end = self.buffer.line_to_char(to.0) - 1;
+        } else if to.0 > from.0 && to.1 == 0 {
+            // an exclusive motion to the start of a line stops at the end of
+            // the one before, and takes whole lines when it began at the indent
+            let indent = first_non_blank(&self.buffer.line(from.0));
+            if from.1 <= indent {
+                self.operate_lines(op, from.0, to.0 - 1, register);
+                return;
+            }
+            range.end = self.buffer.line_to_char(to.0) - 1;
+        }
+        if op == Operator::Change && matches!(target, Target::Motion(Motion::WordStart { .. }, _)) {
+            // `cw` leaves the blanks after the word alone, like `ce`
+            let text = self.buffer.slice(range.clone());
+            if !text.starts_with(char::is_whitespace) {
+                let trimmed = text.trim_end_matches(char::is_whitespace);
+                range.end = range.start + trimmed.chars().count();
+            }
+        }
+        self.operate_chars(op, from, range, register);
+    }
+
+    /// Runs operator `op` over the Visual selection, leaving Visual mode.
+    fn operate_selection(&mut self, op: Operator, register: char) {
+        let (from, to) = self.visual_bounds();
+        let kind = self.visual_kind;
+        let cols = self.block_cols();
+        // after `$` the selection reaches past the last char
+        let past_end =
+            self.cursor.want_col == usize::MAX && to == (self.cursor.line, self.cursor.col);
+        self.set_normal_mode();
+        match kind {
+            VisualKind::Char => {
+                let start = self.buffer.pos_to_char(from.0, from.1);
+                let (line, len) = (self.buffer.line(to.0), self.buffer.line_len(to.0));
+                let mut end = self.buffer.pos_to_char(to.0, to.1);
+                end += display::next_grapheme(&line, to.1).min(len) - to.1.min(len);
+                if (past_end || to.1 >= len) && to.0 + 1 < self.buffer.line_count() {
+                    // the selection takes in the line break
+                    end += 1;
+                }
+                self.operate_chars(op, from, start..end, register);
+            }
+            VisualKind::Line => self.operate_lines(op, from.0, to.0, register),
+            VisualKind::Block => self.operate_block(op, from.0, to.0, cols, register),
+        }
+    }
+
+    /// Applies an operator to block columns `cols` of lines `first..=last`.
+    fn operate_block(
+        &mut self,
+        op: Operator,
+        first: usize,
+        last: usize,
+        cols: (usize, usize),
+        register: char,
+    ) {
+        match op {
+            Operator::Delete | Operator::Change | Operator::Yank => {
+                let text: Vec<String> = (first..=last)
+                    .map(|idx| self.buffer.slice(self.block_range(idx, cols)))
+                    .collect();
+                let text = Register::new(text.join("\n"), RegisterKind::Block);
+                self.store_register(op, register, text);
+                if op == Operator::Yank {
+                    let col = self.block_range(first, cols).start - self.buffer.line_to_char(first);
+                    self.goto_pos(first, col);
+                    return;
+                }
+                if op == Operator::Change {
+                    self.set_insert_mode();
+                } else {
+                    self.buffer.begin_undo_group();
+                }
+                for idx in (first..=last).rev() {
+                    let range = self.block_range(idx, cols);
+                    self.buffer.remove(range);
+                }
+                let col = self.block_range(first, cols).start - self.buffer.line_to_char(first);
+                if op == Operator::Change {
+                    self.block_insert = Some(BlockInsert {
+                        first,
+                        last,
+                        vcol: cols.0,
+                        col,
+                        len: self.buffer.line_len(first),
+                        append: false,
+                    });
+                } else {
+                    self.buffer.end_undo_group();
+                }
+                self.goto_pos(first, col);
+                self.after_edit();
+            }
+            Operator::ToggleCase | Operator::Lowercase | Operator::Uppercase => {
+                self.buffer.begin_undo_group();
+                for idx in first..=last {
+                    self.change_case(op, self.block_range(idx, cols));
+                }
+                self.buffer.end_undo_group();
+                let col = self.block_range(first, cols).start - self.buffer.line_to_char(first);
+                self.goto_pos(first, col);
+            }
+            Operator::ShiftRight | Operator::ShiftLeft | Operator::Format => {
+                self.operate_lines(op, first, last, register)
+            }
+        }
+    }
+
+    /// Replaces the chars in `range` with their case changed by `op`.
+    fn change_case(&mut self, op: Operator, range: std::ops::Range<usize>) {
+        let text = self.buffer.slice(range.clone());
+        let changed: String = text
+            .chars()
+            .flat_map(|c| match op {
+                Operator::Uppercase => c.to_uppercase().collect::<Vec<_>>(),
+                Operator::Lowercase => c.to_lowercase().collect(),
+                _ if c.is_uppercase() => c.to_lowercase().collect(),
+                _ => c.to_uppercase().collect(),
+            })
+            .collect();
+        if changed != text {
+            self.buffer.begin_undo_group();
+            self.buffer.remove(range.clone());
+            self.buffer.insert(range.start, &changed);
+            self.buffer.end_undo_group();
+        }
+    }
+
+    /// Joins lines `first..=last` into one (`J`). Each line break and the
+    /// indent after it become one space, left out before an empty line, after
+    /// trailing blanks and before a `)`.
+    fn join_lines(&mut self, first: usize, last: usize) -> bool {
+        let last = last.min(self.buffer.line_count() - 1);
+        if last <= first {
+            return false;
+        }
+        self.buffer.begin_undo_group();
+        let mut col = 0;
+        for _ in first..last {
+            let line = self.buffer.line(first);
+            let next = self.buffer.line(first + 1);
+            let indent = first_non_blank(&next);
+            let rest = next.chars().nth(indent);
+            let space = !(line.is_empty()
+                || line.ends_with([' ', '\t'])
+                || rest.is_none()
+                || rest == Some(')'));
+            col = line.chars().count();
+            let end = self.buffer.line_to_char(first) + col;
+            self.buffer.remove(end..end + 1 + indent);
+            if space {
+                self.buffer.insert(end, " ");
+            }
+        }
+        self.buffer.end_undo_group();
+        self.goto_pos(first, col);
+        self.after_edit();
+        true
+    }
+
+    /// Makes a text object around the cursor the Visual mode selection.
+    /// Linewise objects like `ip` make the selection linewise and the others
+    /// make it charwise.
+    fn select_object(&mut self, object: TextObject, count: Option<usize>) {
+        let pos = (self.cursor.line, self.cursor.col);
+        let (start, end) = match text_object::select(&self.buffer, pos, count.unwrap_or(1), object)
+        {
+            Some(Selection::Chars(range)) if !range.is_empty() => {
+                self.visual_kind = VisualKind::Char;
+                (
+                    motion::char_to_pos(&self.buffer, range.start),
+                    motion::char_to_pos(&self.buffer, range.end - 1),
+                )
+            }
+            Some(Selection::Lines(first, last)) => {
+                self.visual_kind = VisualKind::Line;
+                ((first, 0), (last, 0))
+            }
+            _ => return,
+        };
+        self.visual_start = start;
+        self.goto_pos(end.0, end.1);
+    }
+
+    /// Applies an operator to the chars in `range`, which starts at `from`.
+    fn operate_chars(
+        &mut self,
+        op: Operator,
+        from: (usize, usize),
+        range: std::ops::Range<usize>,
+        register: char,
+    ) {
+        let text = self.buffer.slice(range.clone());
+        match op {
+            Operator::Delete | Operator::Change => {
+                self.store_register(op, register, Register::new(text, RegisterKind::Char));
+                if op == Operator::Change {
+                    self.set_insert_mode();
+                }
+                self.buffer.remove(range);
+                self.cursor.line = from.0;
+                self.cursor.col = from.1;
+                self.clamp_col();
+                self.after_edit();
+            }
+            Operator::Yank => {
+                self.store_register(op, register, Register::new(text, RegisterKind::Char));
+                let last = self.buffer.char_to_pos(range.end.max(range.start + 1) - 1);
+                self.buffer.marks.set('[', from);
+                self.buffer.marks.set(']', last);
+                self.goto_pos(from.0, from.1);
+            }
+            Operator::ShiftRight | Operator::ShiftLeft | Operator::Format => {
+                let last = self.buffer.char_to_line(range.end.max(range.start + 1) - 1);
+                self.operate_lines(op, from.0, last, register);
+            }
+            Operator::ToggleCase | Operator::Lowercase | Operator::Uppercase => {
+                self.change_case(op, range);
+                self.goto_pos(from.0, from.1);
+            }
+        }
+    }
+
+    /// Applies an operator to whole lines `first..=last`.
+    fn operate_lines(&mut self, op: Operator, first: usize, last: usize, register: char) {
+        let mut text: Vec<String> = (first..=last).map(|i| self.buffer.line(i)).collect();
+        text.push(String::new());
+        let text = text.join("\n");
+        let count = last - first + 1;
+        match op {
+            Operator::Delete => {
+                self.store_register(op, register, Register::new(text, RegisterKind::Line));
+                self.delete_lines(first, last);
+            }
+            Operator::Change => {
+                self.store_register(op, register, Register::new(text, RegisterKind::Line));
+                self.set_insert_mode();
+                let start = self.buffer.line_to_char(first);
+                let end = self.buffer.line_to_char(last) + self.buffer.line_len(last);
+                self.buffer.remove(start..end);
+                self.goto_pos(first, 0);
+                self.after_edit();
+            }
+            Operator::Yank => {
+                self.store_register(op, register, Register::new(text, RegisterKind::Line));
+                self.buffer.marks.set('[', (first, 0));
+                self.buffer
+                    .marks
+                    .set(']', (last, self.buffer.line_len(last)));
+                let col = self.cursor.col;
+                self.goto_pos(first, col);
+            }
+            Operator::ToggleCase | Operator::Lowercase | Operator::Uppercase => {
+                let start = self.buffer.line_to_char(first);
+                let end = self.buffer.line_to_char(last) + self.buffer.line_len(last);
+                self.change_case(op, start..end);
+                let col = self.cursor.col;
+                self.goto_pos(first, col);
+            }
+            Operator::ShiftRight | Operator::ShiftLeft | Operator::Format => {
+                let sw = self.settings.indent_width();
+                let ts = self.settings.tabstop;
+                let base = (0..first)
+                    .rev()
+                    .map(|i| self.buffer.line(i))
+                    .find(|l| !l.trim().is_empty())
+                    .map(|l| display::display_col(&l, first_non_blank(&l), ts))
+                    .unwrap_or(0);
+                self.buffer.begin_undo_group();
+                for idx in first..=last {
+                    let line = self.buffer.line(idx);
+                    if line.trim().is_empty() && op != Operator::ShiftLeft {
+                        continue;
+                    }
+                    let blank = first_non_blank(&line);
+                    let width = display::display_col(&line, blank, ts);
+                    let new_width = match op {
+                        Operator::ShiftRight => width + sw,
+                        Operator::ShiftLeft => width.saturating_sub(sw),
+                        _ => base,
+                    };
+                    self.set_indent(idx, blank, new_width);
+                }
+                self.buffer.end_undo_group();
+                let col = first_non_blank(&self.buffer.line(first));
+                self.goto_pos(first, col);
+                if count > 2 {
+                    self.set_message(match op {
+                        Operator::ShiftRight => format!("{} lines >ed 1 time", count),
+                        Operator::ShiftLeft => format!("{} lines <ed 1 time", count),
+                        _ => format!("{} lines indented ", count),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Replaces the first `blank` chars of line `idx` with indent `width`
+    /// columns wide, made of tabs unless `expandtab` is set.
+    fn set_indent(&mut self, idx: usize, blank: usize, width: usize) {
+        let indent = if self.settings.expandtab {
+            " ".repeat(width)
+        } else {
+            let ts = self.settings.tabstop;
+            format!("{}{}", "\t".repeat(width / ts), " ".repeat(width % ts))
+        };
+        let line = self.buffer.line(idx);
+        let current: String = line.chars().take(blank).collect();
+        if current != indent {
+            let start = self.buffer.line_to_char(idx);
+            self.buffer.remove(start..start + blank);
+            self.buffer.insert(start, &indent);
+        }
+    }
+
+    /// Keeps the text an operator yanked or deleted in register `name`.
+    fn store_register(&mut self, op: Operator, name: char, register: Register) {
+        if op == Operator::Yank {
+            self.yank_into(name, register);
+        } else {
+            self.delete_into(name, register);
+        }
+    }
+
+    /// Keeps yanked text in register `name`, see [`Registers::yank`].
+    pub fn yank_into(&mut self, name: char, register: Register) {
+        self.registers.yank(name, register);
+        self.write_clipboard(name);
+    }
+
+    /// Keeps deleted text in register `name`, see [`Registers::delete`].
+    pub fn delete_into(&mut self, name: char, register: Register) {
+        let small = register.kind == RegisterKind::Char && !register.text.contains('\n');
+        self.registers.delete(name, register, small);
+        self.write_clipboard(name);
+    }
+
+    /// Copies register `+` or `*` to the system clipboard, through the
+    /// `clipcopy` helper when one is set and the terminal otherwise.
+    fn write_clipboard(&mut self, name: char) {
+        if name != '+' && name != '*' {
+            return;
+        }
+        let Some(text) = self.registers.get(name).map(|r| r.text.clone()) else {
+            return;
+        };
+        let result = if self.settings.clipcopy.is_empty() {
+            self.backend
+                .set_clipboard(&text, name == '*')
+                .map_err(|e| e.to_string())
+        } else {
+            clipboard::copy(&self.settings.clipcopy, &text)
+        };
+        if let Err(e) = result {
+            self.set_message(format!("Clipboard: {}", e));
+        }
+    }
+
+    /// Reads the system clipboard into register `+` or `*` through the
+    /// `clippaste` helper. Without one the register keeps what was last
+    /// copied, since terminals won't tell what is on their clipboard.
+    pub fn read_clipboard(&mut self, name: char) {
+        if (name != '+' && name != '*') || self.settings.clippaste.is_empty() {
+            return;
+        }
+        match clipboard::paste(&self.settings.clippaste) {
+            Ok(text) => {
+                let register = clipboard::register_for(text, self.registers.get(name));
+                self.registers.set(name, register);
+            }
+            Err(e) => self.set_message(format!("Clipboard: {}", e)),
+        }
+    }
+
+    /// Contents of register `name`, including `%`, the file name.
+    pub fn read_register(&self, name: char) -> Option<Register> {
+        match name {
+            '%' => self
+                .file_name
+                .as_ref()
+                .map(|name| Register::new(name.as_str(), RegisterKind::Char)),
+            name => self.registers.get(name).cloned(),
+        }
+    }
+
+    /// `p`, `P`, `gp` and `gP`: puts the text of register `name` `count`
+    /// times after or before the cursor, keeping the shape it was yanked
+    /// with. `cursor_after` leaves the cursor just after the new text.
+    fn put(&mut self, name: char, count: usize, before: bool, cursor_after: bool) {
+        self.read_clipboard(name);
+        let Some(register) = self.read_register(name) else {
+            self.set_message(format!("E353: Nothing in register {}", name));
+            return;
+        };
+        self.buffer.begin_undo_group();
+        let (line, col) = match register.kind {
+            RegisterKind::Char => {
+                self.put_chars(&register.text.repeat(count), before, cursor_after)
+            }
+            RegisterKind::Line => {
+                let at = self.cursor.line + !before as usize;
+                self.put_lines(at, &register.text.repeat(count), cursor_after)
+            }
+            RegisterKind::Block => self.put_block(&register.lines(), count, before, cursor_after),
+        };
+        self.buffer.end_undo_group();
+        self.update_line_num_buff(self.buffer.line_count());
+        self.goto_pos(line, col);
+    }
+
+    /// Puts the text of register `name` as whole lines, whatever its type,
+    /// so that its first line becomes line `at`. The cursor ends on the
+    /// last new line, as after `:put`.
+    pub fn put_as_lines(&mut self, name: char, at: usize) -> ExResult<()> {
+        self.read_clipboard(name);
+        let register = self
+            .read_register(name)
+            .ok_or_else(|| format!("E353: Nothing in register {}", name))?;
+        let lines = register.lines();
+        let text: String = lines.iter().map(|l| format!("{}\n", l)).collect();
+        self.put_lines(at, &text, false);
+        self.update_line_num_buff(self.buffer.line_count());
+        let (line, col) = motion::first_non_blank(&self.buffer, at + lines.len() - 1);
+        self.goto_pos(line, col);
+        Ok(())
+    }
+
+    /// Puts charwise text at or after the cursor. The cursor ends on the
+    /// last char put, or at the start when more than one line went in.
+    fn put_chars(&mut self, text: &str, before: bool, cursor_after: bool) -> motion::Pos {
+        let line = self.buffer.line(self.cursor.line);
+        let col = if before {
+            self.cursor.col
+        } else {
+            display::next_grapheme(&line, self.cursor.col).min(line.chars().count())
+        };
+        let at = self.buffer.pos_to_char(self.cursor.line, col);
+        self.buffer.insert(at, text);
+        let end = at + text.chars().count();
+        let cursor = if cursor_after {
+            end
+        } else if text.contains('\n') {
+            at
+        } else {
+            end.max(at + 1) - 1
+        };
+        motion::char_to_pos(&self.buffer, cursor)
+    }
+
+    /// Puts `text`, whole lines ending in a line break, so that its first
+    /// line becomes line `at`. The cursor ends on the first non-blank of the
+    /// new lines, or on the line after them.
+    fn put_lines(&mut self, at: usize, text: &str, cursor_after: bool) -> motion::Pos {
+        if at < self.buffer.line_count() {
+            self.buffer.insert(self.buffer.line_to_char(at), text);
+        } else {
+            let text = format!("\n{}", text.strip_suffix('\n').unwrap_or(text));
+            self.buffer.insert(self.buffer.len_chars(), &text);
+        }
+        if cursor_after {
+            (at + text.matches('\n').count(), 0)
+        } else {
+            motion::first_non_blank(&self.buffer, at)
+        }
+    }
+
+    /// Puts a block at the cursor's display column, or the one after it,
+    /// on the cursor line and those below, adding lines at the end of the
+    /// buffer when they run out. Each line of the block is padded to the
+    /// block's width unless nothing follows it.
+    fn put_block(
+        &mut self,
+        lines: &[&str],
+        count: usize,
+        before: bool,
+        cursor_after: bool,
+    ) -> motion::Pos {
+        let ts = self.settings.tabstop;
+        let width = lines
+            .iter()
+            .map(|l| display::display_width(l, ts))
+            .max()
+            .unwrap_or(0);
+        let span = self.vcol_span((self.cursor.line, self.cursor.col));
+        let vcol = if before || self.buffer.line_len(self.cursor.line) == 0 {
+            span.0
+        } else {
+            span.1
+        };
+        let first = self.cursor.line;
+        let (mut start, mut end) = (0, 0);
+        for (i, piece) in lines.iter().enumerate() {
+            let idx = first + i;
+            if idx == self.buffer.line_count() {
+                self.buffer.insert(self.buffer.len_chars(), "\n");
+            }
+            let padding = " ".repeat(width - display::display_width(piece, ts));
+            let padded = format!("{}{}", piece, padding);
+            let col = self.pad_to_vcol(idx, vcol);
+            let mut text = padded.repeat(count - 1);
+            text.push_str(piece);
+            if col < self.buffer.line_len(idx) {
+                text.push_str(&padding);
+            }
+            self.buffer.insert(self.buffer.pos_to_char(idx, col), &text);
+            if i == 0 {
+                start = col;
+            }
+            end = col + text.chars().count();
+        }
+        if cursor_after {
+            (first + lines.len() - 1, end)
+        } else {
+            (first, start)
+        }
+    }
+
+    /// `q` while recording: stores the keys typed since `q{register}`, in
+    /// key notation and without this `q`, in the register.
+    fn stop_recording(&mut self) {
+        let Some((name, mut typed)) = self.recording.take() else {
+            return;
+        };
+        typed.pop();
+        let text: String = typed.iter().map(keys::key_to_string).collect();
+        self.registers
+            .set(name, Register::new(text, RegisterKind::Char));
+    }
+
+    /// `@`: runs the keys in register `name` `count` times. Line breaks in
+    /// the register are Enter, and `@:` types the last command line again.
+    fn play_register(&mut self, name: char, count: usize) {
+        let name = match (name, self.last_played) {
+            ('@', Some(last)) => last,
+            ('@', None) => {
+                self.failed = true;
+                self.set_message("E748: No previously used register");
+                return;
+            }
+            (name, _) => name,
+        };
+        self.read_clipboard(name);
+        let Some(register) = self.read_register(name) else {
+            self.failed = true;
+            return;
+        };
+        let keys = match name {
+            ':' => {
+                let mut keys = keys::literal_keys(&format!(":{}", register.text));
+                keys.push(KeyEvent::from(KeyCode::Enter));
+                keys
+            }
+            _ => keys::parse_keys(&register.text.replace('\n', "<CR>")),
+        };
+        self.last_played = Some(name);
+        self.run_keys(&keys.repeat(count));
+    }
+
+    /// Handles `keys` as if typed, without drawing in between, until one of
+    /// them fails.
+    pub fn run_keys(&mut self, keys: &[KeyEvent]) {
+        if self.play_depth >= MAX_PLAY_DEPTH {
+            self.failed = true;
+            self.set_message("E169: Command too recursive");
+            return;
+        }
+        self.play_depth += 1;
+        self.failed = false;
+        for &key in keys {
+            self.handle_event(Event::Key(key));
+            if self.failed || self.quit {
+                break;
+            }
+        }
+        self.play_depth -= 1;
+    }
+
+    /// Runs `keys` in Normal mode as `:normal` does, at the start of each
+    /// line in `lines` or else once at the cursor. A command the keys leave
+    /// unfinished is cancelled as Esc would.
+    pub fn normal_keys(&mut self, lines: Option<(usize, usize)>, keys: &[KeyEvent]) {
+        let Some((first, last)) = lines else {
+            self.run_keys(keys);
+            self.cancel_pending();
+            return;
+        };
+        for line in first..=last {
+            if line >= self.buffer.line_count() {
+                break;
+            }
+            self.goto_pos(line, 0);
+            self.run_keys(keys);
+            self.cancel_pending();
+        }
+    }
+
+    /// Ends whatever command or mode keys were left in.
+    fn cancel_pending(&mut self) {
+        self.pending_keys.clear();
+        if self.substitution.is_some() {
+            let _ = self.confirm_substitution(KeyCode::Esc);
+        }
+        match self.mode {
+            EditorMode::Insert | EditorMode::Visual => self.set_normal_mode(),
+            EditorMode::Command => {
+                self.restore_search_origin();
+                self.leave_command_mode();
+            }
+            EditorMode::Normal => {}
+        }
+    }
+
+    pub fn handle_input(&mut self) -> Result<()> {
+        while let Some(event) = self.backend.read_event()? {
+            if !self.output.is_empty() {
+                if let Event::Key(_) = event {
+                    self.dismiss_output();
+                    self.render()?;
+                }
+                continue;
+            }
+            if let (Some((_, keys)), Event::Key(key)) = (self.recording.as_mut(), event) {
+                keys.push(key);
+            }
+            self.handle_event(event);
+            self.render()?;
+            if self.quit {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        if let Event::Resize(width, height) = event {
+            self.resize(width as usize, height as usize);
+            return;
+        }
+        if self.substitution.is_some() {
+            if let Event::Key(KeyEvent { code, modifiers: _ }) = event {
+                if let Err(e) = self.confirm_substitution(code) {
+                    self.set_message(e);
+                }
+            }
+            return;
+        }
+        match self.mode {
+            EditorMode::Command => {
+                if let Event::Key(key) = event {
+                    self.handle_command_key(key);
+                }
+            }
+            EditorMode::Insert => {
+                if let Event::Key(KeyEvent { code, modifiers }) = event {
+                    let modified = modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+                    match code {
+                        KeyCode::Char(_) if modified => {}
+                        KeyCode::Esc => {
+                            self.set_normal_mode();
+                        }
+                        KeyCode::Tab => {
+                            if self.settings.expandtab {
+                                let ts = self.settings.tabstop;
+                                let n = ts - self.cursor_vcol() % ts;
+                                self.insert_text(&" ".repeat(n));
+                            } else {
+                                self.insert_text("\t");
+                            }
+                        }
+                        KeyCode::Backspace => self.backspace(),
+                        KeyCode::Enter => self.insert_newline(),
+                        KeyCode::Char(c) => {
+                            self.insert_text(c.encode_utf8(&mut [0; 4]));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {
+                if let Event::Key(key) = event {
+                    self.handle_normal_key(key);
+                }
+            }
+        }
+    }
+}
+
+/// Column of the first non-blank char of `line`.
+pub fn first_non_blank(line: &str) -> usize {
+    line.chars().take_while(|c| c.is_whitespace()).count()
 }
@@ -0,0 +1,291 @@
+use std::convert::TryFrom;
+
+use crate::editor::Editor;
+
+/// Where an address starts counting from before any `+N`/`-N` offset.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddressBase {
+    /// An absolute, one based line number. `0` is allowed for commands like `:m0`.
+    Line(usize),
+    Current,
+    Last,
+    Mark(char),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Address {
+    pub base: AddressBase,
+    pub offset: isize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineRange {
+    pub start: Address,
+    pub end: Address,
+}
+
+/// A parsed `:` command line, e.g. `:3,10d`, `:.,$s/a/b/g` or `:w! out.txt`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExCommand {
+    pub range: Option<LineRange>,
+    pub name: String,
+    pub bang: bool,
+    pub args: String,
+}
+
+/// Result of running or resolving part of a `:` command. Errors are the
+/// message shown in the status line.
+pub type ExResult<T> = Result<T, String>;
+
+pub type CommandHandler = fn(&mut Editor, &ExCommand) -> ExResult<()>;
+
+#[derive(Debug)]
+struct CommandSpec {
+    short: &'static str,
+    full: &'static str,
+    handler: CommandHandler,
+}
+
+/// Maps command names to handlers. Names are registered vim style, `w[rite]`
+/// accepts anything from `w` up to `write`.
+#[derive(Debug, Default)]
+pub struct CommandRegistry {
+    commands: Vec<CommandSpec>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> CommandRegistry {
+        CommandRegistry {
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, name: &'static str, handler: CommandHandler) {
+        let (short, full) = match name.find('[') {
+            Some(i) => (&name[..i], name),
+            None => (name, name),
+        };
+        self.commands.push(CommandSpec {
+            short,
+            full,
+            handler,
+        });
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<CommandHandler> {
+        self.commands
+            .iter()
+            .find(|spec| {
+                let full: String = spec
+                    .full
+                    .chars()
+                    .filter(|c| *c != '[' && *c != ']')
+                    .collect();
+                name.starts_with(spec.short) && full.starts_with(name)
+            })
+            .map(|spec| spec.handler)
+    }
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    source: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Reads a decimal number, if one starts here. One too large for a
+    /// line number is an error.
+    fn number(&mut self) -> Result<Option<usize>, String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !c.is_ascii_digit() {
+                break;
+            }
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Ok(None);
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .map(Some)
+            .map_err(|_| "E16: Invalid range".to_string())
+    }
+
+    fn address(&mut self) -> Result<Option<Address>, String> {
+        let base = match self.peek() {
+            Some(c) if c.is_ascii_digit() => self.number()?.map(AddressBase::Line),
+            Some('.') => {
+                self.pos += 1;
+                Some(AddressBase::Current)
+            }
+            Some('$') => {
+                self.pos += 1;
+                Some(AddressBase::Last)
+            }
+            Some('\'') => {
+                self.pos += 1;
+                match self.next() {
+                    Some(m) => Some(AddressBase::Mark(m)),
+                    None => return Err("E20: Mark not set".into()),
+                }
+            }
+            _ => None,
+        };
+        let mut offset = 0isize;
+        let mut has_offset = false;
+        while let Some(c) = self.peek() {
+            let sign = match c {
+                '+' => 1,
+                '-' => -1,
+                _ => break,
+            };
+            self.pos += 1;
+            has_offset = true;
+            offset = isize::try_from(self.number()?.unwrap_or(1))
+                .ok()
+                .and_then(|n| n.checked_mul(sign))
+                .and_then(|n| offset.checked_add(n))
+                .ok_or_else(|| "E16: Invalid range".to_string())?;
+        }
+        match base {
+            Some(base) => Ok(Some(Address { base, offset })),
+            None if has_offset => Ok(Some(Address {
+                base: AddressBase::Current,
+                offset,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    fn range(&mut self) -> Result<Option<LineRange>, String> {
+        self.skip_whitespace();
+        if self.peek() == Some('%') {
+            self.pos += 1;
+            return Ok(Some(LineRange {
+                start: Address {
+                    base: AddressBase::Line(1),
+                    offset: 0,
+                },
+                end: Address {
+                    base: AddressBase::Last,
+                    offset: 0,
+                },
+            }));
+        }
+        let start = self.address()?;
+        self.skip_whitespace();
+        if self.peek() == Some(',') || self.peek() == Some(';') {
+            self.pos += 1;
+            self.skip_whitespace();
+            let current = Address {
+                base: AddressBase::Current,
+                offset: 0,
+            };
+            let start = start.unwrap_or_else(|| current.clone());
+            let end = self.address()?.unwrap_or(current);
+            return Ok(Some(LineRange { start, end }));
+        }
+        Ok(start.map(|a| LineRange {
+            start: a.clone(),
+            end: a,
+        }))
+    }
+
+    fn name(&mut self) -> String {
+        let start = self.pos;
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() => {
+                while let Some(c) = self.peek() {
+                    if !c.is_ascii_alphabetic() {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+            }
+            Some('&') => {
+                self.pos += 1;
+                if self.peek() == Some('&') {
+                    self.pos += 1;
+                }
+            }
+            Some(c) if "<>=@!*".contains(c) => self.pos += 1,
+            _ => {}
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+}
+
+/// Parses a lone address, like the destination of `:m`.
+pub fn parse_address(text: &str) -> Result<Address, String> {
+    let mut parser = Parser {
+        chars: text.trim().chars().collect(),
+        pos: 0,
+        source: text,
+    };
+    let address = parser.address()?.ok_or("E14: Invalid address")?;
+    if parser.peek().is_some() {
+        return Err(format!(
+            "E488: Trailing characters: {}",
+            parser.source.trim()
+        ));
+    }
+    Ok(address)
+}
+
+/// Parses a command line (without the leading `:`) into an [`ExCommand`].
+pub fn parse(line: &str) -> Result<ExCommand, String> {
+    let mut parser = Parser {
+        chars: line.chars().collect(),
+        pos: 0,
+        source: line,
+    };
+    while parser.peek() == Some(':') || parser.peek().is_some_and(char::is_whitespace) {
+        parser.pos += 1;
+    }
+    let range = parser.range()?;
+    parser.skip_whitespace();
+    let name = parser.name();
+    if name.is_empty() && parser.peek().is_some() {
+        return Err(format!(
+            "E492: Not an editor command: {}",
+            parser.source.trim()
+        ));
+    }
+    let bang = name != "!" && parser.peek() == Some('!');
+    if bang {
+        parser.pos += 1;
+    }
+    parser.skip_whitespace();
+    let args: String = parser.chars[parser.pos..].iter().collect();
+    Ok(ExCommand {
+        range,
+        name,
+        bang,
+        args,
+    })
+}
@@ -1,13 +1,58 @@
-use std::io::{self, BufRead};
-use std::{fs::File, path::Path};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
 
-pub fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
+use crate::buffer::Buffer;
+
+pub fn read_buffer<P>(filename: P) -> io::Result<Buffer>
+where
+    P: AsRef<Path>,
+{
+    let file = File::open(filename)?;
+    Buffer::from_reader(io::BufReader::new(file))
+}
+
+/// Writes `buffer` to `filename` using the line ending and trailing newline
+/// the buffer was read with. Returns the number of bytes written. The text
+/// goes to a temporary file next to `filename` first, which then replaces
+/// it, so a failed write leaves the old file as it was.
+pub fn write_buffer<P>(filename: P, buffer: &Buffer) -> io::Result<usize>
 where
     P: AsRef<Path>,
 {
-    let file = File::open(filename);
-    match file {
-        Err(e) => panic!("{}", e),
-        Ok(f) => Ok(io::BufReader::new(f).lines()),
+    let path = filename.as_ref();
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file name"))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(temp_name);
+    let written = write_to(&temp, path, buffer).and_then(|_| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written.map(|_| buffer.len_bytes())
+}
+
+/// Writes `buffer` to the new file `temp`, with the permissions of `target`
+/// when it exists, and syncs it to disk.
+fn write_to(temp: &Path, target: &Path, buffer: &Buffer) -> io::Result<()> {
+    let file = File::create(temp)?;
+    if let Ok(metadata) = fs::metadata(target) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    let mut out = io::BufWriter::new(file);
+    let ending = buffer.line_ending.as_str();
+    let last = buffer.line_count() - 1;
+    for idx in 0..=last {
+        for chunk in buffer.line_chunks(idx) {
+            out.write_all(chunk.as_bytes())?;
+        }
+        if idx < last || buffer.trailing_newline {
+            out.write_all(ending.as_bytes())?;
+        }
     }
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()
 }
@@ -0,0 +1,91 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Parses vim style key notation such as `ihello<Esc>` or `<C-r>` into key
+/// events. Unknown `<...>` names are taken literally.
+pub fn parse_keys(s: &str) -> Vec<KeyEvent> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '<' {
+            if let Some(len) = chars[i..].iter().position(|c| *c == '>') {
+                let name: String = chars[i + 1..i + len].iter().collect();
+                if let Some(key) = named_key(&name) {
+                    keys.push(key);
+                    i += len + 1;
+                    continue;
+                }
+            }
+        }
+        keys.push(char_key(chars[i]));
+        i += 1;
+    }
+    keys
+}
+
+/// Key events typing `s` a char at a time, with no `<...>` names.
+pub fn literal_keys(s: &str) -> Vec<KeyEvent> {
+    s.chars().map(char_key).collect()
+}
+
+fn char_key(c: char) -> KeyEvent {
+    let modifiers = if c.is_ascii_uppercase() {
+        KeyModifiers::SHIFT
+    } else {
+        KeyModifiers::NONE
+    };
+    KeyEvent::new(KeyCode::Char(c), modifiers)
+}
+
+fn named_key(name: &str) -> Option<KeyEvent> {
+    let lower = name.to_ascii_lowercase();
+    if let Some(rest) = lower.strip_prefix("c-") {
+        let c = name[2..].chars().next()?;
+        if rest.chars().count() == 1 {
+            return Some(KeyEvent::new(
+                KeyCode::Char(c.to_ascii_lowercase()),
+                KeyModifiers::CONTROL,
+            ));
+        }
+    }
+    let code = match lower.as_str() {
+        "esc" => KeyCode::Esc,
+        "cr" | "enter" | "return" => KeyCode::Enter,
+        "bs" | "backspace" => KeyCode::Backspace,
+        "del" | "delete" => KeyCode::Delete,
+        "tab" => KeyCode::Tab,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "bar" => KeyCode::Char('|'),
+        _ => return None,
+    };
+    Some(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+/// Inverse of [`parse_keys`] for a single key.
+pub fn key_to_string(key: &KeyEvent) -> String {
+    let name = match key.code {
+        KeyCode::Char('<') => "lt".to_string(),
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => format!("C-{}", c),
+        KeyCode::Char(c) => return c.to_string(),
+        KeyCode::Esc => "Esc".into(),
+        KeyCode::Enter => "CR".into(),
+        KeyCode::Backspace => "BS".into(),
+        KeyCode::Delete => "Del".into(),
+        KeyCode::Tab => "Tab".into(),
+        KeyCode::Left => "Left".into(),
+        KeyCode::Right => "Right".into(),
+        KeyCode::Up => "Up".into(),
+        KeyCode::Down => "Down".into(),
+        KeyCode::Home => "Home".into(),
+        KeyCode::End => "End".into(),
+        _ => return String::new(),
+    };
+    format!("<{}>", name)
+}
@@ -0,0 +1,21 @@
+pub mod backend;
+pub mod buffer;
+pub mod clipboard;
+pub mod command_line;
+pub mod commands;
+pub mod display;
+pub mod editor;
+pub mod ex;
+pub mod fred_file;
+pub mod keys;
+pub mod mark;
+pub mod motion;
+pub mod normal;
+pub mod register;
+pub mod screen;
+pub mod search;
+pub mod settings;
+pub mod substitute;
+pub mod term;
+pub mod text_object;
+pub mod undo;
@@ -1,24 +1,9 @@
-use confy;
 use crossterm::Result;
-use serde::{Deserialize, Serialize};
 use std::panic;
-use std::path::Path;
 
-mod editor;
-use editor::Editor;
-mod fred_file;
-mod term;
-
-#[derive(Debug, Serialize, Deserialize)]
-struct FredConfig {
-    tab_spaces: u16,
-}
-
-impl ::std::default::Default for FredConfig {
-    fn default() -> Self {
-        Self { tab_spaces: 4 }
-    }
-}
+use fred::editor::Editor;
+use fred::settings::Settings;
+use fred::term::{self, CrosstermBackend};
 
 fn main() -> Result<()> {
     panic::set_hook(Box::new(|i| {
@@ -26,23 +11,19 @@ fn main() -> Result<()> {
         println!("Unrecoverable error");
         dbg!("{:?}", i);
     }));
-    let cfg: FredConfig = confy::load("fred").unwrap();
-    dbg!(cfg);
+    let (settings, config_error) = Settings::load();
     term::init_term()?;
-    let mut editor = Editor::new();
-    let args: Vec<String> = std::env::args().collect();
-    let mut file_found = false;
-    let mut file_name = String::new();
-    if args.len() > 1 {
-        file_name = args[1].to_string();
-        file_found = Path::new(&file_name[..]).exists();
+    let mut editor = Editor::new(Box::new(CrosstermBackend::new()), settings);
+    if let Some(e) = config_error {
+        editor.set_message(e);
     }
-
-    if file_found {
-        editor.read_from_file(file_name);
+    if let Some(file_name) = std::env::args().nth(1) {
+        if let Err(e) = editor.read_from_file(file_name) {
+            editor.set_message(e);
+        }
     }
 
-    editor.draw_editor(false)?;
+    editor.render()?;
     editor.handle_input()?;
 
     term::die()
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::motion::Pos;
+
+/// Most entries the jump list and the change list keep.
+const LIST_SIZE: usize = 100;
+
+/// What an edit does to line numbers, worked out before it is applied so
+/// that positions can be kept on the same text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LineShift {
+    /// No line breaks were added or removed.
+    None,
+    /// `count` lines were added before line `before`, which moves down.
+    Insert { before: usize, count: usize },
+    /// Whole `lines` were deleted and the ones after them move up.
+    Delete { lines: Range<usize> },
+    /// Line `joined` was joined onto line `line` at column `col`, without
+    /// its first `skip` chars, and the lines between were deleted.
+    Join {
+        line: usize,
+        col: usize,
+        joined: usize,
+        skip: usize,
+    },
+}
+
+impl LineShift {
+    /// Where line `line` ends up, `None` when it is deleted. A line joined
+    /// onto another counts as deleted.
+    pub fn line(&self, line: usize) -> Option<usize> {
+        match *self {
+            LineShift::None => Some(line),
+            LineShift::Insert { before, count } if line >= before => Some(line + count),
+            LineShift::Insert { .. } => Some(line),
+            LineShift::Delete { ref lines } if lines.contains(&line) => None,
+            LineShift::Delete { ref lines } if line >= lines.end => Some(line - lines.len()),
+            LineShift::Delete { .. } => Some(line),
+            LineShift::Join {
+                line: to, joined, ..
+            } if line > to && line <= joined => None,
+            LineShift::Join {
+                line: to, joined, ..
+            } if line > joined => Some(line - (joined - to)),
+            LineShift::Join { .. } => Some(line),
+        }
+    }
+
+    /// Where position `pos` ends up, `None` when its line is deleted. On a
+    /// joined line it follows its text onto the line it was joined to.
+    pub fn pos(&self, (line, col): Pos) -> Option<Pos> {
+        match *self {
+            LineShift::Join {
+                line: to,
+                col: at,
+                joined,
+                skip,
+            } if line == joined => Some((to, at + col.saturating_sub(skip))),
+            _ => Some((self.line(line)?, col)),
+        }
+    }
+}
+
+/// Positions that stay on the same text as the buffer changes: marks set
+/// with `m` and the automatic ones like `.` and `[`, the jump list `Ctrl-o`
+/// and `Ctrl-i` go through and the change list of `g;` and `g,`.
+#[derive(Debug, Default)]
+pub struct Marks {
+    named: HashMap<char, Pos>,
+    jumps: Vec<Pos>,
+    /// Index into `jumps` of the entry last jumped to, `jumps.len()` when
+    /// none has been since the last jump.
+    jump: usize,
+    changes: Vec<Pos>,
+    /// Index into `changes`, like `jump`.
+    change: usize,
+}
+
+impl Marks {
+    pub fn new() -> Marks {
+        Marks::default()
+    }
+
+    pub fn get(&self, name: char) -> Option<Pos> {
+        self.named.get(&name).copied()
+    }
+
+    pub fn set(&mut self, name: char, pos: Pos) {
+        self.named.insert(name, pos);
+    }
+
+    pub fn remove(&mut self, name: char) -> Option<Pos> {
+        self.named.remove(&name)
+    }
+
+    /// Set marks, sorted by name.
+    pub fn list(&self) -> Vec<(char, Pos)> {
+        let mut marks: Vec<(char, Pos)> = self.named.iter().map(|(&c, &p)| (c, p)).collect();
+        marks.sort_unstable();
+        marks
+    }
+
+    /// Remembers `pos` as the place a jump left, in the jump list and as
+    /// the `'` mark. Older entries on the same line are dropped.
+    pub fn push_jump(&mut self, pos: Pos) {
+        self.named.insert('\'', pos);
+        push_entry(&mut self.jumps, pos, |old| old.0 == pos.0);
+        self.jump = self.jumps.len();
+    }
+
+    /// Entry `count` steps back through the jump list, or forward when
+    /// `forward`. Going back from the newest end remembers `from` first, so
+    /// `Ctrl-i` can return to it.
+    pub fn step_jump(&mut self, from: Pos, count: usize, forward: bool) -> Option<Pos> {
+        let target = if forward {
+            Some(self.jump.saturating_add(count)).filter(|&i| i < self.jumps.len())?
+        } else {
+            if self.jump >= self.jumps.len() {
+                self.push_jump(from);
+                self.jump = self.jumps.len() - 1;
+            }
+            self.jump.checked_sub(count)?
+        };
+        self.jump = target;
+        Some(self.jumps[target])
+    }
+
+    /// Notes a change at `pos` as the `.` mark and in the change list, where
+    /// it replaces the newest entry when that is on the same line.
+    pub fn push_change(&mut self, pos: Pos) {
+        self.named.insert('.', pos);
+        push_entry(&mut self.changes, pos, |_| false);
+        self.change = self.changes.len();
+    }
+
+    /// Entry `count` steps back through the change list, or forward when
+    /// `forward`.
+    pub fn step_change(&mut self, count: usize, forward: bool) -> Result<Pos, String> {
+        if self.changes.is_empty() {
+            return Err("E664: Changelist is empty".into());
+        }
+        let target = if forward {
+            Some(self.change.saturating_add(count)).filter(|&i| i < self.changes.len())
+        } else {
+            self.change.checked_sub(count)
+        };
+        let target = match target {
+            Some(i) => i,
+            None if forward => return Err("E663: At end of changelist".into()),
+            None => return Err("E662: At start of changelist".into()),
+        };
+        self.change = target;
+        Ok(self.changes[target])
+    }
+
+    /// Moves every position along with an edit, dropping those whose line
+    /// was deleted.
+    pub fn shift(&mut self, shift: &LineShift) {
+        if *shift == LineShift::None {
+            return;
+        }
+        self.named = self
+            .named
+            .drain()
+            .filter_map(|(name, pos)| Some((name, shift.pos(pos)?)))
+            .collect();
+        shift_list(&mut self.jumps, &mut self.jump, shift);
+        shift_list(&mut self.changes, &mut self.change, shift);
+    }
+}
+
+/// Adds `pos` to the end of a jump or change list, dropping the entries
+/// `replaces` picks and any newest one on the same line, and the oldest one
+/// when the list is full.
+fn push_entry(list: &mut Vec<Pos>, pos: Pos, replaces: impl Fn(&Pos) -> bool) {
+    list.retain(|old| !replaces(old));
+    if list.last().is_some_and(|last| last.0 == pos.0) {
+        list.pop();
+    }
+    list.push(pos);
+    if list.len() > LIST_SIZE {
+        list.remove(0);
+    }
+}
+
+fn shift_list(list: &mut Vec<Pos>, idx: &mut usize, shift: &LineShift) {
+    let before = *idx;
+    let mut kept = Vec::with_capacity(list.len());
+    for (i, &pos) in list.iter().enumerate() {
+        match shift.pos(pos) {
+            Some(pos) => kept.push(pos),
+            None if i < before => *idx -= 1,
+            None => {}
+        }
+    }
+    *list = kept;
+    *idx = (*idx).min(list.len());
+}
@@ -0,0 +1,498 @@
+use unicode_width::UnicodeWidthChar;
+
+use crate::buffer::Buffer;
+use crate::display;
+
+/// Position in the buffer as (line, char column).
+pub type Pos = (usize, usize);
+
+/// Word class of a char: blanks, punctuation or keyword chars. Combining
+/// marks belong with the letters they modify. For a WORD every non-blank
+/// char is in the same class.
+fn class(c: char, big: bool) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if big || c.is_alphanumeric() || c == '_' || c.width() == Some(0) {
+        2
+    } else {
+        1
+    }
+}
+
+fn line_chars(buffer: &Buffer, line: usize) -> Vec<char> {
+    buffer.line(line).chars().collect()
+}
+
+/// Position of char offset `idx`.
+pub fn char_to_pos(buffer: &Buffer, idx: usize) -> Pos {
+    let line = buffer.char_to_line(idx);
+    (line, idx - buffer.line_to_char(line))
+}
+
+/// Applies `step` up to `count` times. Fails only when the first step
+/// can't move.
+fn repeat(pos: Pos, count: usize, step: impl Fn(Pos) -> Option<Pos>) -> Option<Pos> {
+    let mut pos = step(pos)?;
+    for _ in 1..count {
+        match step(pos) {
+            Some(next) => pos = next,
+            None => break,
+        }
+    }
+    Some(pos)
+}
+
+/// `count` graphemes left (`h`), stopping at the start of the line.
+pub fn left(buffer: &Buffer, (line, col): Pos, count: usize) -> Option<Pos> {
+    let text = buffer.line(line);
+    repeat((line, col), count, |(line, col)| {
+        (col > 0).then(|| (line, display::prev_grapheme(&text, col)))
+    })
+}
+
+/// `count` graphemes right (`l`). This may end one past the last char, so
+/// an operator takes in the end of the line; the cursor itself stops on it.
+pub fn right(buffer: &Buffer, (line, col): Pos, count: usize) -> Option<Pos> {
+    let text = buffer.line(line);
+    let len = buffer.line_len(line);
+    repeat((line, col), count, |(line, col)| {
+        (col < len).then(|| (line, display::next_grapheme(&text, col).min(len)))
+    })
+}
+
+/// Line `count` lines up (`k`), or the first line when there are fewer.
+pub fn up(line: usize, count: usize) -> Option<usize> {
+    (line > 0).then(|| line.saturating_sub(count))
+}
+
+/// Line `count` lines down (`j`), or the last line when there are fewer.
+pub fn down(buffer: &Buffer, line: usize, count: usize) -> Option<usize> {
+    let last = buffer.line_count() - 1;
+    (line < last).then(|| line.saturating_add(count).min(last))
+}
+
+/// First non-blank char of `line` (`^`), or its end when it is all blank.
+pub fn first_non_blank(buffer: &Buffer, line: usize) -> Pos {
+    (line, crate::editor::first_non_blank(&buffer.line(line)))
+}
+
+/// First non-blank char `count - 1` lines down (`_`).
+pub fn first_non_blank_down(buffer: &Buffer, line: usize, count: usize) -> Option<Pos> {
+    let line = line.saturating_add(count.max(1) - 1);
+    (line < buffer.line_count()).then(|| first_non_blank(buffer, line))
+}
+
+/// Last char of the line `count - 1` lines down (`$`).
+pub fn line_end(buffer: &Buffer, (line, _): Pos, count: usize) -> Option<Pos> {
+    let line = line.saturating_add(count.max(1) - 1);
+    if line >= buffer.line_count() {
+        return None;
+    }
+    let len = buffer.line_len(line);
+    Some((line, display::prev_grapheme(&buffer.line(line), len)))
+}
+
+/// Start of the `count`th next word (`w`, `W`). An empty line counts as a
+/// word. In the last word, even on its last char, this is one past the end
+/// of the buffer, so that an operator still takes in the rest of the word.
+pub fn word_start(buffer: &Buffer, pos: Pos, count: usize, big: bool) -> Option<Pos> {
+    repeat(pos, count, |pos| next_word_start(buffer, pos, big))
+}
+
+fn next_word_start(buffer: &Buffer, (mut line, mut col): Pos, big: bool) -> Option<Pos> {
+    let last_line = buffer.line_count() - 1;
+    let mut chars = line_chars(buffer, line);
+    if line == last_line && col >= chars.len() {
+        return None;
+    }
+    if let Some(&c) = chars.get(col) {
+        let start = class(c, big);
+        if start != 0 {
+            while col < chars.len() && class(chars[col], big) == start {
+                col += 1;
+            }
+        }
+    }
+    loop {
+        while col < chars.len() && class(chars[col], big) == 0 {
+            col += 1;
+        }
+        if col < chars.len() {
+            return Some((line, col));
+        }
+        if line == last_line {
+            // past the end, so an operator takes in the last word
+            return Some((line, chars.len()));
+        }
+        line += 1;
+        col = 0;
+        chars = line_chars(buffer, line);
+        if chars.is_empty() {
+            return Some((line, 0));
+        }
+    }
+}
+
+/// End of the `count`th next word (`e`, `E`).
+pub fn word_end(buffer: &Buffer, pos: Pos, count: usize, big: bool) -> Option<Pos> {
+    repeat(pos, count, |pos| next_word_end(buffer, pos, big))
+}
+
+fn next_word_end(buffer: &Buffer, (mut line, mut col): Pos, big: bool) -> Option<Pos> {
+    let mut chars = line_chars(buffer, line);
+    // always move at least one char, then skip blanks and empty lines
+    col += 1;
+    loop {
+        while col < chars.len() && class(chars[col], big) == 0 {
+            col += 1;
+        }
+        if col < chars.len() {
+            break;
+        }
+        if line + 1 >= buffer.line_count() {
+            return None;
+        }
+        line += 1;
+        col = 0;
+        chars = line_chars(buffer, line);
+    }
+    let cls = class(chars[col], big);
+    while col + 1 < chars.len() && class(chars[col + 1], big) == cls {
+        col += 1;
+    }
+    Some((line, col))
+}
+
+/// Start of the `count`th previous word (`b`, `B`).
+pub fn word_back(buffer: &Buffer, pos: Pos, count: usize, big: bool) -> Option<Pos> {
+    repeat(pos, count, |pos| prev_word_start(buffer, pos, big))
+}
+
+fn prev_word_start(buffer: &Buffer, (mut line, mut col): Pos, big: bool) -> Option<Pos> {
+    let start = (line, col);
+    let mut chars = line_chars(buffer, line);
+    // step back over blanks and line breaks, an empty line is a word
+    loop {
+        if col == 0 {
+            if line == 0 {
+                return (start != (0, 0)).then_some((0, 0));
+            }
+            line -= 1;
+            chars = line_chars(buffer, line);
+            col = chars.len();
+            if chars.is_empty() {
+                return Some((line, 0));
+            }
+            continue;
+        }
+        col -= 1;
+        if class(chars[col], big) != 0 {
+            break;
+        }
+    }
+    let cls = class(chars[col], big);
+    while col > 0 && class(chars[col - 1], big) == cls {
+        col -= 1;
+    }
+    Some((line, col))
+}
+
+/// End of the `count`th previous word (`ge`, `gE`).
+pub fn word_end_back(buffer: &Buffer, pos: Pos, count: usize, big: bool) -> Option<Pos> {
+    repeat(pos, count, |pos| prev_word_end(buffer, pos, big))
+}
+
+fn prev_word_end(buffer: &Buffer, (mut line, mut col): Pos, big: bool) -> Option<Pos> {
+    let mut chars = line_chars(buffer, line);
+    // leave the word under the cursor first
+    let cls = chars.get(col).map_or(0, |&c| class(c, big));
+    while cls != 0 && col > 0 && class(chars[col - 1], big) == cls {
+        col -= 1;
+    }
+    loop {
+        if col == 0 {
+            if line == 0 {
+                return None;
+            }
+            line -= 1;
+            chars = line_chars(buffer, line);
+            col = chars.len();
+            if chars.is_empty() {
+                return Some((line, 0));
+            }
+        }
+        col -= 1;
+        if class(chars[col], big) != 0 {
+            return Some((line, col));
+        }
+    }
+}
+
+/// A search for a char within the line, as done by `f`, `F`, `t` and `T`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Find {
+    pub ch: char,
+    pub forward: bool,
+    /// Stop next to the char instead of on it.
+    pub till: bool,
+}
+
+impl Find {
+    /// The same search the other way, for `,`.
+    pub fn reversed(self) -> Find {
+        Find {
+            forward: !self.forward,
+            ..self
+        }
+    }
+}
+
+/// Position `find` reaches on the cursor line after `count` matches. A
+/// `repeat`ed till search skips the char it stopped next to last time, so
+/// `;` after `tx` doesn't get stuck.
+pub fn find_char(
+    buffer: &Buffer,
+    (line, mut col): Pos,
+    count: usize,
+    find: Find,
+    repeat: bool,
+) -> Option<Pos> {
+    let chars = line_chars(buffer, line);
+    let skip = (find.till && repeat) as usize;
+    for i in 0..count.max(1) {
+        let skip = if i == 0 { skip } else { 0 };
+        col = if find.forward {
+            let from = col + 1 + skip;
+            from + chars.get(from..)?.iter().position(|&c| c == find.ch)?
+        } else {
+            let to = col.checked_sub(skip)?;
+            chars.get(..to)?.iter().rposition(|&c| c == find.ch)?
+        };
+    }
+    match (find.till, find.forward) {
+        (false, _) => Some((line, col)),
+        (true, true) => Some((line, col - 1)),
+        (true, false) => Some((line, col + 1)),
+    }
+}
+
+/// Whether a sentence starts at char `idx`. Sentences start after a `.`,
+/// `!` or `?` that is followed by blanks or a line break, with any closing
+/// `)`, `]`, `"` or `'` in between. Empty lines and the text after them
+/// start one too.
+pub fn is_sentence_start(buffer: &Buffer, idx: usize) -> bool {
+    let at = |i: usize| buffer.char(i).unwrap_or('\n');
+    let c = at(idx);
+    if c == '\n' {
+        // only the first of several empty lines
+        return (idx == 0 || at(idx - 1) == '\n') && (idx < 2 || at(idx - 2) != '\n');
+    }
+    if c.is_whitespace() {
+        return false;
+    }
+    let mut i = idx;
+    let mut breaks = 0;
+    while i > 0 && at(i - 1).is_whitespace() {
+        breaks += (at(i - 1) == '\n') as usize;
+        i -= 1;
+    }
+    if i == 0 {
+        return true;
+    }
+    if i == idx {
+        return false;
+    }
+    if breaks >= 2 {
+        return true;
+    }
+    while i > 0 && ")]\"'".contains(at(i - 1)) {
+        i -= 1;
+    }
+    i > 0 && ".!?".contains(at(i - 1))
+}
+
+/// Start of the `count`th next sentence (`)`), or the end of the buffer
+/// after the last one.
+pub fn sentence_forward(buffer: &Buffer, pos: Pos, count: usize) -> Option<Pos> {
+    let len = buffer.len_chars();
+    repeat(pos, count, |pos| {
+        let idx = buffer.pos_to_char(pos.0, pos.1);
+        match (idx + 1..len).find(|&i| is_sentence_start(buffer, i)) {
+            Some(i) => Some(char_to_pos(buffer, i)),
+            None if idx + 1 < len => Some(char_to_pos(buffer, len - 1)),
+            None => None,
+        }
+    })
+}
+
+/// Start of the sentence the cursor is in, or of the one before when it is
+/// already at a start (`(`).
+pub fn sentence_backward(buffer: &Buffer, pos: Pos, count: usize) -> Option<Pos> {
+    repeat(pos, count, |pos| {
+        let idx = buffer.pos_to_char(pos.0, pos.1);
+        let start = (0..idx).rev().find(|&i| is_sentence_start(buffer, i));
+        (idx > 0).then(|| char_to_pos(buffer, start.unwrap_or(0)))
+    })
+}
+
+/// The `count`th next empty line (`}`). After the last paragraph this is
+/// one past the end of the buffer, so an operator takes in all of it.
+pub fn paragraph_forward(buffer: &Buffer, (line, _): Pos, count: usize) -> Option<Pos> {
+    let last = buffer.line_count() - 1;
+    if line == last {
+        return None;
+    }
+    let empty = |l: usize| buffer.line_len(l) == 0;
+    let mut line = line;
+    for _ in 0..count.max(1) {
+        while line < last && empty(line) {
+            line += 1;
+        }
+        while line < last && !empty(line) {
+            line += 1;
+        }
+    }
+    Some((line, buffer.line_len(line)))
+}
+
+/// The `count`th previous empty line (`{`), or the start of the buffer.
+pub fn paragraph_backward(buffer: &Buffer, (line, col): Pos, count: usize) -> Option<Pos> {
+    if (line, col) == (0, 0) {
+        return None;
+    }
+    let empty = |l: usize| buffer.line_len(l) == 0;
+    let mut line = line;
+    for _ in 0..count.max(1) {
+        while line > 0 && empty(line) {
+            line -= 1;
+        }
+        while line > 0 && !empty(line) {
+            line -= 1;
+        }
+    }
+    Some((line, 0))
+}
+
+const PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
+/// The bracket matching the first one at or after the cursor on its line
+/// (`%`), taking nesting into account.
+pub fn matching_pair(buffer: &Buffer, (line, col): Pos) -> Option<Pos> {
+    let chars = line_chars(buffer, line);
+    let (start, c) = chars.iter().enumerate().skip(col).find(|(_, c)| {
+        PAIRS
+            .iter()
+            .any(|&(open, close)| **c == open || **c == close)
+    })?;
+    let (other, forward) = PAIRS.iter().find_map(|&(open, close)| match *c {
+        ch if ch == open => Some((close, true)),
+        ch if ch == close => Some((open, false)),
+        _ => None,
+    })?;
+    let mut idx = buffer.pos_to_char(line, start);
+    let mut depth = 0usize;
+    loop {
+        let ch = buffer.char(idx)?;
+        if ch == *c {
+            depth += 1;
+        } else if ch == other {
+            depth -= 1;
+            if depth == 0 {
+                return Some(char_to_pos(buffer, idx));
+            }
+        }
+        idx = if forward {
+            idx + 1
+        } else {
+            idx.checked_sub(1)?
+        };
+    }
+}
+
+/// First non-blank of the line `count` percent of the way into the buffer
+/// (`N%`).
+pub fn percent_line(buffer: &Buffer, count: usize) -> Option<Pos> {
+    if count == 0 || count > 100 {
+        return None;
+    }
+    let line = (count * buffer.line_count()).div_ceil(100) - 1;
+    Some(first_non_blank(buffer, line))
+}
+
+/// Which line of the screen `H`, `M` and `L` go to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScreenLine {
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// First non-blank of a line on screen, for a view showing lines
+/// `top..bottom`. `H` and `L` count lines in from the edge and stay
+/// `scrolloff` lines away from it unless the buffer ends there.
+pub fn screen_line(
+    buffer: &Buffer,
+    (top, bottom): (usize, usize),
+    scrolloff: usize,
+    which: ScreenLine,
+    count: usize,
+) -> Pos {
+    let bottom = bottom.min(buffer.line_count()).max(top + 1);
+    let n = count.max(1) - 1;
+    let line = match which {
+        ScreenLine::Top if top > 0 => top.saturating_add(n).max(top + scrolloff),
+        ScreenLine::Top => top.saturating_add(n),
+        ScreenLine::Middle => top + (bottom - 1 - top) / 2,
+        ScreenLine::Bottom if bottom < buffer.line_count() => (bottom - 1)
+            .saturating_sub(n)
+            .min((bottom - 1).saturating_sub(scrolloff)),
+        ScreenLine::Bottom => (bottom - 1).saturating_sub(n),
+    };
+    first_non_blank(buffer, line.clamp(top, bottom - 1))
+}
+
+/// How far `Ctrl-d`, `Ctrl-u`, `Ctrl-f` and `Ctrl-b` scroll.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scroll {
+    HalfDown,
+    HalfUp,
+    PageDown,
+    PageUp,
+}
+
+/// New top line of a view of `rows` lines and new cursor line after
+/// scrolling. A count gives the lines for half page scrolls and the number
+/// of pages for whole ones.
+pub fn scroll(
+    buffer: &Buffer,
+    (top, rows): (usize, usize),
+    line: usize,
+    scroll: Scroll,
+    count: Option<usize>,
+) -> Option<(usize, usize)> {
+    let last = buffer.line_count() - 1;
+    let half = count.unwrap_or(rows / 2).max(1);
+    // whole pages keep two lines of context
+    let page = count
+        .unwrap_or(1)
+        .saturating_mul(rows.saturating_sub(2).max(1));
+    match scroll {
+        Scroll::HalfDown if line < last => {
+            let last_top = buffer.line_count().saturating_sub(rows);
+            Some((
+                top.saturating_add(half).min(last_top).max(top),
+                line.saturating_add(half).min(last),
+            ))
+        }
+        Scroll::HalfUp if line > 0 => Some((top.saturating_sub(half), line.saturating_sub(half))),
+        Scroll::PageDown if top < last => {
+            let top = top.saturating_add(page).min(last);
+            Some((top, line.max(top)))
+        }
+        Scroll::PageUp if top > 0 => {
+            let top = top.saturating_sub(page);
+            Some((top, line.min(top + rows - 1)))
+        }
+        _ => None,
+    }
+}
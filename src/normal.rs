@@ -0,0 +1,675 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::motion::{Find, ScreenLine, Scroll};
+use crate::text_object::TextObject;
+
+/// Commands that act on the text a motion moves over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    ShiftRight,
+    ShiftLeft,
+    Format,
+    ToggleCase,
+    Lowercase,
+    Uppercase,
+}
+
+impl Operator {
+    /// The operator `key` starts. Visual mode has a few more, since there
+    /// keys like `u` and `~` don't need to leave Normal mode's meaning free.
+    fn from_key(key: Key, visual: bool) -> Option<Operator> {
+        let op = match key {
+            Key::Char('d') => Operator::Delete,
+            Key::Char('c') => Operator::Change,
+            Key::Char('y') => Operator::Yank,
+            Key::Char('>') => Operator::ShiftRight,
+            Key::Char('<') => Operator::ShiftLeft,
+            Key::Char('=') => Operator::Format,
+            Key::Char('x') if visual => Operator::Delete,
+            Key::Char('~') if visual => Operator::ToggleCase,
+            Key::Char('u') if visual => Operator::Lowercase,
+            Key::Char('U') if visual => Operator::Uppercase,
+            _ => return None,
+        };
+        Some(op)
+    }
+}
+
+/// How a Visual mode selection extends between its ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VisualKind {
+    /// Every char from one end to the other, `v`.
+    Char,
+    /// Whole lines, `V`.
+    Line,
+    /// A rectangle with the ends at opposite corners, `Ctrl-v`.
+    Block,
+}
+
+/// How much of the text between the cursor and the end of a motion an
+/// operator works on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MotionKind {
+    /// Up to but not including the end.
+    Exclusive,
+    /// Up to and including the end.
+    Inclusive,
+    /// Every line touched, whole.
+    Linewise,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    DisplayUp,
+    DisplayDown,
+    LineStart,
+    FirstNonBlank,
+    /// `_`, the first non-blank `count - 1` lines down.
+    FirstNonBlankDown,
+    LineEnd,
+    /// `big` motions move over WORDs, runs of anything but blanks.
+    WordStart {
+        big: bool,
+    },
+    WordEnd {
+        big: bool,
+    },
+    WordBack {
+        big: bool,
+    },
+    WordEndBack {
+        big: bool,
+    },
+    Find(Find),
+    /// `;` repeats the last `f`, `F`, `t` or `T`, `,` does so the other way.
+    RepeatFind {
+        reverse: bool,
+    },
+    SentenceForward,
+    SentenceBackward,
+    ParagraphForward,
+    ParagraphBackward,
+    /// `%` without a count, a percentage of the buffer with one.
+    MatchPair,
+    ScreenLine(ScreenLine),
+    Scroll(Scroll),
+    FirstLine,
+    LastLine,
+    /// `n` repeats the last search, `N` does so the other way.
+    SearchNext {
+        reverse: bool,
+    },
+    /// `*` and `#`, search for the word under the cursor.
+    SearchWord {
+        forward: bool,
+    },
+    /// `'x` goes to the first non-blank of the line of mark `x`, `` `x `` to
+    /// the mark itself.
+    Mark {
+        name: char,
+        exact: bool,
+    },
+}
+
+impl Motion {
+    fn from_keys(prefix: Option<char>, key: Key) -> Option<Motion> {
+        let motion = match (prefix, key) {
+            (None, Key::Char('h')) | (None, Key::Code(KeyCode::Left)) => Motion::Left,
+            (None, Key::Code(KeyCode::Backspace)) => Motion::Left,
+            (None, Key::Char('l')) | (None, Key::Code(KeyCode::Right)) => Motion::Right,
+            (None, Key::Char(' ')) => Motion::Right,
+            (None, Key::Char('k')) | (None, Key::Code(KeyCode::Up)) => Motion::Up,
+            (None, Key::Char('j')) | (None, Key::Code(KeyCode::Down)) => Motion::Down,
+            (None, Key::Char('0')) | (None, Key::Code(KeyCode::Home)) => Motion::LineStart,
+            (None, Key::Char('^')) => Motion::FirstNonBlank,
+            (None, Key::Char('_')) => Motion::FirstNonBlankDown,
+            (None, Key::Char('$')) | (None, Key::Code(KeyCode::End)) => Motion::LineEnd,
+            (None, Key::Char('w')) => Motion::WordStart { big: false },
+            (None, Key::Char('W')) => Motion::WordStart { big: true },
+            (None, Key::Char('e')) => Motion::WordEnd { big: false },
+            (None, Key::Char('E')) => Motion::WordEnd { big: true },
+            (None, Key::Char('b')) => Motion::WordBack { big: false },
+            (None, Key::Char('B')) => Motion::WordBack { big: true },
+            (Some('g'), Key::Char('e')) => Motion::WordEndBack { big: false },
+            (Some('g'), Key::Char('E')) => Motion::WordEndBack { big: true },
+            (None, Key::Char(';')) => Motion::RepeatFind { reverse: false },
+            (None, Key::Char(',')) => Motion::RepeatFind { reverse: true },
+            (None, Key::Char(')')) => Motion::SentenceForward,
+            (None, Key::Char('(')) => Motion::SentenceBackward,
+            (None, Key::Char('}')) => Motion::ParagraphForward,
+            (None, Key::Char('{')) => Motion::ParagraphBackward,
+            (None, Key::Char('%')) => Motion::MatchPair,
+            (None, Key::Char('H')) => Motion::ScreenLine(ScreenLine::Top),
+            (None, Key::Char('M')) => Motion::ScreenLine(ScreenLine::Middle),
+            (None, Key::Char('L')) => Motion::ScreenLine(ScreenLine::Bottom),
+            (None, Key::Ctrl('d')) => Motion::Scroll(Scroll::HalfDown),
+            (None, Key::Ctrl('u')) => Motion::Scroll(Scroll::HalfUp),
+            (None, Key::Ctrl('f')) | (None, Key::Code(KeyCode::PageDown)) => {
+                Motion::Scroll(Scroll::PageDown)
+            }
+            (None, Key::Ctrl('b')) | (None, Key::Code(KeyCode::PageUp)) => {
+                Motion::Scroll(Scroll::PageUp)
+            }
+            (None, Key::Char('G')) => Motion::LastLine,
+            (Some('g'), Key::Char('g')) => Motion::FirstLine,
+            (Some('g'), Key::Char('j')) => Motion::DisplayDown,
+            (Some('g'), Key::Char('k')) => Motion::DisplayUp,
+            (None, Key::Char('n')) => Motion::SearchNext { reverse: false },
+            (None, Key::Char('N')) => Motion::SearchNext { reverse: true },
+            (None, Key::Char('*')) => Motion::SearchWord { forward: true },
+            (None, Key::Char('#')) => Motion::SearchWord { forward: false },
+            (Some(c @ '\''), Key::Char(name)) | (Some(c @ '`'), Key::Char(name))
+                if is_mark_name(name) =>
+            {
+                Motion::Mark {
+                    name,
+                    exact: c == '`',
+                }
+            }
+            _ => return None,
+        };
+        Some(motion)
+    }
+
+    /// Direction and whether to stop short for `f`, `F`, `t` and `T`,
+    /// which still wait for the char to find.
+    fn find_from_key(prefix: Option<char>, key: Key) -> Option<(bool, bool)> {
+        match (prefix, key) {
+            (None, Key::Char('f')) => Some((true, false)),
+            (None, Key::Char('F')) => Some((false, false)),
+            (None, Key::Char('t')) => Some((true, true)),
+            (None, Key::Char('T')) => Some((false, true)),
+            _ => None,
+        }
+    }
+
+    /// How an operator treats the motion. `;` and `,` take the kind of the
+    /// search they repeat and `%` with a count is linewise, which only the
+    /// editor knows, so they are reported as inclusive here.
+    pub fn kind(self) -> MotionKind {
+        match self {
+            Motion::Up
+            | Motion::Down
+            | Motion::FirstLine
+            | Motion::LastLine
+            | Motion::DisplayUp
+            | Motion::DisplayDown
+            | Motion::FirstNonBlankDown
+            | Motion::ScreenLine(_)
+            | Motion::Scroll(_)
+            | Motion::Mark { exact: false, .. } => MotionKind::Linewise,
+            Motion::LineEnd
+            | Motion::WordEnd { .. }
+            | Motion::WordEndBack { .. }
+            | Motion::RepeatFind { .. }
+            | Motion::MatchPair => MotionKind::Inclusive,
+            Motion::Find(find) if find.forward => MotionKind::Inclusive,
+            Motion::Find(_)
+            | Motion::Left
+            | Motion::Right
+            | Motion::LineStart
+            | Motion::FirstNonBlank
+            | Motion::WordStart { .. }
+            | Motion::WordBack { .. }
+            | Motion::SentenceForward
+            | Motion::SentenceBackward
+            | Motion::ParagraphForward
+            | Motion::ParagraphBackward
+            | Motion::SearchNext { .. }
+            | Motion::SearchWord { .. }
+            | Motion::Mark { exact: true, .. } => MotionKind::Exclusive,
+        }
+    }
+
+    /// Whether the motion is a jump, which leaves the position it started
+    /// from in the jump list.
+    pub fn is_jump(self) -> bool {
+        matches!(
+            self,
+            Motion::FirstLine
+                | Motion::LastLine
+                | Motion::SentenceForward
+                | Motion::SentenceBackward
+                | Motion::ParagraphForward
+                | Motion::ParagraphBackward
+                | Motion::MatchPair
+                | Motion::ScreenLine(_)
+                | Motion::SearchNext { .. }
+                | Motion::SearchWord { .. }
+                | Motion::Mark { .. }
+        )
+    }
+}
+
+/// Commands that don't take a motion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Insert,
+    /// `a`, insert after the cursor.
+    Append,
+    /// `I`, insert before the first non-blank, or before a Visual block.
+    InsertLineStart,
+    /// `A`, insert at the end of the line, or after a Visual block.
+    AppendLineEnd,
+    Undo,
+    Redo,
+    UndoTravel {
+        forward: bool,
+    },
+    /// Start Visual mode, or switch to this kind or leave it when already in it.
+    Visual(VisualKind),
+    /// `gv`, select the previous Visual area again.
+    Reselect,
+    /// `o` in Visual mode, move the cursor to the other end of the selection.
+    SwapEnds,
+    /// `J`, join lines.
+    Join,
+    /// `p` and `P`, put a register after or before the cursor. `gp` and
+    /// `gP` leave the cursor after the new text.
+    Put {
+        before: bool,
+        cursor_after: bool,
+    },
+    /// `.`, repeat the last change.
+    Repeat,
+    /// `&`, repeat the last `:s` on the cursor line. `g&` repeats it with
+    /// its flags on every line, searching for the last search pattern.
+    RepeatSubstitute {
+        all: bool,
+    },
+    /// `q{register}`, start recording the keys typed into a register.
+    Record(char),
+    /// `@{register}`, run the keys in a register as if typed. `@@` runs the
+    /// last one again and `@:` the last command line.
+    Play(char),
+    /// `m{mark}`, set a mark at the cursor.
+    SetMark(char),
+    /// `Ctrl-o` and `Ctrl-i`, go back and forward through the jump list.
+    Jump {
+        forward: bool,
+    },
+    /// `g;` and `g,`, go back and forward through the change list.
+    ChangeList {
+        forward: bool,
+    },
+    /// `/` and `?`, type a pattern to search for.
+    Search {
+        forward: bool,
+    },
+    CommandLine,
+    ScrollLeft,
+    ScrollRight,
+    ScrollCursorStart,
+    ScrollCursorEnd,
+}
+
+impl Action {
+    fn from_keys(prefix: Option<char>, key: Key, visual: bool) -> Option<Action> {
+        let action = match (prefix, key) {
+            (None, Key::Char('i')) | (None, Key::Code(KeyCode::Insert)) => Action::Insert,
+            (None, Key::Char('a')) => Action::Append,
+            (None, Key::Char('I')) => Action::InsertLineStart,
+            (None, Key::Char('A')) => Action::AppendLineEnd,
+            (None, Key::Char('u')) => Action::Undo,
+            (None, Key::Ctrl('r')) => Action::Redo,
+            (None, Key::Char('v')) => Action::Visual(VisualKind::Char),
+            (None, Key::Char('V')) => Action::Visual(VisualKind::Line),
+            (None, Key::Ctrl('v')) => Action::Visual(VisualKind::Block),
+            (Some('g'), Key::Char('v')) => Action::Reselect,
+            (None, Key::Char('o')) | (None, Key::Char('O')) if visual => Action::SwapEnds,
+            (None, Key::Char('J')) => Action::Join,
+            (None, Key::Char(c @ 'p')) | (None, Key::Char(c @ 'P')) if !visual => Action::Put {
+                before: c == 'P',
+                cursor_after: false,
+            },
+            (Some('g'), Key::Char(c @ 'p')) | (Some('g'), Key::Char(c @ 'P')) if !visual => {
+                Action::Put {
+                    before: c == 'P',
+                    cursor_after: true,
+                }
+            }
+            (None, Key::Char('.')) if !visual => Action::Repeat,
+            (None, Key::Char('&')) if !visual => Action::RepeatSubstitute { all: false },
+            (Some('g'), Key::Char('&')) if !visual => Action::RepeatSubstitute { all: true },
+            (Some('q'), Key::Char(c)) if c.is_ascii_alphanumeric() => Action::Record(c),
+            (Some('@'), Key::Char(c)) if !visual && (is_register_name(c) || c == '@') => {
+                Action::Play(c)
+            }
+            (Some('m'), Key::Char(c)) if c.is_ascii_alphabetic() || "'`[]<>".contains(c) => {
+                Action::SetMark(c)
+            }
+            (None, Key::Ctrl('o')) => Action::Jump { forward: false },
+            (None, Key::Ctrl('i')) | (None, Key::Code(KeyCode::Tab)) => {
+                Action::Jump { forward: true }
+            }
+            (Some('g'), Key::Char(';')) => Action::ChangeList { forward: false },
+            (Some('g'), Key::Char(',')) => Action::ChangeList { forward: true },
+            (None, Key::Char('/')) if !visual => Action::Search { forward: true },
+            (None, Key::Char('?')) if !visual => Action::Search { forward: false },
+            (None, Key::Char(':')) => Action::CommandLine,
+            (Some('g'), Key::Char('-')) => Action::UndoTravel { forward: false },
+            (Some('g'), Key::Char('+')) => Action::UndoTravel { forward: true },
+            (Some('z'), Key::Char('h')) | (Some('z'), Key::Code(KeyCode::Left)) => {
+                Action::ScrollLeft
+            }
+            (Some('z'), Key::Char('l')) | (Some('z'), Key::Code(KeyCode::Right)) => {
+                Action::ScrollRight
+            }
+            (Some('z'), Key::Char('s')) => Action::ScrollCursorStart,
+            (Some('z'), Key::Char('e')) => Action::ScrollCursorEnd,
+            _ => return None,
+        };
+        Some(action)
+    }
+}
+
+/// What an operator works on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// The text a motion moves over. The count is the one typed after the
+    /// operator, as in `d3w`.
+    Motion(Motion, Option<usize>),
+    /// A text object, as in `d2aw`, with the count typed after the operator.
+    Object(TextObject, Option<usize>),
+    /// `count` whole lines, from doubling the operator as in `dd`.
+    Lines,
+    /// The Visual mode selection.
+    Selection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandKind {
+    Motion(Motion),
+    Operator(Operator, Target),
+    Action(Action),
+    /// A text object typed in Visual mode, which becomes the selection.
+    Select(TextObject),
+}
+
+/// A complete Normal mode command, e.g. `"a3dw`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NormalCommand {
+    pub register: Option<char>,
+    pub count: Option<usize>,
+    pub kind: CommandKind,
+}
+
+impl NormalCommand {
+    /// Count for the motion of an operator, `2d3w` moves over six words.
+    /// `None` when neither count was typed.
+    pub fn motion_count(&self) -> Option<usize> {
+        match (self.count, self.kind) {
+            (None, CommandKind::Operator(_, Target::Motion(_, None)))
+            | (None, CommandKind::Operator(_, Target::Object(_, None))) => None,
+            (count, CommandKind::Operator(_, Target::Motion(_, m_count)))
+            | (count, CommandKind::Operator(_, Target::Object(_, m_count))) => {
+                Some(times(count.unwrap_or(1), m_count.unwrap_or(1)))
+            }
+            (count, _) => count,
+        }
+    }
+
+    /// The motion the command moves by, itself or after an operator.
+    pub fn motion(&self) -> Option<Motion> {
+        match self.kind {
+            CommandKind::Motion(motion) | CommandKind::Operator(_, Target::Motion(motion, _)) => {
+                Some(motion)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the command changes the text, which makes it the one `.`
+    /// repeats.
+    pub fn is_change(&self) -> bool {
+        match self.kind {
+            CommandKind::Operator(op, _) => op != Operator::Yank,
+            CommandKind::Action(action) => matches!(
+                action,
+                Action::Insert
+                    | Action::Append
+                    | Action::InsertLineStart
+                    | Action::AppendLineEnd
+                    | Action::Join
+                    | Action::Put { .. }
+                    | Action::RepeatSubstitute { .. }
+            ),
+            _ => false,
+        }
+    }
+
+    /// The command with `count` in place of the counts it was typed with,
+    /// as when `.` is given one. `3.` after `2d3w` deletes three words.
+    pub fn with_count(self, count: usize) -> NormalCommand {
+        let kind = match self.kind {
+            CommandKind::Operator(op, Target::Motion(motion, _)) => {
+                CommandKind::Operator(op, Target::Motion(motion, None))
+            }
+            CommandKind::Operator(op, Target::Object(object, _)) => {
+                CommandKind::Operator(op, Target::Object(object, None))
+            }
+            kind => kind,
+        };
+        NormalCommand {
+            count: Some(count),
+            kind,
+            ..self
+        }
+    }
+}
+
+/// Result of parsing the keys typed so far.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Parse {
+    /// The keys are the start of a command, more are needed.
+    Pending,
+    Done(NormalCommand),
+    /// The keys can't start any command.
+    Invalid,
+    /// Escape was pressed part way through.
+    Cancelled,
+}
+
+/// A key as the parser sees it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Char(char),
+    Ctrl(char),
+    Code(KeyCode),
+}
+
+impl From<&KeyEvent> for Key {
+    fn from(key: &KeyEvent) -> Key {
+        match key.code {
+            KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => Key::Ctrl(c),
+            KeyCode::Char(c) => Key::Char(c),
+            code => Key::Code(code),
+        }
+    }
+}
+
+/// Keys that only mean something together with the key after them.
+/// Largest count a command takes, as in Vim. Longer counts are cut down to
+/// it, so the lines and columns they are added to can't overflow.
+pub const MAX_COUNT: usize = i32::MAX as usize;
+
+/// Product of two counts, no larger than [`MAX_COUNT`].
+fn times(a: usize, b: usize) -> usize {
+    a.saturating_mul(b).min(MAX_COUNT)
+}
+
+const PREFIXES: &[char] = &['g', 'z', 'q', '@', 'm', '\'', '`'];
+
+/// Names a register may have after `"`.
+pub fn is_register_name(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "\"-.:%_+*/#=".contains(c)
+}
+
+/// Marks `'` and `` ` `` can jump to.
+pub fn is_mark_name(c: char) -> bool {
+    c.is_ascii_alphabetic() || "'`[]<>.^".contains(c)
+}
+
+struct Keys<'a> {
+    keys: std::slice::Iter<'a, KeyEvent>,
+}
+
+impl Keys<'_> {
+    /// Next key, or `Parse::Pending` when the typed keys run out.
+    fn next(&mut self) -> Result<Key, Parse> {
+        match self.keys.next().map(Key::from) {
+            None => Err(Parse::Pending),
+            Some(Key::Code(KeyCode::Esc)) => Err(Parse::Cancelled),
+            Some(key) => Ok(key),
+        }
+    }
+
+    /// Reads a count if one starts at `key`, returning it with the key after.
+    fn count(&mut self, mut key: Key) -> Result<(Option<usize>, Key), Parse> {
+        let mut count: Option<usize> = None;
+        while let Key::Char(c @ '0'..='9') = key {
+            if c == '0' && count.is_none() {
+                break;
+            }
+            let digit = c.to_digit(10).unwrap_or(0) as usize;
+            count = Some(
+                times(count.unwrap_or(0), 10)
+                    .saturating_add(digit)
+                    .min(MAX_COUNT),
+            );
+            key = self.next()?;
+        }
+        Ok((count, key))
+    }
+
+    /// Reads the second key of a prefixed command, if `key` is a prefix.
+    fn prefixed(&mut self, key: Key) -> Result<(Option<char>, Key), Parse> {
+        match key {
+            Key::Char(c) if PREFIXES.contains(&c) => Ok((Some(c), self.next()?)),
+            key => Ok((None, key)),
+        }
+    }
+
+    /// The text object starting at `key`, if it is `i` or `a`.
+    fn object(&mut self, key: Key) -> Result<Option<TextObject>, Parse> {
+        let inner = match key {
+            Key::Char('i') => true,
+            Key::Char('a') => false,
+            _ => return Ok(None),
+        };
+        match self.next()? {
+            Key::Char(c) => Ok(Some(TextObject::from_key(inner, c).ok_or(Parse::Invalid)?)),
+            _ => Err(Parse::Invalid),
+        }
+    }
+
+    /// The motion starting at `key`, reading the char `f` and friends
+    /// search for.
+    fn motion(&mut self, prefix: Option<char>, key: Key) -> Result<Option<Motion>, Parse> {
+        let Some((forward, till)) = Motion::find_from_key(prefix, key) else {
+            return Ok(Motion::from_keys(prefix, key));
+        };
+        let ch = match self.next()? {
+            Key::Char(c) => c,
+            Key::Code(KeyCode::Tab) => '\t',
+            _ => return Err(Parse::Invalid),
+        };
+        Ok(Some(Motion::Find(Find { ch, forward, till })))
+    }
+}
+
+/// Parses Normal mode keys into a command: an optional register and count,
+/// then a motion, an action, or an operator followed by its own count and a
+/// motion or text object. In `visual` mode an operator works on the
+/// selection straight away and a text object changes the selection.
+pub fn parse(keys: &[KeyEvent], visual: bool) -> Parse {
+    let mut keys = Keys { keys: keys.iter() };
+    match parse_keys(&mut keys, visual) {
+        Ok(cmd) => Parse::Done(cmd),
+        Err(parse) => parse,
+    }
+}
+
+fn parse_keys(keys: &mut Keys, visual: bool) -> Result<NormalCommand, Parse> {
+    let mut register = None;
+    let mut count: Option<usize> = None;
+    let mut key = keys.next()?;
+    loop {
+        if key == Key::Char('"') {
+            match keys.next()? {
+                Key::Char(c) if is_register_name(c) => register = Some(c),
+                _ => return Err(Parse::Invalid),
+            }
+            key = keys.next()?;
+            continue;
+        }
+        let (n, next) = keys.count(key)?;
+        key = next;
+        match n {
+            // counts on both sides of a register multiply, like `2"a3dd`
+            Some(n) => count = Some(times(count.unwrap_or(1), n)),
+            None => break,
+        }
+    }
+    let kind = if let Some(op) = Operator::from_key(key, visual) {
+        if visual {
+            return Ok(NormalCommand {
+                register,
+                count,
+                kind: CommandKind::Operator(op, Target::Selection),
+            });
+        }
+        let next = keys.next()?;
+        let (m_count, key) = keys.count(next)?;
+        if key == Key::Char(op_key(op)) {
+            let lines = times(count.unwrap_or(1), m_count.unwrap_or(1));
+            return Ok(NormalCommand {
+                register,
+                count: Some(lines),
+                kind: CommandKind::Operator(op, Target::Lines),
+            });
+        }
+        if let Some(object) = keys.object(key)? {
+            CommandKind::Operator(op, Target::Object(object, m_count))
+        } else {
+            let (prefix, key) = keys.prefixed(key)?;
+            let motion = keys.motion(prefix, key)?.ok_or(Parse::Invalid)?;
+            CommandKind::Operator(op, Target::Motion(motion, m_count))
+        }
+    } else if visual && matches!(key, Key::Char('i') | Key::Char('a')) {
+        CommandKind::Select(keys.object(key)?.ok_or(Parse::Invalid)?)
+    } else {
+        let (prefix, key) = keys.prefixed(key)?;
+        if let Some(motion) = keys.motion(prefix, key)? {
+            CommandKind::Motion(motion)
+        } else {
+            CommandKind::Action(Action::from_keys(prefix, key, visual).ok_or(Parse::Invalid)?)
+        }
+    };
+    Ok(NormalCommand {
+        register,
+        count,
+        kind,
+    })
+}
+
+fn op_key(op: Operator) -> char {
+    match op {
+        Operator::Delete => 'd',
+        Operator::Change => 'c',
+        Operator::Yank => 'y',
+        Operator::ShiftRight => '>',
+        Operator::ShiftLeft => '<',
+        Operator::Format => '=',
+        Operator::ToggleCase => '~',
+        Operator::Lowercase => 'u',
+        Operator::Uppercase => 'U',
+    }
+}
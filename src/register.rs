@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+/// How the text in a register goes back into the buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegisterKind {
+    /// Put inside a line, from a charwise yank or delete.
+    Char,
+    /// Whole lines, put above or below the cursor line. The text ends with a
+    /// line break.
+    Line,
+    /// A rectangle from a Visual block, one line of the block per line of
+    /// text, put at the same column on consecutive lines.
+    Block,
+}
+
+impl RegisterKind {
+    /// Letter `:registers` shows in its Type column.
+    pub fn letter(self) -> char {
+        match self {
+            RegisterKind::Char => 'c',
+            RegisterKind::Line => 'l',
+            RegisterKind::Block => 'b',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Register {
+    pub text: String,
+    pub kind: RegisterKind,
+}
+
+impl Register {
+    pub fn new<S: Into<String>>(text: S, kind: RegisterKind) -> Register {
+        Register {
+            text: text.into(),
+            kind,
+        }
+    }
+
+    /// The text split into the lines it puts, without a linewise register's
+    /// final line break.
+    pub fn lines(&self) -> Vec<&str> {
+        let text = match self.kind {
+            RegisterKind::Line => self.text.strip_suffix('\n').unwrap_or(&self.text),
+            _ => &self.text,
+        };
+        text.split('\n').collect()
+    }
+
+    /// Adds `other` to the end, as writing to an uppercase register does.
+    /// Anything appended to or from whole lines makes the register linewise.
+    fn append(&mut self, other: Register) {
+        use RegisterKind::*;
+        match (self.kind, other.kind) {
+            (Line, Line) => self.text.push_str(&other.text),
+            (Line, _) => {
+                self.text.push_str(&other.text);
+                self.text.push('\n');
+            }
+            (_, Line) => {
+                self.text.push('\n');
+                self.text.push_str(&other.text);
+                self.kind = Line;
+            }
+            (Char, Char) => self.text.push_str(&other.text),
+            _ => {
+                self.text.push('\n');
+                self.text.push_str(&other.text);
+                self.kind = Block;
+            }
+        }
+    }
+}
+
+/// Names listed by `:registers`, in the order it lists them.
+const LISTED: &str = "\"0123456789abcdefghijklmnopqrstuvwxyz-*+.:%/";
+
+/// Vim's registers. Yanks go to `0` and deletes to `1`, shifting the older
+/// ones up to `9`, unless they are smaller than a line, which go to `-`.
+/// `a` to `z` are only written when named, `A` to `Z` append to them, and
+/// `_` throws text away. The unnamed register `"` is whichever was written
+/// last. `.`, `:` and `/` hold the last inserted text, command line and
+/// search pattern and can't be written by yanks or deletes. `+` and `*`
+/// keep what was last copied to or read from the system clipboard, which
+/// the editor keeps in step.
+#[derive(Debug, Default)]
+pub struct Registers {
+    registers: HashMap<char, Register>,
+    /// Name of the register `"` stands for.
+    unnamed: Option<char>,
+}
+
+impl Registers {
+    pub fn new() -> Registers {
+        Registers::default()
+    }
+
+    /// Contents of register `name`. `%` is not stored here, it is the file
+    /// name the editor knows.
+    pub fn get(&self, name: char) -> Option<&Register> {
+        let name = match name {
+            '"' => self.unnamed?,
+            c => c.to_ascii_lowercase(),
+        };
+        self.registers.get(&name)
+    }
+
+    /// Whether yanks and deletes can write to register `name`.
+    pub fn is_writable(name: char) -> bool {
+        name.is_ascii_alphanumeric() || "\"-_+*".contains(name)
+    }
+
+    /// Stores yanked text. Without a register name it goes to `0`.
+    pub fn yank(&mut self, name: char, register: Register) {
+        match name {
+            '"' => self.store('0', register),
+            name => self.write(name, register),
+        }
+    }
+
+    /// Stores deleted text. Without a register name it goes to `1`, or to
+    /// `-` when `small`, a charwise delete within one line.
+    pub fn delete(&mut self, name: char, register: Register, small: bool) {
+        match name {
+            '"' if small => self.store('-', register),
+            '"' => self.shift_deletes(register),
+            name => self.write(name, register),
+        }
+    }
+
+    /// Sets a register without making it the unnamed one, for those the
+    /// editor fills itself like `.`, `:`, the clipboard and recordings. An
+    /// uppercase name appends, as recording with `qA` does.
+    pub fn set(&mut self, name: char, register: Register) {
+        let lower = name.to_ascii_lowercase();
+        match self.registers.get_mut(&lower) {
+            Some(existing) if name.is_ascii_uppercase() => existing.append(register),
+            _ => {
+                self.registers.insert(lower, register);
+            }
+        }
+    }
+
+    /// Names and contents of the registers holding text, in `:registers`
+    /// order with the unnamed one first. `%` is left to the caller.
+    pub fn list(&self) -> Vec<(char, &Register)> {
+        LISTED
+            .chars()
+            .filter_map(|name| Some((name, self.get(name)?)))
+            .collect()
+    }
+
+    fn write(&mut self, name: char, register: Register) {
+        if name == '_' || !Registers::is_writable(name) {
+            return;
+        }
+        let lower = name.to_ascii_lowercase();
+        match self.registers.get_mut(&lower) {
+            Some(existing) if name.is_ascii_uppercase() => {
+                existing.append(register);
+                self.unnamed = Some(lower);
+            }
+            _ => self.store(lower, register),
+        }
+    }
+
+    fn store(&mut self, name: char, register: Register) {
+        self.registers.insert(name, register);
+        self.unnamed = Some(name);
+    }
+
+    /// Moves `1`..`8` up to `2`..`9` and stores `register` in `1`.
+    fn shift_deletes(&mut self, register: Register) {
+        for n in (1..9).rev() {
+            let from = std::char::from_digit(n, 10).unwrap_or('1');
+            let to = std::char::from_digit(n + 1, 10).unwrap_or('9');
+            if let Some(text) = self.registers.remove(&from) {
+                self.registers.insert(to, text);
+            }
+        }
+        self.store('1', register);
+    }
+}
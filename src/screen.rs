@@ -0,0 +1,163 @@
+use crossterm::style::Color;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+}
+
+/// One terminal column. `symbol` is a whole grapheme; the column after a
+/// wide one holds an empty symbol since the terminal fills it already.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub symbol: String,
+    pub style: Style,
+}
+
+impl Cell {
+    pub fn is_continuation(&self) -> bool {
+        self.symbol.is_empty()
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            symbol: " ".to_string(),
+            style: Style::default(),
+        }
+    }
+}
+
+/// One frame of terminal output.
+///
+/// The editor draws a whole frame into a `Screen` and only the cells that
+/// differ from the previously shown frame are sent to the terminal.
+#[derive(Debug, Clone, Default)]
+pub struct Screen {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Cell>,
+    pub cursor: (usize, usize),
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Screen {
+        Screen {
+            width,
+            height,
+            cells: vec![Cell::default(); width * height],
+            cursor: (0, 0),
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&Cell> {
+        if x < self.width && y < self.height {
+            self.cells.get(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    pub fn put_char(&mut self, x: usize, y: usize, ch: char, style: Style) {
+        self.put_cell(
+            x,
+            y,
+            Cell {
+                symbol: ch.to_string(),
+                style,
+            },
+        );
+    }
+
+    pub fn put_cell(&mut self, x: usize, y: usize, cell: Cell) {
+        if x < self.width && y < self.height {
+            self.cells[y * self.width + x] = cell;
+        }
+    }
+
+    /// Writes `s` starting at `x` and clips at the right edge. Wide graphemes
+    /// take two cells, one that doesn't fit leaves a space, and control chars
+    /// are written in caret notation. Returns the column after the last cell
+    /// written.
+    pub fn put_str(&mut self, x: usize, y: usize, s: &str, style: Style) -> usize {
+        let mut col = x;
+        for g in s.graphemes(true) {
+            if col >= self.width {
+                break;
+            }
+            if let Some(c) = display::caret(g) {
+                self.put_char(col, y, '^', style);
+                self.put_char(col + 1, y, c, style);
+                col = (col + 2).min(self.width);
+                continue;
+            }
+            let width = display::grapheme_width(g, 0, 1);
+            if col + width > self.width {
+                self.put_char(col, y, ' ', style);
+                col += 1;
+                continue;
+            }
+            self.put_cell(
+                col,
+                y,
+                Cell {
+                    symbol: g.to_string(),
+                    style,
+                },
+            );
+            for i in 1..width {
+                self.put_cell(
+                    col + i,
+                    y,
+                    Cell {
+                        symbol: String::new(),
+                        style,
+                    },
+                );
+            }
+            col += width;
+        }
+        col
+    }
+
+    /// Restyles `width` cells from `x` on row `y`, keeping what they show.
+    pub fn set_style(&mut self, x: usize, y: usize, width: usize, style: Style) {
+        if y >= self.height {
+            return;
+        }
+        for x in x..(x + width).min(self.width) {
+            self.cells[y * self.width + x].style = style;
+        }
+    }
+
+    /// Sets the style of every cell in row `y`, used for full width bars.
+    pub fn fill_row(&mut self, y: usize, style: Style) {
+        for x in 0..self.width {
+            self.put_char(x, y, ' ', style);
+        }
+    }
+
+    pub fn set_cursor(&mut self, x: usize, y: usize) {
+        self.cursor = (x, y);
+    }
+
+    /// Cells that differ from `prev`, in row order. Everything counts as
+    /// changed when the sizes differ.
+    pub fn changes<'a>(&'a self, prev: &Screen) -> Vec<(usize, usize, &'a Cell)> {
+        let same_size = self.width == prev.width && self.height == prev.height;
+        let mut changes = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let cell = &self.cells[y * self.width + x];
+                if !same_size || prev.get(x, y) != Some(cell) {
+                    changes.push((x, y, cell));
+                }
+            }
+        }
+        changes
+    }
+}
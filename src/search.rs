@@ -0,0 +1,360 @@
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+use crate::buffer::Buffer;
+use crate::motion::Pos;
+
+/// A `/` or `?` search, which `n` and `N` repeat.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Search {
+    pub pattern: String,
+    pub forward: bool,
+}
+
+/// Compiles a search pattern, written as in vim with `magic` on: `\(` and
+/// `\)` make a group, `\%(` one that doesn't capture, `\|` separates
+/// alternatives, `\+`, `\?`, `\=` and `\{n,m}` repeat and `\<` and `\>`
+/// match at word boundaries, while `(`, `+`, `{` and friends on their own
+/// are plain chars. Classes like `\s`, `\d`, `\w`, `\a` and `\u` work too,
+/// and other escaped letters are an error rather than meaning something
+/// else. A `\c` or `\C` anywhere makes the search ignore or match case
+/// whatever the settings say. Otherwise case is ignored with `ignorecase`,
+/// unless `smartcase` is on too and the pattern has an uppercase letter.
+pub fn compile(pattern: &str, ignorecase: bool, smartcase: bool) -> Result<Regex, String> {
+    let invalid = || format!("E383: Invalid search string: {}", pattern);
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut source = String::with_capacity(pattern.len());
+    let mut forced = None;
+    let mut upper = false;
+    // whether `^` here starts the pattern, a group or an alternative
+    let mut at_start = true;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        let starts = at_start;
+        at_start = false;
+        if c != '\\' {
+            match c {
+                '(' | ')' | '+' | '?' | '{' | '}' | '|' => {
+                    source.push('\\');
+                    source.push(c);
+                }
+                '^' if starts => source.push('^'),
+                '^' => source.push_str("\\^"),
+                '$' if chars[i..].is_empty()
+                    || chars[i..].starts_with(&['\\', ')'])
+                    || chars[i..].starts_with(&['\\', '|']) =>
+                {
+                    source.push('$')
+                }
+                '$' => source.push_str("\\$"),
+                '[' => match class_end(&chars, i) {
+                    Some(end) => {
+                        push_class(&mut source, &chars[i - 1..end]);
+                        i = end;
+                    }
+                    None => source.push_str("\\["),
+                },
+                c => {
+                    upper |= c.is_uppercase();
+                    source.push(c);
+                }
+            }
+            continue;
+        }
+        let Some(&next) = chars.get(i) else {
+            source.push_str("\\\\");
+            break;
+        };
+        i += 1;
+        match next {
+            'c' => {
+                forced = Some(true);
+                at_start = starts;
+            }
+            'C' => {
+                forced = Some(false);
+                at_start = starts;
+            }
+            '(' | '|' => {
+                source.push(next);
+                at_start = true;
+            }
+            '%' if chars.get(i) == Some(&'(') => {
+                i += 1;
+                source.push_str("(?:");
+                at_start = true;
+            }
+            ')' | '+' | '?' => source.push(next),
+            '=' => source.push('?'),
+            '{' => {
+                let lazy = chars.get(i) == Some(&'-');
+                i += lazy as usize;
+                let close = chars[i..]
+                    .iter()
+                    .position(|&c| c == '}')
+                    .ok_or_else(invalid)?;
+                let mut bounds: String = chars[i..i + close].iter().collect();
+                i += close + 1;
+                // `\{n,m\}` may escape the brace
+                if bounds.ends_with('\\') {
+                    bounds.pop();
+                }
+                if !bounds.chars().all(|c| c.is_ascii_digit() || c == ',') {
+                    return Err(invalid());
+                }
+                match bounds.as_str() {
+                    "" | "," => source.push('*'),
+                    b if b.starts_with(',') => source.push_str(&format!("{{0{}}}", b)),
+                    b => source.push_str(&format!("{{{}}}", b)),
+                }
+                if lazy {
+                    source.push('?');
+                }
+            }
+            '<' => source.push_str("\\b{start}"),
+            '>' => source.push_str("\\b{end}"),
+            's' | 'S' | 'd' | 'D' | 'w' | 'W' | 'n' | 't' | 'r' => {
+                source.push('\\');
+                source.push(next);
+            }
+            'e' => source.push_str("\\x1b"),
+            'a' => source.push_str("[a-zA-Z]"),
+            'A' => source.push_str("[^a-zA-Z]"),
+            'l' => source.push_str("[a-z]"),
+            'L' => source.push_str("[^a-z]"),
+            'u' => source.push_str("[A-Z]"),
+            'U' => source.push_str("[^A-Z]"),
+            'x' => source.push_str("[0-9a-fA-F]"),
+            'X' => source.push_str("[^0-9a-fA-F]"),
+            'o' => source.push_str("[0-7]"),
+            'O' => source.push_str("[^0-7]"),
+            'h' => source.push_str("[a-zA-Z_]"),
+            'H' => source.push_str("[^a-zA-Z_]"),
+            next if next.is_alphanumeric() => {
+                return Err(format!(
+                    "E383: Invalid search string: \\{} in {}",
+                    next, pattern
+                ))
+            }
+            next => {
+                source.push('\\');
+                source.push(next);
+            }
+        }
+    }
+    let ignore = forced.unwrap_or(ignorecase && !(smartcase && upper));
+    RegexBuilder::new(&source)
+        .case_insensitive(ignore)
+        .build()
+        .map_err(|e| match e {
+            regex::Error::Syntax(_) => invalid(),
+            e => e.to_string(),
+        })
+}
+
+/// Index just past the `]` closing the `[` class that starts before
+/// `start`. A `]` first in the class, or after `^`, is one of its chars.
+fn class_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut i = start;
+    if chars.get(i) == Some(&'^') {
+        i += 1;
+    }
+    if chars.get(i) == Some(&']') {
+        i += 1;
+    }
+    while i < chars.len() {
+        match chars[i] {
+            ']' => return Some(i + 1),
+            '\\' => i += 2,
+            '[' if chars.get(i + 1) == Some(&':') => {
+                let rest = &chars[i + 2..];
+                let close = rest.windows(2).position(|w| w == [':', ']'])?;
+                i += close + 4;
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Copies the class `class`, brackets included, escaping the chars that
+/// mean more inside a Rust regex class than in vim's.
+fn push_class(source: &mut String, class: &[char]) {
+    let inner = &class[1..class.len() - 1];
+    source.push('[');
+    let mut i = 0;
+    if inner.first() == Some(&'^') {
+        source.push('^');
+        i = 1;
+    }
+    while i < inner.len() {
+        let c = inner[i];
+        match c {
+            '[' if inner.get(i + 1) == Some(&':') => {
+                let len = inner[i..]
+                    .windows(2)
+                    .position(|w| w == [':', ']'])
+                    .map_or(1, |p| p + 2);
+                source.extend(&inner[i..i + len]);
+                i += len;
+                continue;
+            }
+            '\\' if i + 1 < inner.len() => {
+                source.push('\\');
+                source.push(inner[i + 1]);
+                i += 2;
+                continue;
+            }
+            '[' | '&' | '~' | '\\' => source.push('\\'),
+            ']' if i == 0 || (i == 1 && inner[0] == '^') => source.push('\\'),
+            _ => {}
+        }
+        source.push(c);
+        i += 1;
+    }
+    source.push(']');
+}
+
+/// `text` escaped to match itself in a pattern [`compile`] reads.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.*[]~^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether `c` can delimit a pattern, as `/` does in `:s/a/b/` and
+/// `:g/a/d`: anything but letters, digits, blanks, `\`, `"` and `|`.
+pub fn is_delimiter(c: char) -> bool {
+    !(c.is_alphanumeric() || c.is_whitespace() || "\\\"|".contains(c))
+}
+
+/// Splits `text` at the first `delim` not escaped by a backslash, dropping
+/// the backslash of escaped ones. Without a `delim` it all goes before.
+pub fn split_delimited(text: &str, delim: char) -> (String, &str) {
+    let mut part = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, next)) if next == delim => part.push(next),
+                Some((_, next)) => {
+                    part.push(c);
+                    part.push(next);
+                }
+                None => part.push(c),
+            },
+            c if c == delim => return (part, &text[i + c.len_utf8()..]),
+            c => part.push(c),
+        }
+    }
+    (part, "")
+}
+
+/// Char ranges of the matches of `regex` in `line`.
+pub fn line_matches(regex: &Regex, line: &str) -> Vec<Range<usize>> {
+    let mut chars = 0;
+    let mut byte = 0;
+    let mut to_char = |b: usize| {
+        chars += line[byte..b].chars().count();
+        byte = b;
+        chars
+    };
+    regex
+        .find_iter(line)
+        .map(|m| {
+            let start = to_char(m.start());
+            start..to_char(m.end())
+        })
+        .collect()
+}
+
+/// Where the next match of `regex` after `pos`, or before it going back,
+/// starts and whether getting there went round the end of the buffer.
+/// Without `wrap` the search stops at the end.
+pub fn find(
+    buffer: &Buffer,
+    regex: &Regex,
+    (line, col): Pos,
+    forward: bool,
+    wrap: bool,
+) -> Option<(Pos, bool)> {
+    let count = buffer.line_count();
+    let starts = |idx: usize| -> Vec<usize> {
+        line_matches(regex, &buffer.line(idx))
+            .into_iter()
+            .map(|m| m.start)
+            .collect()
+    };
+    if forward {
+        if let Some(c) = starts(line).into_iter().find(|&c| c > col) {
+            return Some(((line, c), false));
+        }
+        for idx in line + 1..count {
+            if let Some(&c) = starts(idx).first() {
+                return Some(((idx, c), false));
+            }
+        }
+        if !wrap {
+            return None;
+        }
+        for idx in 0..=line {
+            if let Some(&c) = starts(idx).first() {
+                return Some(((idx, c), true));
+            }
+        }
+    } else {
+        if let Some(c) = starts(line).into_iter().rfind(|&c| c < col) {
+            return Some(((line, c), false));
+        }
+        for idx in (0..line).rev() {
+            if let Some(&c) = starts(idx).last() {
+                return Some(((idx, c), false));
+            }
+        }
+        if !wrap {
+            return None;
+        }
+        for idx in (line..count).rev() {
+            if let Some(&c) = starts(idx).last() {
+                return Some(((idx, c), true));
+            }
+        }
+    }
+    None
+}
+
+/// Pattern `*` and `#` search for and the column it starts at: the keyword
+/// under or after column `col`, matched as a whole word, or else the run of
+/// other non-blanks there.
+pub fn word_pattern(line: &str, col: usize) -> Option<(String, usize)> {
+    let chars: Vec<char> = line.chars().collect();
+    let keyword = |c: char| c.is_alphanumeric() || c == '_';
+    let (start, is_keyword) = match (col..chars.len()).find(|&i| keyword(chars[i])) {
+        Some(i) => {
+            let start = (0..i).rev().take_while(|&j| keyword(chars[j])).last();
+            (start.unwrap_or(i), true)
+        }
+        None => (
+            (col..chars.len()).find(|&i| !chars[i].is_whitespace())?,
+            false,
+        ),
+    };
+    let word: String = chars[start..]
+        .iter()
+        .take_while(|&&c| keyword(c) == is_keyword && !c.is_whitespace())
+        .collect();
+    let escaped = escape(&word);
+    let pattern = if is_keyword {
+        format!("\\<{}\\>", escaped)
+    } else {
+        escaped
+    };
+    Some((pattern, start))
+}
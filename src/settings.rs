@@ -0,0 +1,342 @@
+use crossterm::style::Color;
+use serde::{Deserialize, Serialize};
+
+/// Every user option, loaded from the config file and changed with `:set`.
+///
+/// Field names double as the option names used in the config file and on
+/// the command line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Columns a tab character takes up. Older config files call it
+    /// `tab_spaces`.
+    #[serde(alias = "tab_spaces")]
+    pub tabstop: usize,
+    /// Columns used by each step of indent, `0` means use `tabstop`.
+    pub shiftwidth: usize,
+    /// Insert spaces when Tab is pressed.
+    pub expandtab: bool,
+    /// Show line numbers.
+    pub number: bool,
+    /// Lines kept visible above and below the cursor.
+    pub scrolloff: usize,
+    /// Show long lines across several rows instead of scrolling sideways.
+    pub wrap: bool,
+    /// Searches ignore case, unless `smartcase` is on and the pattern has
+    /// an uppercase letter.
+    pub ignorecase: bool,
+    pub smartcase: bool,
+    /// Highlight every match of the last search.
+    pub hlsearch: bool,
+    /// Move to the match while the search pattern is typed.
+    pub incsearch: bool,
+    /// Searches go round the end of the buffer.
+    pub wrapscan: bool,
+    /// Status line colors, see [`parse_color`].
+    pub statusfg: String,
+    pub statusbg: String,
+    /// Commands the `+` and `*` registers copy through, given the text on
+    /// stdin, and paste from, printing it. Empty uses the terminal to copy.
+    pub clipcopy: String,
+    pub clippaste: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            tabstop: 4,
+            shiftwidth: 4,
+            expandtab: true,
+            number: true,
+            scrolloff: 0,
+            wrap: false,
+            ignorecase: false,
+            smartcase: false,
+            hlsearch: true,
+            incsearch: true,
+            wrapscan: true,
+            statusfg: "black".into(),
+            statusbg: "darkmagenta".into(),
+            clipcopy: String::new(),
+            clippaste: String::new(),
+        }
+    }
+}
+
+/// Value of a single option.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Number(usize),
+    Text(String),
+}
+
+/// Full names and abbreviations of every option.
+const OPTIONS: &[(&str, &str)] = &[
+    ("tabstop", "ts"),
+    ("shiftwidth", "sw"),
+    ("expandtab", "et"),
+    ("number", "nu"),
+    ("scrolloff", "so"),
+    ("wrap", "wrap"),
+    ("ignorecase", "ic"),
+    ("smartcase", "scs"),
+    ("hlsearch", "hls"),
+    ("incsearch", "is"),
+    ("wrapscan", "ws"),
+    ("statusfg", "statusfg"),
+    ("statusbg", "statusbg"),
+    ("clipcopy", "clipcopy"),
+    ("clippaste", "clippaste"),
+];
+
+/// Largest `tabstop`, `shiftwidth` or `scrolloff`, as Vim allows for
+/// `tabstop`. Lines are expanded to that many columns per tab.
+const MAX_NUMBER: usize = 9999;
+
+fn full_name(name: &str) -> Option<&'static str> {
+    OPTIONS
+        .iter()
+        .find(|(full, short)| *full == name || *short == name)
+        .map(|(full, _)| *full)
+}
+
+impl Settings {
+    /// Loads the settings from the config file. When it can't be read the
+    /// defaults are used and the error is returned alongside them.
+    pub fn load() -> (Settings, Option<String>) {
+        match confy::load::<Settings>("fred") {
+            Ok(settings) => match settings.check() {
+                Ok(()) => (settings, None),
+                Err(e) => (
+                    Settings::default(),
+                    Some(format!("Error in config file: {}", e)),
+                ),
+            },
+            Err(e) => (
+                Settings::default(),
+                Some(format!("Error in config file: {}", e)),
+            ),
+        }
+    }
+
+    /// Checks values serde can't, like color names.
+    pub fn check(&self) -> Result<(), String> {
+        let mut checked = Settings::default();
+        for (name, _) in OPTIONS {
+            let value = self.get(name).expect("every option has a value");
+            checked
+                .put(name, value)
+                .map_err(|e| format!("{}: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        let value = match full_name(name)? {
+            "tabstop" => Value::Number(self.tabstop),
+            "shiftwidth" => Value::Number(self.shiftwidth),
+            "expandtab" => Value::Bool(self.expandtab),
+            "number" => Value::Bool(self.number),
+            "scrolloff" => Value::Number(self.scrolloff),
+            "wrap" => Value::Bool(self.wrap),
+            "ignorecase" => Value::Bool(self.ignorecase),
+            "smartcase" => Value::Bool(self.smartcase),
+            "hlsearch" => Value::Bool(self.hlsearch),
+            "incsearch" => Value::Bool(self.incsearch),
+            "wrapscan" => Value::Bool(self.wrapscan),
+            "statusfg" => Value::Text(self.statusfg.clone()),
+            "statusbg" => Value::Text(self.statusbg.clone()),
+            "clipcopy" => Value::Text(self.clipcopy.clone()),
+            "clippaste" => Value::Text(self.clippaste.clone()),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Sets option `name`, which must exist and take a `value` of this type.
+    fn put(&mut self, name: &str, value: Value) -> Result<(), String> {
+        match (name, value) {
+            ("tabstop", Value::Number(0)) => return Err("E487: Argument must be positive".into()),
+            ("tabstop", Value::Number(n))
+            | ("shiftwidth", Value::Number(n))
+            | ("scrolloff", Value::Number(n))
+                if n > MAX_NUMBER =>
+            {
+                return Err(format!("E474: Invalid argument: {}={}", name, n))
+            }
+            ("tabstop", Value::Number(n)) => self.tabstop = n,
+            ("shiftwidth", Value::Number(n)) => self.shiftwidth = n,
+            ("expandtab", Value::Bool(b)) => self.expandtab = b,
+            ("number", Value::Bool(b)) => self.number = b,
+            ("scrolloff", Value::Number(n)) => self.scrolloff = n,
+            ("wrap", Value::Bool(b)) => self.wrap = b,
+            ("ignorecase", Value::Bool(b)) => self.ignorecase = b,
+            ("smartcase", Value::Bool(b)) => self.smartcase = b,
+            ("hlsearch", Value::Bool(b)) => self.hlsearch = b,
+            ("incsearch", Value::Bool(b)) => self.incsearch = b,
+            ("wrapscan", Value::Bool(b)) => self.wrapscan = b,
+            ("statusfg", Value::Text(s)) | ("statusbg", Value::Text(s))
+                if parse_color(&s).is_none() =>
+            {
+                return Err(format!("E254: Cannot allocate color {}", s))
+            }
+            ("statusfg", Value::Text(s)) => self.statusfg = s,
+            ("statusbg", Value::Text(s)) => self.statusbg = s,
+            ("clipcopy", Value::Text(s)) => self.clipcopy = s,
+            ("clippaste", Value::Text(s)) => self.clippaste = s,
+            (name, _) => return Err(format!("E474: Invalid argument: {}", name)),
+        }
+        Ok(())
+    }
+
+    /// Applies one `:set` argument such as `ts=8`, `nowrap`, `wrap!` or
+    /// `ts?`. Returns the text to show for arguments that ask for a value.
+    pub fn apply(&mut self, arg: &str) -> Result<Option<String>, String> {
+        let unknown = || format!("E518: Unknown option: {}", arg);
+        let split = arg.find(['=', ':']).map(|i| {
+            let (name, value) = (&arg[..i], &arg[i + 1..]);
+            match name.chars().last() {
+                Some(op @ '+') | Some(op @ '-') | Some(op @ '^') => {
+                    (&name[..name.len() - 1], Some(op), value)
+                }
+                _ => (name, None, value),
+            }
+        });
+        if let Some((name, op, text)) = split {
+            let name = full_name(name).ok_or_else(unknown)?;
+            let value = match self.get(name).expect("known option") {
+                Value::Bool(_) => return Err(format!("E474: Invalid argument: {}", arg)),
+                Value::Number(old) => {
+                    let n: usize = text
+                        .parse()
+                        .map_err(|_| format!("E521: Number required after =: {}", arg))?;
+                    let number = match op {
+                        Some('+') => old.checked_add(n),
+                        Some('-') => old.checked_sub(n),
+                        Some('^') => old.checked_mul(n),
+                        _ => Some(n),
+                    };
+                    // out of range, like a number too large to parse
+                    let number =
+                        number.ok_or_else(|| format!("E521: Number required after =: {}", arg))?;
+                    Value::Number(number)
+                }
+                Value::Text(old) => match op {
+                    Some('+') => Value::Text(old + text),
+                    Some('^') => Value::Text(format!("{}{}", text, old)),
+                    Some(_) => Value::Text(old.replacen(text, "", 1)),
+                    None => Value::Text(text.to_string()),
+                },
+            };
+            self.put(name, value)?;
+            return Ok(None);
+        }
+        if let Some(name) = arg.strip_suffix('?') {
+            let name = full_name(name).ok_or_else(unknown)?;
+            return Ok(Some(self.show(name)));
+        }
+        if let Some(name) = arg.strip_suffix('&') {
+            let name = full_name(name).ok_or_else(unknown)?;
+            let default = Settings::default().get(name).expect("known option");
+            self.put(name, default)?;
+            return Ok(None);
+        }
+        let (name, toggle) = match arg.strip_suffix('!') {
+            Some(name) => (name, true),
+            None => (arg, false),
+        };
+        if let Some(name) = full_name(name) {
+            return match self.get(name).expect("known option") {
+                Value::Bool(b) => self.put(name, Value::Bool(!toggle || !b)).map(|_| None),
+                _ if toggle => Err(format!("E474: Invalid argument: {}", arg)),
+                _ => Ok(Some(self.show(name))),
+            };
+        }
+        let (name, value) = if let Some(name) = name.strip_prefix("no") {
+            (name, Some(false))
+        } else if let Some(name) = name.strip_prefix("inv") {
+            (name, None)
+        } else {
+            return Err(unknown());
+        };
+        let name = full_name(name).ok_or_else(unknown)?;
+        match (self.get(name).expect("known option"), value) {
+            (Value::Bool(_), Some(b)) => self.put(name, Value::Bool(b)).map(|_| None),
+            (Value::Bool(b), None) => self.put(name, Value::Bool(!b)).map(|_| None),
+            _ => Err(format!("E474: Invalid argument: {}", arg)),
+        }
+    }
+
+    /// Option as `:set` shows it, e.g. `tabstop=4` or `nowrap`.
+    pub fn show(&self, name: &str) -> String {
+        match self.get(name) {
+            Some(Value::Bool(true)) => name.to_string(),
+            Some(Value::Bool(false)) => format!("no{}", name),
+            Some(Value::Number(n)) => format!("{}={}", name, n),
+            Some(Value::Text(s)) => format!("{}={}", name, s),
+            None => String::new(),
+        }
+    }
+
+    /// Options that differ from their default, as listed by a bare `:set`.
+    pub fn changed(&self) -> Vec<String> {
+        let defaults = Settings::default();
+        OPTIONS
+            .iter()
+            .filter(|(name, _)| self.get(name) != defaults.get(name))
+            .map(|(name, _)| self.show(name))
+            .collect()
+    }
+
+    /// Columns each indent step takes up.
+    pub fn indent_width(&self) -> usize {
+        if self.shiftwidth == 0 {
+            self.tabstop
+        } else {
+            self.shiftwidth
+        }
+    }
+
+    pub fn status_style(&self) -> (Color, Color) {
+        (
+            parse_color(&self.statusfg).unwrap_or(Color::Black),
+            parse_color(&self.statusbg).unwrap_or(Color::DarkMagenta),
+        )
+    }
+}
+
+/// Parses a color name like `darkmagenta` or a `#rrggbb` value.
+pub fn parse_color(name: &str) -> Option<Color> {
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let part = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color::Rgb {
+            r: part(0)?,
+            g: part(2)?,
+            b: part(4)?,
+        });
+    }
+    let color = match name.to_ascii_lowercase().as_str() {
+        "black" => Color::Black,
+        "darkgrey" | "darkgray" => Color::DarkGrey,
+        "red" => Color::Red,
+        "darkred" => Color::DarkRed,
+        "green" => Color::Green,
+        "darkgreen" => Color::DarkGreen,
+        "yellow" => Color::Yellow,
+        "darkyellow" => Color::DarkYellow,
+        "blue" => Color::Blue,
+        "darkblue" => Color::DarkBlue,
+        "magenta" => Color::Magenta,
+        "darkmagenta" => Color::DarkMagenta,
+        "cyan" => Color::Cyan,
+        "darkcyan" => Color::DarkCyan,
+        "white" => Color::White,
+        "grey" | "gray" => Color::Grey,
+        _ => return None,
+    };
+    Some(color)
+}
@@ -0,0 +1,344 @@
+use regex::{Captures, Regex};
+use std::ops::Range;
+
+use crate::buffer::Buffer;
+use crate::search;
+
+/// Flags after a `:s` command, e.g. the `gc` of `:s/a/b/gc`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Flags {
+    /// `g`, every match on a line rather than the first.
+    pub global: bool,
+    /// `c`, ask before each substitution.
+    pub confirm: bool,
+    /// `i` and `I`, ignore or match case whatever the settings say.
+    pub ignore_case: Option<bool>,
+    /// `n`, count the matches without substituting.
+    pub count_only: bool,
+}
+
+/// A substitution as it runs, and as `:&` and `g&` repeat it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Substitute {
+    pub pattern: String,
+    pub replacement: String,
+    pub flags: Flags,
+}
+
+/// The argument of a `:s` command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Args {
+    /// Pattern and replacement, `None` for `:s` and `:&` on their own, which
+    /// repeat the last substitution.
+    pub pattern: Option<(String, String)>,
+    pub flags: Flags,
+    /// A leading `&` in the flags keeps those of the last substitution.
+    pub keep_flags: bool,
+    /// A trailing count, the number of lines to work on from the end of the
+    /// range.
+    pub count: Option<usize>,
+}
+
+/// Parses `/pattern/replacement/flags count`. Any other delimiter
+/// [`search::is_delimiter`] allows can take the place of `/`, and escaping it
+/// with a backslash puts it in the pattern or replacement.
+pub fn parse(args: &str) -> Result<Args, String> {
+    let mut rest = args;
+    let mut pattern = None;
+    if let Some(delim) = args
+        .chars()
+        .next()
+        .filter(|&c| search::is_delimiter(c) && c != '&')
+    {
+        let (from, after) = search::split_delimited(&args[delim.len_utf8()..], delim);
+        let (to, after) = search::split_delimited(after, delim);
+        pattern = Some((from, to));
+        rest = after;
+    }
+    let mut chars = rest.chars().peekable();
+    let mut flags = Flags::default();
+    let keep_flags = chars.peek() == Some(&'&');
+    if keep_flags {
+        chars.next();
+    }
+    while let Some(&c) = chars.peek() {
+        match c {
+            'g' => flags.global = true,
+            'c' => flags.confirm = true,
+            'i' => flags.ignore_case = Some(true),
+            'I' => flags.ignore_case = Some(false),
+            'n' => flags.count_only = true,
+            'e' => {}
+            _ => break,
+        }
+        chars.next();
+    }
+    let rest: String = chars.collect();
+    let rest = rest.trim();
+    let count = match rest {
+        "" => None,
+        n => match n.parse::<usize>() {
+            Ok(n) if n > 0 => Some(n),
+            Ok(_) => return Err("E939: Positive count required".into()),
+            Err(_) => return Err(format!("E488: Trailing characters: {}", rest)),
+        },
+    };
+    Ok(Args {
+        pattern,
+        flags,
+        keep_flags,
+        count,
+    })
+}
+
+/// Fills in what `args` leaves to the last substitution and search: no
+/// pattern and replacement repeats the last substitution, an empty pattern
+/// is the last search and `~` in the replacement is the last replacement.
+pub fn resolve(
+    args: &Args,
+    last: Option<&Substitute>,
+    last_search: Option<&str>,
+) -> Result<Substitute, String> {
+    let no_previous = || "E35: No previous regular expression".to_string();
+    let previous = last.map_or("", |s| s.replacement.as_str());
+    let (pattern, replacement) = match &args.pattern {
+        Some((pattern, replacement)) if pattern.is_empty() => (
+            last_search.ok_or_else(no_previous)?.to_string(),
+            with_previous(replacement, previous),
+        ),
+        Some((pattern, replacement)) => (pattern.clone(), with_previous(replacement, previous)),
+        None => {
+            let last = last.ok_or_else(no_previous)?;
+            (last.pattern.clone(), last.replacement.clone())
+        }
+    };
+    let mut flags = args.flags;
+    if let (true, Some(last)) = (args.keep_flags, last) {
+        flags.global |= last.flags.global;
+        flags.confirm |= last.flags.confirm;
+        flags.count_only |= last.flags.count_only;
+        flags.ignore_case = flags.ignore_case.or(last.flags.ignore_case);
+    }
+    Ok(Substitute {
+        pattern,
+        replacement,
+        flags,
+    })
+}
+
+/// Replaces each unescaped `~` in `replacement` with the previous one.
+fn with_previous(replacement: &str, previous: &str) -> String {
+    let mut out = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '~' => out.push_str(previous),
+            '\\' => {
+                out.push(c);
+                out.extend(chars.next());
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[derive(Clone, Copy)]
+enum Case {
+    Upper,
+    Lower,
+}
+
+/// Pushes `text` with the case `one` gives its first char and `run` the rest.
+fn push_cased(out: &mut String, text: &str, one: &mut Option<Case>, run: Option<Case>) {
+    for c in text.chars() {
+        match one.take().or(run) {
+            Some(Case::Upper) => out.extend(c.to_uppercase()),
+            Some(Case::Lower) => out.extend(c.to_lowercase()),
+            None => out.push(c),
+        }
+    }
+}
+
+/// Text that replaces the match `caps`. In `replacement`, `&` and `\0` are
+/// the whole match and `\1` to `\9` its groups, `\r` and `\n` break the
+/// line and `\t` is a tab. `\u` and `\l` change the case of the next char,
+/// `\U` and `\L` that of everything up to `\E` or `\e`.
+pub fn expand(replacement: &str, caps: &Captures) -> String {
+    let mut out = String::new();
+    let mut one = None;
+    let mut run = None;
+    let group = |n: usize| caps.get(n).map_or("", |m| m.as_str());
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        let text = match c {
+            '&' => group(0).to_string(),
+            '\\' => match chars.next() {
+                Some(d @ '0'..='9') => group(d as usize - '0' as usize).to_string(),
+                Some('r') | Some('n') => "\n".to_string(),
+                Some('t') => "\t".to_string(),
+                Some('u') => {
+                    one = Some(Case::Upper);
+                    continue;
+                }
+                Some('l') => {
+                    one = Some(Case::Lower);
+                    continue;
+                }
+                Some('U') => {
+                    run = Some(Case::Upper);
+                    continue;
+                }
+                Some('L') => {
+                    run = Some(Case::Lower);
+                    continue;
+                }
+                Some('E') | Some('e') => {
+                    one = None;
+                    run = None;
+                    continue;
+                }
+                Some(c) => c.to_string(),
+                None => "\\".to_string(),
+            },
+            c => c.to_string(),
+        };
+        push_cased(&mut out, &text, &mut one, run);
+    }
+    out
+}
+
+/// A match found by a [`Run`]: its line and the chars it covers there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub line: usize,
+    pub range: Range<usize>,
+}
+
+/// A substitution working through a range of lines one match at a time, so
+/// that confirm mode can stop and ask about each. Searching carries on after
+/// the replacement text, which is never matched again.
+#[derive(Debug)]
+pub struct Run {
+    regex: Regex,
+    replacement: String,
+    global: bool,
+    /// Where the next search starts.
+    line: usize,
+    col: usize,
+    /// Last line of the range, moved down as replacements add lines.
+    last: usize,
+    /// End of the previous match, where an empty match isn't taken again.
+    prev_end: Option<(usize, usize)>,
+    /// Matches substituted or counted, and the lines they were on.
+    pub substitutions: usize,
+    pub lines: usize,
+    /// Line of the last substitution.
+    pub last_changed: Option<usize>,
+}
+
+impl Run {
+    pub fn new(regex: Regex, replacement: String, global: bool, lines: (usize, usize)) -> Run {
+        Run {
+            regex,
+            replacement,
+            global,
+            line: lines.0,
+            col: 0,
+            last: lines.1,
+            prev_end: None,
+            substitutions: 0,
+            lines: 0,
+            last_changed: None,
+        }
+    }
+
+    /// The next match in the range, `None` when there are no more.
+    pub fn find(&self, buffer: &Buffer) -> Option<Match> {
+        let (mut line, mut col) = (self.line, self.col);
+        while line <= self.last.min(buffer.line_count() - 1) {
+            let text = buffer.line(line);
+            let len = text.chars().count();
+            while col <= len {
+                let byte = char_to_byte(&text, col);
+                let Some(m) = self.regex.find_at(&text, byte) else {
+                    break;
+                };
+                let start = col + text[byte..m.start()].chars().count();
+                let end = start + m.as_str().chars().count();
+                if m.is_empty() && self.prev_end == Some((line, start)) {
+                    col = start + 1;
+                    continue;
+                }
+                return Some(Match {
+                    line,
+                    range: start..end,
+                });
+            }
+            line += 1;
+            col = 0;
+        }
+        None
+    }
+
+    /// Replaces match `m` with the expanded replacement.
+    pub fn replace(&mut self, buffer: &mut Buffer, m: &Match) {
+        let text = buffer.line(m.line);
+        let byte = char_to_byte(&text, m.range.start);
+        let new = match self.regex.captures_at(&text, byte) {
+            Some(caps) => expand(&self.replacement, &caps),
+            None => return,
+        };
+        let start = buffer.line_to_char(m.line) + m.range.start;
+        buffer.remove(start..start + m.range.len());
+        buffer.insert(start, &new);
+        let breaks = new.matches('\n').count();
+        let end_col = match new.rfind('\n') {
+            Some(i) => new[i + 1..].chars().count(),
+            None => m.range.start + new.chars().count(),
+        };
+        self.last += breaks;
+        self.count_line(m.line);
+        self.last_changed = Some(m.line + breaks);
+        self.advance(m.line + breaks, end_col);
+    }
+
+    /// Leaves match `m` as it is and moves past it.
+    pub fn skip(&mut self, m: &Match) {
+        self.advance(m.line, m.range.end);
+    }
+
+    /// Counts match `m` without changing it, for the `n` flag.
+    pub fn count(&mut self, m: &Match) {
+        self.count_line(m.line);
+        self.last_changed = Some(m.line);
+        self.skip(m);
+    }
+
+    fn count_line(&mut self, line: usize) {
+        self.substitutions += 1;
+        if self.last_changed != Some(line) {
+            self.lines += 1;
+        }
+    }
+
+    fn advance(&mut self, line: usize, col: usize) {
+        if self.global {
+            self.line = line;
+            self.col = col;
+            self.prev_end = Some((line, col));
+        } else {
+            self.line = line + 1;
+            self.col = 0;
+        }
+    }
+
+    /// The replacement as typed, for the confirm prompt.
+    pub fn replacement(&self) -> &str {
+        &self.replacement
+    }
+}
+
+fn char_to_byte(text: &str, col: usize) -> usize {
+    text.char_indices().nth(col).map_or(text.len(), |(i, _)| i)
+}
@@ -1,26 +1,117 @@
 use crossterm::{
     cursor,
+    event::{read, Event},
+    style::{ResetColor, SetBackgroundColor, SetForegroundColor},
     terminal::{self, ClearType},
     QueueableCommand, Result,
 };
-use std::io::{stdout, Write};
+use std::io::{stdout, Stdout, Write};
 
-pub fn save_cursor_pos() {
-    let mut stdout = stdout();
-    stdout.queue(cursor::SavePosition).unwrap();
-    stdout.flush().unwrap();
+use crate::backend::Backend;
+use crate::screen::Style;
+
+/// [`Backend`] drawing to the real terminal through crossterm.
+pub struct CrosstermBackend {
+    stdout: Stdout,
 }
 
-pub fn restore_cursor_pos() {
-    let mut stdout = stdout();
-    stdout.queue(cursor::RestorePosition).unwrap();
-    stdout.flush().unwrap();
+impl std::fmt::Debug for CrosstermBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CrosstermBackend")
+    }
 }
 
-pub fn set_cursor_pos(x: u16, y: u16) {
-    let mut stdout = stdout();
-    stdout.queue(cursor::MoveTo(x, y)).unwrap();
-    stdout.flush().unwrap();
+impl Default for CrosstermBackend {
+    fn default() -> Self {
+        CrosstermBackend::new()
+    }
+}
+
+impl CrosstermBackend {
+    pub fn new() -> CrosstermBackend {
+        CrosstermBackend { stdout: stdout() }
+    }
+}
+
+impl Backend for CrosstermBackend {
+    fn size(&self) -> (usize, usize) {
+        get_term_size()
+    }
+
+    fn move_cursor(&mut self, x: usize, y: usize) -> Result<()> {
+        self.stdout.queue(cursor::MoveTo(x as u16, y as u16))?;
+        Ok(())
+    }
+
+    fn show_cursor(&mut self, visible: bool) -> Result<()> {
+        if visible {
+            self.stdout.queue(cursor::Show)?;
+        } else {
+            self.stdout.queue(cursor::Hide)?;
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.stdout.queue(terminal::Clear(ClearType::All))?;
+        Ok(())
+    }
+
+    fn set_style(&mut self, style: Style) -> Result<()> {
+        self.stdout.queue(ResetColor)?;
+        if let Some(fg) = style.fg {
+            self.stdout.queue(SetForegroundColor(fg))?;
+        }
+        if let Some(bg) = style.bg {
+            self.stdout.queue(SetBackgroundColor(bg))?;
+        }
+        Ok(())
+    }
+
+    fn print(&mut self, symbol: &str) -> Result<()> {
+        write!(self.stdout, "{}", symbol)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    fn set_clipboard(&mut self, text: &str, primary: bool) -> Result<()> {
+        write!(self.stdout, "{}", osc52(text, primary))?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    fn read_event(&mut self) -> Result<Option<Event>> {
+        read().map(Some)
+    }
+}
+
+/// OSC 52 escape asking the terminal to put `text` on the clipboard, or on
+/// the primary selection. It works over SSH since the terminal does the
+/// copying, but terminals don't answer reads of it.
+pub fn osc52(text: &str, primary: bool) -> String {
+    let selection = if primary { 'p' } else { 'c' };
+    format!("\x1b]52;{};{}\x07", selection, base64(text.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let byte = |i: usize| *chunk.get(i).unwrap_or(&0) as u32;
+        let n = byte(0) << 16 | byte(1) << 8 | byte(2);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
 }
 
 pub fn get_term_size() -> (usize, usize) {
@@ -60,9 +151,3 @@ pub fn die() -> Result<()> {
     terminal::disable_raw_mode()?;
     Ok(())
 }
-
-pub fn move_to_column(col: u16) {
-    let mut stdout = stdout();
-    stdout.queue(cursor::MoveToColumn(col)).unwrap();
-    stdout.flush().unwrap();
-}
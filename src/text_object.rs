@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use ropey::iter::Chars;
+
+use crate::buffer::Buffer;
+use crate::motion;
+
+/// What a text object selects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjectKind {
+    /// A word, or a WORD when `big`.
+    Word {
+        big: bool,
+    },
+    Sentence,
+    Paragraph,
+    /// Text between a pair of `"`, `'` or `` ` `` on the cursor line.
+    Quote(char),
+    /// Text between an opening and closing bracket, like `(` and `)`.
+    Bracket(char, char),
+    /// Text between an XML or HTML start tag and its end tag.
+    Tag,
+}
+
+/// A text object as typed after an operator or in Visual mode, `iw` or
+/// `a(`. Inner objects leave out the surrounding white space or delimiters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextObject {
+    pub kind: ObjectKind,
+    pub inner: bool,
+}
+
+impl TextObject {
+    /// The object named by the key after `i` or `a`.
+    pub fn from_key(inner: bool, key: char) -> Option<TextObject> {
+        let kind = match key {
+            'w' => ObjectKind::Word { big: false },
+            'W' => ObjectKind::Word { big: true },
+            's' => ObjectKind::Sentence,
+            'p' => ObjectKind::Paragraph,
+            '"' | '\'' | '`' => ObjectKind::Quote(key),
+            '(' | ')' | 'b' => ObjectKind::Bracket('(', ')'),
+            '[' | ']' => ObjectKind::Bracket('[', ']'),
+            '{' | '}' | 'B' => ObjectKind::Bracket('{', '}'),
+            '<' | '>' => ObjectKind::Bracket('<', '>'),
+            't' => ObjectKind::Tag,
+            _ => return None,
+        };
+        Some(TextObject { kind, inner })
+    }
+}
+
+/// Text a text object covers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selection {
+    /// Chars in a range of char offsets.
+    Chars(Range<usize>),
+    /// Whole lines, first and last.
+    Lines(usize, usize),
+}
+
+/// Selects `count` of `object` around `pos`.
+pub fn select(
+    buffer: &Buffer,
+    pos: motion::Pos,
+    count: usize,
+    object: TextObject,
+) -> Option<Selection> {
+    let count = count.max(1);
+    let inner = object.inner;
+    match object.kind {
+        ObjectKind::Word { big } => word(buffer, pos, count, big, inner),
+        ObjectKind::Sentence => sentence(buffer, pos, count, inner),
+        ObjectKind::Paragraph => paragraph(buffer, pos.0, count, inner),
+        ObjectKind::Quote(quote) => self::quote(buffer, pos, quote, inner),
+        ObjectKind::Bracket(open, close) => bracket(buffer, pos, count, open, close, inner),
+        ObjectKind::Tag => tag(buffer, pos, count, inner),
+    }
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// Word class, where blanks are a class of their own so a run of them is
+/// selected like a word.
+fn class(c: char, big: bool) -> u8 {
+    if is_blank(c) {
+        0
+    } else if big || c.is_alphanumeric() || c == '_' {
+        2
+    } else {
+        1
+    }
+}
+
+/// End of the run of same class chars starting at `i`.
+fn run_end(chars: &[char], i: usize, big: bool) -> usize {
+    let cls = class(chars[i], big);
+    i + chars[i..]
+        .iter()
+        .take_while(|&&c| class(c, big) == cls)
+        .count()
+}
+
+fn run_start(chars: &[char], i: usize, big: bool) -> usize {
+    let cls = class(chars[i], big);
+    i - chars[..i]
+        .iter()
+        .rev()
+        .take_while(|&&c| class(c, big) == cls)
+        .count()
+}
+
+/// `iw` selects the word or blanks under the cursor, `aw` adds the blanks
+/// after the word, or before it when there are none after. A count takes
+/// in more words and blanks.
+fn word(
+    buffer: &Buffer,
+    (line, col): motion::Pos,
+    count: usize,
+    big: bool,
+    inner: bool,
+) -> Option<Selection> {
+    let chars: Vec<char> = buffer.line(line).chars().collect();
+    if col >= chars.len() {
+        return None;
+    }
+    let mut start = run_start(&chars, col, big);
+    let mut end = run_end(&chars, col, big);
+    let on_blank = is_blank(chars[col]);
+    for i in 0..count {
+        if i > 0 && end < chars.len() {
+            end = run_end(&chars, end, big);
+        }
+        if !inner && end < chars.len() && (on_blank || is_blank(chars[end])) {
+            // the word after leading blanks, or the blanks after a word
+            end = run_end(&chars, end, big);
+        } else if !inner && !on_blank {
+            while start > 0 && is_blank(chars[start - 1]) {
+                start -= 1;
+            }
+        }
+    }
+    let offset = buffer.line_to_char(line);
+    Some(Selection::Chars(offset + start..offset + end))
+}
+
+/// `is` is the sentence the cursor is in without the blanks after it, `as`
+/// keeps the blanks.
+fn sentence(
+    buffer: &Buffer,
+    (line, col): motion::Pos,
+    count: usize,
+    inner: bool,
+) -> Option<Selection> {
+    let len = buffer.len_chars();
+    let idx = buffer.pos_to_char(line, col);
+    let start = (0..=idx)
+        .rev()
+        .find(|&i| motion::is_sentence_start(buffer, i))
+        .unwrap_or(0);
+    let mut next = idx;
+    for _ in 0..count {
+        next = (next + 1..len)
+            .find(|&i| motion::is_sentence_start(buffer, i))
+            .unwrap_or(len);
+    }
+    let blank = |i: usize| buffer.char(i).is_some_and(char::is_whitespace);
+    let mut end = next;
+    while end > start && blank(end - 1) {
+        end -= 1;
+    }
+    if inner {
+        return Some(Selection::Chars(start..end));
+    }
+    let same_line_blank = |i: usize| buffer.char(i).is_some_and(is_blank);
+    let mut start = start;
+    if end < next && same_line_blank(end) {
+        while same_line_blank(end) {
+            end += 1;
+        }
+    } else {
+        while start > 0 && same_line_blank(start - 1) {
+            start -= 1;
+        }
+    }
+    Some(Selection::Chars(start..end))
+}
+
+/// `ip` is the paragraph or run of blank lines the cursor is in, `ap` adds
+/// the blank lines after it, or before it when there are none after.
+fn paragraph(buffer: &Buffer, line: usize, count: usize, inner: bool) -> Option<Selection> {
+    let last = buffer.line_count() - 1;
+    let blank = |l: usize| buffer.line(l).chars().all(char::is_whitespace);
+    let run_end = |l: usize| {
+        let b = blank(l);
+        (l..=last)
+            .take_while(|&i| blank(i) == b)
+            .last()
+            .unwrap_or(l)
+    };
+    let mut first = (0..=line)
+        .rev()
+        .take_while(|&i| blank(i) == blank(line))
+        .last()
+        .unwrap_or(line);
+    let mut end = run_end(line);
+    for i in 0..count {
+        if i > 0 && end < last {
+            end = run_end(end + 1);
+        }
+        if !inner && end < last {
+            end = run_end(end + 1);
+        } else if !inner && !blank(line) {
+            while first > 0 && blank(first - 1) {
+                first -= 1;
+            }
+        }
+    }
+    Some(Selection::Lines(first, end))
+}
+
+/// Columns of the unescaped `quote` chars on a line.
+fn quote_cols(chars: &[char], quote: char) -> Vec<usize> {
+    let mut cols = Vec::new();
+    let mut escaped = false;
+    for (i, &c) in chars.iter().enumerate() {
+        if c == quote && !escaped {
+            cols.push(i);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    cols
+}
+
+/// `i"` is the text between the quotes around or after the cursor, `a"`
+/// includes the quotes and the blanks after them, or before when there are
+/// none after.
+fn quote(buffer: &Buffer, (line, col): motion::Pos, quote: char, inner: bool) -> Option<Selection> {
+    let chars: Vec<char> = buffer.line(line).chars().collect();
+    let cols = quote_cols(&chars, quote);
+    let (open, close) = cols
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, close)| col <= close)?;
+    let offset = buffer.line_to_char(line);
+    if inner {
+        return Some(Selection::Chars(offset + open + 1..offset + close));
+    }
+    let (mut start, mut end) = (open, close + 1);
+    if end < chars.len() && is_blank(chars[end]) {
+        end += chars[end..].iter().take_while(|&&c| is_blank(c)).count();
+    } else {
+        start -= chars[..start]
+            .iter()
+            .rev()
+            .take_while(|&&c| is_blank(c))
+            .count();
+    }
+    Some(Selection::Chars(offset + start..offset + end))
+}
+
+/// Finds which chars of the buffer are inside strings, a line at a time.
+/// A `'` between letters is an apostrophe, not a quote.
+struct Strings<'a> {
+    buffer: &'a Buffer,
+    lines: HashMap<usize, Vec<Range<usize>>>,
+}
+
+impl Strings<'_> {
+    /// The string char `idx` is in, as a range of char offsets.
+    fn around(&mut self, idx: usize) -> Option<Range<usize>> {
+        let buffer = self.buffer;
+        let line = buffer.char_to_line(idx);
+        let offset = buffer.line_to_char(line);
+        let spans = self.lines.entry(line).or_insert_with(|| {
+            let chars: Vec<char> = buffer.line(line).chars().collect();
+            let mut spans = Vec::new();
+            let mut i = 0;
+            while i < chars.len() {
+                let c = chars[i];
+                let apostrophe = c == '\'' && i > 0 && chars[i - 1].is_alphanumeric();
+                if matches!(c, '"' | '\'' | '`') && !apostrophe {
+                    let close = quote_cols(&chars[i + 1..], c)
+                        .first()
+                        .map(|&end| i + 1 + end);
+                    if let Some(close) = close {
+                        spans.push(offset + i..offset + close + 1);
+                        i = close;
+                    }
+                }
+                i += 1;
+            }
+            spans
+        });
+        spans.iter().find(|span| span.contains(&idx)).cloned()
+    }
+}
+
+/// `i(` is the text between the `count`th enclosing pair of brackets, `a(`
+/// includes the brackets. Brackets in strings are skipped unless the cursor
+/// is in the same string. When the brackets end and start lines, as around
+/// a block, the inner object is the lines in between.
+fn bracket(
+    buffer: &Buffer,
+    (line, col): motion::Pos,
+    count: usize,
+    open: char,
+    close: char,
+    inner: bool,
+) -> Option<Selection> {
+    let idx = buffer.pos_to_char(line, col);
+    let mut strings = Strings {
+        buffer,
+        lines: HashMap::new(),
+    };
+    let home = strings.around(idx);
+    let mut counts = |i: usize| strings.around(i) == home;
+    // the cursor on a bracket selects that pair
+    let mut from = match buffer.char(idx) {
+        Some(c) if c == open && counts(idx) => idx + 1,
+        Some(c) if c == close && counts(idx) => idx,
+        _ => idx,
+    };
+    let mut start = 0;
+    for _ in 0..count {
+        let mut depth = 0;
+        start = loop {
+            from = from.checked_sub(1)?;
+            match buffer.char(from) {
+                Some(c) if c == close && counts(from) => depth += 1,
+                Some(c) if c == open && counts(from) => {
+                    if depth == 0 {
+                        break from;
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
+        };
+    }
+    let mut end = start + 1;
+    let mut depth = 0;
+    loop {
+        match buffer.char(end)? {
+            c if c == open && counts(end) => depth += 1,
+            c if c == close && counts(end) => {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        end += 1;
+    }
+    if !inner {
+        return Some(Selection::Chars(start..end + 1));
+    }
+    let (open_line, close_line) = (buffer.char_to_line(start), buffer.char_to_line(end));
+    let opens_line = start + 1 == buffer.line_to_char(open_line) + buffer.line_len(open_line);
+    let closes_line = buffer
+        .slice(buffer.line_to_char(close_line)..end)
+        .chars()
+        .all(is_blank);
+    if opens_line && closes_line && close_line > open_line + 1 {
+        return Some(Selection::Lines(open_line + 1, close_line - 1));
+    }
+    Some(Selection::Chars(start + 1..end))
+}
+
+/// A start or end tag, as a range of char offsets.
+struct Tag {
+    range: Range<usize>,
+    name: String,
+    end: bool,
+}
+
+/// The tag starting with the `<` at `start`, if there is one. Self closing
+/// tags and things like comments don't count.
+fn tag_at(buffer: &Buffer, start: usize) -> Option<Tag> {
+    let mut chars = buffer.chars_at(start + 1).peekable();
+    let end = chars.peek() == Some(&'/');
+    if end {
+        chars.next();
+    }
+    let mut name = String::new();
+    let mut in_name = true;
+    let mut last = '<';
+    let mut len = 1 + end as usize;
+    for c in chars {
+        len += 1;
+        match c {
+            '>' if name.is_empty() || last == '/' => return None,
+            '>' => {
+                return Some(Tag {
+                    range: start..start + len,
+                    name,
+                    end,
+                })
+            }
+            '<' => return None,
+            c if in_name && (c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')) => {
+                name.push(c)
+            }
+            _ => in_name = false,
+        }
+        last = c;
+    }
+    None
+}
+
+/// The tag the char at `idx` is part of.
+fn tag_under(buffer: &Buffer, idx: usize) -> Option<Tag> {
+    let mut i = (idx + 1).min(buffer.len_chars());
+    for c in buffer.chars_at(i).reversed() {
+        i -= 1;
+        match c {
+            '<' => return tag_at(buffer, i).filter(|tag| tag.range.contains(&idx)),
+            '>' if i < idx => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// End tags after the cursor that close elements it is inside, innermost
+/// first. They are read going forward only as far as they are asked for, so
+/// the text after the cursor is read at most once.
+struct EndTags<'a> {
+    buffer: &'a Buffer,
+    chars: Chars<'a>,
+    idx: usize,
+    /// Elements started after the cursor and not closed yet.
+    open: Vec<String>,
+    found: Vec<Tag>,
+    /// How many of `found` earlier matches have passed over.
+    used: usize,
+}
+
+impl<'a> EndTags<'a> {
+    fn new(buffer: &'a Buffer, from: usize) -> Self {
+        EndTags {
+            buffer,
+            chars: buffer.chars_at(from),
+            idx: from,
+            open: Vec::new(),
+            found: Vec::new(),
+            used: 0,
+        }
+    }
+
+    /// The next end tag closing an element started before the cursor.
+    fn read(&mut self) -> Option<Tag> {
+        for c in self.chars.by_ref() {
+            self.idx += 1;
+            if c != '<' {
+                continue;
+            }
+            let Some(tag) = tag_at(self.buffer, self.idx - 1) else {
+                continue;
+            };
+            if !tag.end {
+                self.open.push(tag.name);
+            } else if let Some(p) = self.open.iter().rposition(|name| *name == tag.name) {
+                self.open.truncate(p);
+            } else {
+                return Some(tag);
+            }
+        }
+        None
+    }
+
+    /// The first end tag named `name` after the last one taken, which then
+    /// closes its element. Tags passed over can't close later ones.
+    fn take(&mut self, name: &str) -> Option<Range<usize>> {
+        let k = match self.found[self.used..]
+            .iter()
+            .position(|tag| tag.name == name)
+        {
+            Some(k) => self.used + k,
+            None => loop {
+                let tag = self.read()?;
+                self.found.push(tag);
+                if self.found[self.found.len() - 1].name == name {
+                    break self.found.len() - 1;
+                }
+            },
+        };
+        self.used = k + 1;
+        Some(self.found[k].range.clone())
+    }
+}
+
+/// `it` is the text between the `count`th enclosing start and end tags,
+/// `at` includes the tags. Start tags are looked for going back from the
+/// cursor, skipping those closed before it, and each is matched with an
+/// end tag going forward, so only the text around the pairs is read. A tag
+/// under the cursor counts as inside the element it starts or ends.
+fn tag(buffer: &Buffer, (line, col): motion::Pos, count: usize, inner: bool) -> Option<Selection> {
+    let idx = buffer.pos_to_char(line, col);
+    let (back_from, forward_from) = match tag_under(buffer, idx) {
+        Some(tag) if tag.end => (tag.range.start, tag.range.start),
+        Some(tag) => (tag.range.end, tag.range.end),
+        None => (idx, idx),
+    };
+    // end tags seen going back, closing elements the cursor isn't in
+    let mut closed: Vec<String> = Vec::new();
+    let mut ends = EndTags::new(buffer, forward_from);
+    let mut found = 0;
+    let mut i = back_from;
+    for c in buffer.chars_at(back_from).reversed() {
+        i -= 1;
+        if c != '<' {
+            continue;
+        }
+        let Some(start) = tag_at(buffer, i) else {
+            continue;
+        };
+        if start.end {
+            closed.push(start.name);
+            continue;
+        }
+        if let Some(p) = closed.iter().rposition(|name| *name == start.name) {
+            closed.truncate(p);
+            continue;
+        }
+        let Some(end) = ends.take(&start.name) else {
+            continue;
+        };
+        found += 1;
+        if found == count {
+            return Some(if inner {
+                Selection::Chars(start.range.end..end.start)
+            } else {
+                Selection::Chars(start.range.start..end.end)
+            });
+        }
+    }
+    None
+}
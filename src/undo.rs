@@ -0,0 +1,239 @@
+/// A single change to the text, in buffer char offsets.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    Insert { at: usize, text: String },
+    Remove { at: usize, text: String },
+}
+
+impl Edit {
+    pub fn at(&self) -> usize {
+        match self {
+            Edit::Insert { at, .. } | Edit::Remove { at, .. } => *at,
+        }
+    }
+
+    pub fn inverse(&self) -> Edit {
+        match self {
+            Edit::Insert { at, text } => Edit::Remove {
+                at: *at,
+                text: text.clone(),
+            },
+            Edit::Remove { at, text } => Edit::Insert {
+                at: *at,
+                text: text.clone(),
+            },
+        }
+    }
+
+    /// Folds `next` into this edit when it directly continues it, like typing
+    /// the next char or backspacing over the previous one.
+    fn merge(&mut self, next: &Edit) -> bool {
+        match (self, next) {
+            (
+                Edit::Insert { at, text },
+                Edit::Insert {
+                    at: n_at,
+                    text: n_text,
+                },
+            ) if *n_at == *at + text.chars().count() => {
+                text.push_str(n_text);
+                true
+            }
+            (
+                Edit::Remove { at, text },
+                Edit::Remove {
+                    at: n_at,
+                    text: n_text,
+                },
+            ) if *n_at == *at => {
+                text.push_str(n_text);
+                true
+            }
+            (
+                Edit::Remove { at, text },
+                Edit::Remove {
+                    at: n_at,
+                    text: n_text,
+                },
+            ) if *n_at + n_text.chars().count() == *at => {
+                text.insert_str(0, n_text);
+                *at = *n_at;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct UndoNode {
+    parent: usize,
+    /// Child to follow on redo, the most recently created or visited one.
+    redo_child: Option<usize>,
+    edits: Vec<Edit>,
+    depth: usize,
+}
+
+/// One step taken while moving through the tree.
+pub enum UndoStep {
+    Undo(Vec<Edit>),
+    Redo(Vec<Edit>),
+}
+
+/// Branching undo history.
+///
+/// Node 0 is the unchanged text and every other node is one change, stored as
+/// a child of the state it was made in. Nodes are numbered in the order they
+/// were created, so the node index doubles as the change number used by
+/// `g-`, `g+` and `:undolist`. Undoing and then making a new change starts a
+/// new branch, the old one stays reachable.
+#[derive(Debug)]
+pub struct UndoTree {
+    nodes: Vec<UndoNode>,
+    current: usize,
+    pending: Vec<Edit>,
+    group_depth: usize,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        UndoTree::new()
+    }
+}
+
+impl UndoTree {
+    pub fn new() -> UndoTree {
+        UndoTree {
+            nodes: vec![UndoNode {
+                parent: 0,
+                redo_child: None,
+                edits: Vec::new(),
+                depth: 0,
+            }],
+            current: 0,
+            pending: Vec::new(),
+            group_depth: 0,
+        }
+    }
+
+    /// Number of the change the text is currently at.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn begin_group(&mut self) {
+        self.group_depth += 1;
+    }
+
+    pub fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+        if self.group_depth == 0 {
+            self.commit();
+        }
+    }
+
+    pub fn record(&mut self, edit: Edit) {
+        let merged = match self.pending.last_mut() {
+            Some(last) => last.merge(&edit),
+            None => false,
+        };
+        if !merged {
+            self.pending.push(edit);
+        }
+        if self.group_depth == 0 {
+            self.commit();
+        }
+    }
+
+    fn commit(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let idx = self.nodes.len();
+        self.nodes.push(UndoNode {
+            parent: self.current,
+            redo_child: None,
+            edits: std::mem::take(&mut self.pending),
+            depth: self.nodes[self.current].depth + 1,
+        });
+        self.nodes[self.current].redo_child = Some(idx);
+        self.current = idx;
+    }
+
+    pub fn undo(&mut self) -> Option<UndoStep> {
+        self.commit();
+        if self.current == 0 {
+            return None;
+        }
+        let node = &self.nodes[self.current];
+        let step = UndoStep::Undo(node.edits.clone());
+        let parent = node.parent;
+        self.nodes[parent].redo_child = Some(self.current);
+        self.current = parent;
+        Some(step)
+    }
+
+    pub fn redo(&mut self) -> Option<UndoStep> {
+        self.commit();
+        let child = self.nodes[self.current].redo_child?;
+        self.current = child;
+        Some(UndoStep::Redo(self.nodes[child].edits.clone()))
+    }
+
+    /// Steps needed to reach change number `target` from the current state,
+    /// undoing up to the common ancestor and redoing down the other branch.
+    pub fn travel(&mut self, target: usize) -> Vec<UndoStep> {
+        self.commit();
+        if target >= self.nodes.len() {
+            return Vec::new();
+        }
+        let mut from = self.current;
+        let mut to = target;
+        let mut undos = Vec::new();
+        let mut redos = Vec::new();
+        while from != to {
+            if self.nodes[from].depth >= self.nodes[to].depth {
+                undos.push(from);
+                from = self.nodes[from].parent;
+            } else {
+                redos.push(to);
+                to = self.nodes[to].parent;
+            }
+        }
+        let mut steps: Vec<UndoStep> = undos
+            .into_iter()
+            .map(|n| UndoStep::Undo(self.nodes[n].edits.clone()))
+            .collect();
+        for &n in redos.iter().rev() {
+            let parent = self.nodes[n].parent;
+            self.nodes[parent].redo_child = Some(n);
+            steps.push(UndoStep::Redo(self.nodes[n].edits.clone()));
+        }
+        self.current = target;
+        steps
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Change number and number of changes from the start for every branch
+    /// tip, as listed by `:undolist`.
+    pub fn leaves(&self) -> Vec<(usize, usize)> {
+        let mut has_child = vec![false; self.nodes.len()];
+        for node in self.nodes.iter().skip(1) {
+            has_child[node.parent] = true;
+        }
+        self.nodes
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter(|(i, _)| !has_child[*i])
+            .map(|(i, node)| (i, node.depth))
+            .collect()
+    }
+}
@@ -0,0 +1,56 @@
+use fred::buffer::{Buffer, LineEnding};
+use fred::fred_file::{read_buffer, write_buffer};
+use std::io::{self, Read};
+
+/// Hands out its bytes one at a time, so every char and line ending is
+/// split across reads.
+struct Trickle<'a>(&'a [u8]);
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some((&first, rest)) = self.0.split_first() else {
+            return Ok(0);
+        };
+        buf[0] = first;
+        self.0 = rest;
+        Ok(1)
+    }
+}
+
+#[test]
+fn reading_in_pieces_keeps_chars_and_line_endings() {
+    let buffer = Buffer::from_reader(Trickle("héllo\r\nwörld\r\n".as_bytes())).unwrap();
+    assert_eq!(buffer.line_ending, LineEnding::CrLf);
+    assert!(buffer.trailing_newline);
+    assert_eq!(buffer.line_count(), 2);
+    assert_eq!(buffer.line(0), "héllo");
+    assert_eq!(buffer.line(1), "wörld");
+    let lone = Buffer::from_reader(Trickle(b"a\rb\nc")).unwrap();
+    assert_eq!(lone.line_ending, LineEnding::Lf);
+    assert!(!lone.trailing_newline);
+    assert_eq!(lone.line_count(), 2);
+    assert_eq!(lone.line(0), "a\rb");
+}
+
+#[test]
+fn only_newlines_end_lines() {
+    let text = "a\x0cb\nc\u{2028}d\re\u{85}\n";
+    let buffer = Buffer::from_string(text.to_string());
+    assert_eq!(buffer.line_count(), 2);
+    assert_eq!(buffer.line(0), "a\x0cb");
+    assert_eq!(buffer.line(1), "c\u{2028}d\re\u{85}");
+    let path = std::env::temp_dir().join(format!("fred-breaks-{}.txt", std::process::id()));
+    write_buffer(&path, &buffer).unwrap();
+    let reread = read_buffer(&path).unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(written, text);
+    assert_eq!(reread.line_count(), 2);
+}
+
+#[test]
+fn invalid_utf8_is_an_error() {
+    let err = Buffer::from_reader(&b"ok\n\xff\xfe"[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(Buffer::from_reader(Trickle(b"cut \xc3")).is_err());
+}
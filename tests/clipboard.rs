@@ -0,0 +1,73 @@
+mod common;
+
+use common::{editor, editor_with, type_keys};
+use fred::clipboard;
+use fred::register::RegisterKind;
+use fred::settings::Settings;
+use fred::term::osc52;
+use std::path::PathBuf;
+
+/// Writes a fake `xclip` style helper that keeps the clipboard in a file
+/// next to it: `-i` copies stdin there and `-o` prints it.
+fn fake_helper(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fred-clip-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("clip.sh");
+    std::fs::write(
+        &script,
+        "if [ \"$1\" = -o ]; then cat \"$0.data\"; else cat > \"$0.data\"; fi\n",
+    )
+    .unwrap();
+    script
+}
+
+#[test]
+fn osc52_encodes_the_text_in_base64() {
+    assert_eq!(osc52("hello", false), "\x1b]52;c;aGVsbG8=\x07");
+    assert_eq!(osc52("hi\n", true), "\x1b]52;p;aGkK\x07");
+    assert_eq!(osc52("", false), "\x1b]52;c;\x07");
+}
+
+#[test]
+fn helpers_copy_and_paste_through_the_shell() {
+    let script = fake_helper("helpers");
+    let copy = format!("sh {} -i", script.display());
+    let paste = format!("sh {} -o", script.display());
+    clipboard::copy(&copy, "some text\n").unwrap();
+    assert_eq!(clipboard::paste(&paste).unwrap(), "some text\n");
+    assert!(clipboard::copy("exit 3", "x").is_err());
+}
+
+#[test]
+fn plus_register_copies_through_the_terminal() {
+    let (mut ed, backend) = editor("one two\nthree", 40, 10);
+    type_keys(&mut ed, &backend, "\"+yw");
+    assert_eq!(backend.clipboard(), Some("one ".to_string()));
+    type_keys(&mut ed, &backend, "j\"*yy");
+    assert_eq!(backend.clipboard(), Some("three\n".to_string()));
+    type_keys(&mut ed, &backend, "\"+P");
+    assert_eq!(ed.buffer.line(1), "one three");
+}
+
+#[test]
+fn helper_is_used_for_both_directions() {
+    let script = fake_helper("editor");
+    let settings = Settings {
+        clipcopy: format!("sh {} -i", script.display()),
+        clippaste: format!("sh {} -o", script.display()),
+        ..Settings::default()
+    };
+    let (mut ed, backend) = editor_with("alpha\nbeta", 40, 10, settings);
+    type_keys(&mut ed, &backend, "\"+yy");
+    assert_eq!(backend.clipboard(), None);
+    assert_eq!(
+        std::fs::read_to_string(script.with_extension("sh.data")).unwrap(),
+        "alpha\n"
+    );
+    type_keys(&mut ed, &backend, "j\"+p");
+    assert_eq!(ed.buffer.line(2), "alpha");
+    std::fs::write(script.with_extension("sh.data"), "from outside").unwrap();
+    type_keys(&mut ed, &backend, "\"+P");
+    assert_eq!(ed.buffer.line(2), "from outsidealpha");
+    assert_eq!(ed.read_register('+').unwrap().kind, RegisterKind::Char);
+}
@@ -0,0 +1,28 @@
+use fred::backend::VirtualBackend;
+use fred::buffer::Buffer;
+use fred::editor::Editor;
+use fred::settings::Settings;
+
+/// An editor on a `width` by `height` virtual terminal showing `text`.
+pub fn editor(text: &str, width: usize, height: usize) -> (Editor, VirtualBackend) {
+    editor_with(text, width, height, Settings::default())
+}
+
+pub fn editor_with(
+    text: &str,
+    width: usize,
+    height: usize,
+    settings: Settings,
+) -> (Editor, VirtualBackend) {
+    let backend = VirtualBackend::new(width, height);
+    let mut ed = Editor::new(Box::new(backend.clone()), settings);
+    ed.load_buffer(Buffer::from_string(text.to_string()));
+    ed.render().unwrap();
+    (ed, backend)
+}
+
+/// Feeds `keys`, in the notation `parse_keys` reads, to the editor.
+pub fn type_keys(ed: &mut Editor, backend: &VirtualBackend, keys: &str) {
+    backend.push_keys(keys);
+    ed.handle_input().unwrap();
+}
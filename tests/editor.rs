@@ -0,0 +1,1020 @@
+mod common;
+
+use common::{editor, type_keys};
+use crossterm::style::Color;
+use fred::buffer::Buffer;
+use fred::editor::Editor;
+use fred::register::RegisterKind;
+
+fn register(ed: &Editor, name: char) -> String {
+    ed.read_register(name).map(|r| r.text).unwrap_or_default()
+}
+
+fn lines(ed: &Editor) -> Vec<String> {
+    (0..ed.buffer.line_count())
+        .map(|i| ed.buffer.line(i))
+        .collect()
+}
+
+#[test]
+fn typing_in_insert_mode_is_drawn() {
+    let (mut ed, backend) = editor("", 40, 10);
+    type_keys(&mut ed, &backend, "ihello<Esc>");
+    assert_eq!(backend.row_text(0), " 1 hello");
+    assert!(backend
+        .row_text(9)
+        .starts_with(" NORMAL | Line: 1/1 Col: 5"));
+    assert_eq!(backend.cursor(), (7, 0));
+}
+
+#[test]
+fn enter_and_backspace_split_and_join_lines() {
+    let (mut ed, backend) = editor("", 40, 10);
+    type_keys(&mut ed, &backend, "ione<CR>two<Esc>");
+    assert_eq!(lines(&ed), vec!["one", "two"]);
+    type_keys(&mut ed, &backend, "0i<BS><Esc>");
+    assert_eq!(lines(&ed), vec!["onetwo"]);
+    assert_eq!(backend.row_text(0), " 1 onetwo");
+    assert_eq!(backend.row_text(1), "");
+}
+
+#[test]
+fn vertical_moves_remember_the_column() {
+    let (mut ed, backend) = editor("abcdef\nab\nabcdef", 40, 10);
+    type_keys(&mut ed, &backend, "llllj");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 1));
+    type_keys(&mut ed, &backend, "j");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (2, 4));
+    assert_eq!(backend.cursor(), (7, 2));
+}
+
+#[test]
+fn moving_past_the_bottom_scrolls() {
+    let text: Vec<String> = (1..=20).map(|n| format!("line {}", n)).collect();
+    let (mut ed, backend) = editor(&text.join("\n"), 40, 10);
+    type_keys(&mut ed, &backend, "G");
+    assert_eq!(backend.row_text(0), " 12 line 12");
+    assert_eq!(backend.row_text(8), " 20 line 20");
+    assert_eq!(backend.cursor().1, 8);
+    type_keys(&mut ed, &backend, "gg");
+    assert_eq!(backend.row_text(0), "  1 line 1");
+    assert_eq!(backend.cursor(), (4, 0));
+}
+
+#[test]
+fn undo_reverts_a_whole_insert_session() {
+    let (mut ed, backend) = editor("abc", 40, 10);
+    type_keys(&mut ed, &backend, "ixyz<Esc>");
+    assert_eq!(lines(&ed), vec!["xyzabc"]);
+    type_keys(&mut ed, &backend, "u");
+    assert_eq!(lines(&ed), vec!["abc"]);
+    assert_eq!(backend.row_text(0), " 1 abc");
+    type_keys(&mut ed, &backend, "<C-r>");
+    assert_eq!(lines(&ed), vec!["xyzabc"]);
+}
+
+#[test]
+fn undo_tree_keeps_abandoned_branches() {
+    let (mut ed, backend) = editor("", 40, 10);
+    type_keys(&mut ed, &backend, "ione<Esc>uitwo<Esc>");
+    assert_eq!(lines(&ed), vec!["two"]);
+    type_keys(&mut ed, &backend, "g-");
+    assert_eq!(lines(&ed), vec!["one"]);
+    type_keys(&mut ed, &backend, "g-");
+    assert_eq!(lines(&ed), vec![""]);
+    type_keys(&mut ed, &backend, "g+g+");
+    assert_eq!(lines(&ed), vec!["two"]);
+}
+
+#[test]
+fn ex_range_delete() {
+    let (mut ed, backend) = editor("1\n2\n3\n4\n5", 40, 10);
+    type_keys(&mut ed, &backend, ":2,4d<CR>");
+    assert_eq!(lines(&ed), vec!["1", "5"]);
+    type_keys(&mut ed, &backend, ":$<CR>");
+    assert_eq!(ed.cursor.line, 1);
+    for range in &[
+        ".+9223372036854775807",
+        "9223372036854775808-1",
+        ".-9223372036854775807",
+    ] {
+        type_keys(&mut ed, &backend, &format!(":{}<CR>", range));
+        assert_eq!(backend.row_text(9).trim_end(), "E16: Invalid range");
+    }
+}
+
+#[test]
+fn command_line_is_echoed_and_editable() {
+    let (mut ed, backend) = editor("", 40, 10);
+    type_keys(&mut ed, &backend, ":wqx<Left><BS>");
+    assert_eq!(backend.row_text(9), ":wx");
+    assert_eq!(backend.cursor(), (2, 9));
+    type_keys(&mut ed, &backend, "<Esc>");
+    assert!(backend.row_text(9).starts_with(" NORMAL"));
+}
+
+#[test]
+fn unknown_commands_report_an_error() {
+    let (mut ed, backend) = editor("", 40, 10);
+    type_keys(&mut ed, &backend, ":frobnicate<CR>");
+    assert_eq!(
+        backend.row_text(9),
+        "E492: Not an editor command: frobnicate"
+    );
+}
+
+#[test]
+fn write_keeps_line_endings() {
+    let path = std::env::temp_dir().join(format!("fred-write-{}.txt", std::process::id()));
+    let (mut ed, backend) = editor("", 40, 10);
+    ed.load_buffer(Buffer::from_string("one\r\ntwo".to_string()));
+    type_keys(&mut ed, &backend, "iX<Esc>");
+    type_keys(&mut ed, &backend, &format!(":w {}<CR>", path.display()));
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(written, "Xone\r\ntwo");
+    let name = path.file_name().unwrap().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    assert!(!temp.exists());
+    assert!(!ed.buffer.modified);
+}
+
+#[test]
+fn writing_over_another_file_needs_a_bang() {
+    let path = std::env::temp_dir().join(format!("fred-exists-{}.txt", std::process::id()));
+    std::fs::write(&path, "keep").unwrap();
+    let (mut ed, backend) = editor("new", 40, 10);
+    type_keys(&mut ed, &backend, &format!(":w {}<CR>", path.display()));
+    assert!(backend.row_text(9).starts_with("E13"));
+    type_keys(&mut ed, &backend, &format!(":sav {}<CR>", path.display()));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep");
+    type_keys(&mut ed, &backend, &format!(":sav! {}<CR>", path.display()));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+    type_keys(&mut ed, &backend, "Aer<Esc>:w<CR>");
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(written, "newer");
+}
+
+#[test]
+fn unreadable_files_keep_the_current_name() {
+    let (mut ed, _backend) = editor("text", 40, 10);
+    let dir = std::env::temp_dir();
+    let err = ed.read_from_file(dir.display().to_string()).unwrap_err();
+    assert!(err.starts_with("E484"));
+    assert_eq!(ed.file_name, None);
+    assert_eq!(lines(&ed), vec!["text"]);
+    let missing = dir.join(format!("fred-missing-{}.txt", std::process::id()));
+    ed.read_from_file(missing.display().to_string()).unwrap();
+    assert_eq!(ed.file_name, Some(missing.display().to_string()));
+    assert_eq!(lines(&ed), vec![""]);
+    assert!(ed.message.ends_with("[New]"));
+}
+
+#[test]
+fn quit_refuses_to_drop_changes() {
+    let (mut ed, backend) = editor("", 40, 10);
+    type_keys(&mut ed, &backend, "ia<Esc>:q<CR>");
+    assert!(!ed.quit);
+    assert!(backend.row_text(9).starts_with("E37"));
+    type_keys(&mut ed, &backend, ":q!<CR>");
+    assert!(ed.quit);
+}
+
+#[test]
+fn only_changed_cells_are_redrawn() {
+    let (mut ed, backend) = editor("abc", 40, 10);
+    let before = backend.screen();
+    type_keys(&mut ed, &backend, "l");
+    let after = backend.screen();
+    let changed = after.changes(&before);
+    assert!(!changed.is_empty());
+    assert!(changed.iter().all(|(_, y, _)| *y == 9));
+}
+
+#[test]
+fn resize_moves_the_status_line_and_keeps_the_cursor_visible() {
+    let text: Vec<String> = (1..=30).map(|n| format!("line {}", n)).collect();
+    let (mut ed, backend) = editor(&text.join("\n"), 40, 20);
+    type_keys(&mut ed, &backend, ":15<CR>");
+    assert_eq!(backend.cursor().1, 14);
+
+    backend.resize(30, 8);
+    ed.handle_input().unwrap();
+    assert!(backend.row_text(7).starts_with(" NORMAL | Line: 15/30"));
+    assert_eq!(backend.row_text(6), " 15 line 15");
+    assert_eq!(backend.cursor(), (4, 6));
+    assert_eq!(ed.v_draw_region, (8, 15));
+    assert_eq!(ed.h_draw_region, (0, 26));
+
+    backend.resize(40, 20);
+    ed.handle_input().unwrap();
+    assert!(backend.row_text(19).starts_with(" NORMAL"));
+    assert_eq!(backend.row_text(0), "  9 line 9");
+    assert_eq!(backend.row_text(18), " 27 line 27");
+    assert_eq!(backend.cursor(), (4, 6));
+}
+
+#[test]
+fn long_lines_scroll_sideways_with_the_cursor() {
+    let (mut ed, backend) = editor("abcdefghijklmnopqrst\nxy", 13, 5);
+    assert_eq!(backend.row_text(0), " 1 abcdefghij");
+    type_keys(&mut ed, &backend, "lllllllllll");
+    assert_eq!(ed.h_draw_region, (2, 12));
+    assert_eq!(backend.row_text(0), " 1 cdefghijkl");
+    assert_eq!(backend.row_text(1), " 2");
+    assert_eq!(backend.cursor(), (12, 0));
+    type_keys(&mut ed, &backend, "0");
+    assert_eq!(backend.row_text(0), " 1 abcdefghij");
+}
+
+#[test]
+fn z_commands_scroll_horizontally() {
+    let (mut ed, backend) = editor("abcdefghijklmnopqrst", 13, 5);
+    type_keys(&mut ed, &backend, "zl");
+    assert_eq!(backend.row_text(0), " 1 bcdefghijk");
+    assert_eq!(ed.cursor.col, 1);
+    type_keys(&mut ed, &backend, "lllllzs");
+    assert_eq!(backend.row_text(0), " 1 ghijklmnop");
+    assert_eq!(backend.cursor(), (3, 0));
+    type_keys(&mut ed, &backend, "zh");
+    assert_eq!(backend.row_text(0), " 1 fghijklmno");
+    assert_eq!(ed.cursor.col, 6);
+    type_keys(&mut ed, &backend, "lllllze");
+    assert_eq!(backend.row_text(0), " 1 cdefghijkl");
+    assert_eq!(backend.cursor(), (12, 0));
+}
+
+#[test]
+fn wrap_shows_long_lines_on_several_rows() {
+    let (mut ed, backend) = editor("abcdefghijklmnopqrstuvw\nxy", 13, 6);
+    type_keys(&mut ed, &backend, ":set wrap<CR>");
+    assert_eq!(backend.row_text(0), " 1 abcdefghij");
+    assert_eq!(backend.row_text(1), " ↪ klmnopqrst");
+    assert_eq!(backend.row_text(2), " ↪ uvw");
+    assert_eq!(backend.row_text(3), " 2 xy");
+    type_keys(&mut ed, &backend, "j");
+    assert_eq!(backend.cursor(), (3, 3));
+    type_keys(&mut ed, &backend, ":set nowrap<CR>");
+    assert_eq!(backend.row_text(1), " 2 xy");
+}
+
+#[test]
+fn gj_and_gk_move_by_screen_rows() {
+    let (mut ed, backend) = editor("abcdefghijklmnopqrstuvw\nxy", 13, 6);
+    type_keys(&mut ed, &backend, ":set wrap<CR>llgj");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (0, 12));
+    assert_eq!(backend.cursor(), (5, 1));
+    type_keys(&mut ed, &backend, "gjgj");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 1));
+    type_keys(&mut ed, &backend, "gk");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (0, 22));
+    type_keys(&mut ed, &backend, "k");
+    assert_eq!(ed.cursor.line, 0);
+    type_keys(&mut ed, &backend, "j");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 1));
+}
+
+#[test]
+fn wrapped_view_scrolls_by_whole_lines() {
+    let long = "x".repeat(25);
+    let text = format!("a\n{}\nb\nc\nd", long);
+    let (mut ed, backend) = editor(&text, 13, 5);
+    type_keys(&mut ed, &backend, ":set wrap<CR>G");
+    assert_eq!(ed.v_draw_region, (2, 5));
+    assert_eq!(backend.row_text(0), " 3 b");
+    assert_eq!(backend.cursor(), (3, 2));
+    type_keys(&mut ed, &backend, "kkk");
+    assert_eq!(backend.row_text(0), " 2 xxxxxxxxxx");
+    assert_eq!(backend.row_text(3), " 3 b");
+}
+
+#[test]
+fn set_changes_options_at_runtime() {
+    let (mut ed, backend) = editor("abc", 40, 10);
+    type_keys(&mut ed, &backend, ":set ts?<CR>");
+    assert_eq!(backend.row_text(9), "tabstop=4");
+    type_keys(&mut ed, &backend, ":set nonumber<CR>");
+    assert_eq!(backend.row_text(0), "abc");
+    assert_eq!(backend.cursor(), (0, 0));
+    type_keys(&mut ed, &backend, ":set bogus<CR>");
+    assert_eq!(backend.row_text(9), "E518: Unknown option: bogus");
+}
+
+#[test]
+fn tab_expands_to_the_next_tabstop() {
+    let (mut ed, backend) = editor("", 40, 10);
+    type_keys(&mut ed, &backend, "ia<Tab>b<Esc>");
+    assert_eq!(lines(&ed), vec!["a   b"]);
+    type_keys(&mut ed, &backend, ":set ts=8<CR>i<Tab><Esc>");
+    assert_eq!(lines(&ed), vec!["a       b"]);
+}
+
+#[test]
+fn scrolloff_keeps_context_around_the_cursor() {
+    let text: Vec<String> = (1..=30).map(|n| n.to_string()).collect();
+    let (mut ed, backend) = editor(&text.join("\n"), 40, 11);
+    type_keys(&mut ed, &backend, ":set so=3<CR>jjjjjjj");
+    assert_eq!(ed.v_draw_region, (1, 11));
+    type_keys(&mut ed, &backend, "G");
+    assert_eq!(ed.v_draw_region, (20, 30));
+    type_keys(&mut ed, &backend, "gg");
+    assert_eq!(ed.v_draw_region, (0, 10));
+}
+
+#[test]
+fn tabs_are_drawn_to_the_next_tabstop() {
+    let (mut ed, backend) = editor("\tab\tc\nabcdefghij", 40, 10);
+    assert_eq!(backend.row_text(0), " 1     ab  c");
+    assert_eq!(backend.cursor(), (6, 0));
+    type_keys(&mut ed, &backend, "lll");
+    assert_eq!(backend.cursor(), (10, 0));
+    type_keys(&mut ed, &backend, "j");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 7));
+    type_keys(&mut ed, &backend, "hk");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (0, 3));
+    type_keys(&mut ed, &backend, "i");
+    assert_eq!(backend.cursor(), (9, 0));
+}
+
+#[test]
+fn noexpandtab_inserts_a_real_tab() {
+    let (mut ed, backend) = editor("", 40, 10);
+    type_keys(&mut ed, &backend, ":set noet ts=8<CR>ia<Tab>b<Esc>");
+    assert_eq!(lines(&ed), vec!["a\tb"]);
+    assert_eq!(backend.row_text(0), " 1 a       b");
+    assert_eq!(backend.cursor(), (11, 0));
+}
+
+#[test]
+fn tabs_scroll_horizontally_by_display_column() {
+    let (mut ed, backend) = editor("\t\t\tx", 13, 5);
+    type_keys(&mut ed, &backend, "lll");
+    assert_eq!(ed.h_draw_region, (3, 13));
+    assert_eq!(backend.row_text(0), " 1          x");
+    assert_eq!(backend.cursor(), (12, 0));
+}
+
+#[test]
+fn control_chars_are_drawn_in_caret_notation() {
+    let (mut ed, backend) = editor("a\x1b[2Jb\x07\x7f\x0c", 40, 10);
+    assert_eq!(backend.row_text(0), " 1 a^[[2Jb^G^?^L");
+    type_keys(&mut ed, &backend, "ll");
+    assert_eq!(backend.cursor(), (6, 0));
+    type_keys(&mut ed, &backend, "$");
+    assert_eq!(backend.cursor(), (14, 0));
+}
+
+#[test]
+fn wide_chars_take_two_cells() {
+    let (mut ed, backend) = editor("a中文b\nabcdef", 40, 10);
+    assert_eq!(backend.row_text(0), " 1 a中文b");
+    type_keys(&mut ed, &backend, "l");
+    assert_eq!(backend.cursor(), (4, 0));
+    type_keys(&mut ed, &backend, "l");
+    assert_eq!(backend.cursor(), (6, 0));
+    type_keys(&mut ed, &backend, "j");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 3));
+    type_keys(&mut ed, &backend, "lk");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (0, 2));
+}
+
+#[test]
+fn graphemes_move_and_delete_as_one() {
+    let (mut ed, backend) = editor("e\u{301}👍🏽x", 40, 10);
+    type_keys(&mut ed, &backend, "l");
+    assert_eq!(ed.cursor.col, 2);
+    assert_eq!(backend.cursor(), (4, 0));
+    type_keys(&mut ed, &backend, "l");
+    assert_eq!(ed.cursor.col, 4);
+    assert_eq!(backend.cursor(), (6, 0));
+    type_keys(&mut ed, &backend, "i<BS><BS><Esc>");
+    assert_eq!(lines(&ed), vec!["x"]);
+}
+
+#[test]
+fn wide_char_cut_by_the_edge_is_blanked() {
+    let (mut ed, backend) = editor("abcdefghi中x", 13, 5);
+    assert_eq!(backend.row_text(0), " 1 abcdefghi");
+    type_keys(&mut ed, &backend, "lllllllllll");
+    assert_eq!(ed.h_draw_region, (2, 12));
+    assert_eq!(backend.row_text(0), " 1 cdefghi中x");
+    type_keys(&mut ed, &backend, "zl");
+    assert_eq!(backend.row_text(0), " 1 defghi中x");
+}
+
+#[test]
+fn replacing_a_wide_char_redraws_both_cells() {
+    let (mut ed, backend) = editor("中x", 40, 10);
+    type_keys(&mut ed, &backend, "li<BS>ab<Esc>");
+    assert_eq!(backend.row_text(0), " 1 abx");
+    type_keys(&mut ed, &backend, "u");
+    assert_eq!(backend.row_text(0), " 1 中x");
+}
+
+#[test]
+fn counts_repeat_motions() {
+    let text: Vec<String> = (1..=10).map(|n| format!("line {}", n)).collect();
+    let (mut ed, backend) = editor(&text.join("\n"), 40, 20);
+    type_keys(&mut ed, &backend, "5j3l");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (5, 3));
+    type_keys(&mut ed, &backend, "2G$");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 5));
+    type_keys(&mut ed, &backend, "j");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (2, 5));
+}
+
+#[test]
+fn delete_with_counts_and_motions() {
+    let (mut ed, backend) = editor("one two three four\na\nb\nc\nd", 40, 20);
+    type_keys(&mut ed, &backend, "d2w");
+    assert_eq!(lines(&ed)[0], "three four");
+    type_keys(&mut ed, &backend, "wd$");
+    assert_eq!(lines(&ed)[0], "three ");
+    type_keys(&mut ed, &backend, "j2dd");
+    assert_eq!(lines(&ed), vec!["three ", "c", "d"]);
+    assert_eq!(register(&ed, '"'), "a\nb\n");
+    type_keys(&mut ed, &backend, "u");
+    assert_eq!(lines(&ed), vec!["three ", "a", "b", "c", "d"]);
+}
+
+#[test]
+fn change_word_and_line() {
+    let (mut ed, backend) = editor("foo bar baz\n  indented", 40, 20);
+    type_keys(&mut ed, &backend, "wcwxy<Esc>");
+    assert_eq!(lines(&ed)[0], "foo xy baz");
+    type_keys(&mut ed, &backend, "jccnew<Esc>");
+    assert_eq!(lines(&ed), vec!["foo xy baz", "new"]);
+    type_keys(&mut ed, &backend, "u");
+    assert_eq!(lines(&ed), vec!["foo xy baz", "  indented"]);
+}
+
+#[test]
+fn dw_on_the_last_word_deletes_to_the_end() {
+    let (mut ed, backend) = editor(
+        "one two
+x y",
+        40,
+        20,
+    );
+    type_keys(&mut ed, &backend, "G$dw");
+    assert_eq!(lines(&ed), vec!["one two", "x "]);
+    type_keys(&mut ed, &backend, "ggwcwsix<Esc>");
+    assert_eq!(lines(&ed), vec!["one six", "x "]);
+    type_keys(&mut ed, &backend, "G0w");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 1));
+}
+
+#[test]
+fn yank_into_named_register() {
+    let (mut ed, backend) = editor("alpha beta\ngamma", 40, 20);
+    type_keys(&mut ed, &backend, "\"ayejyy");
+    assert_eq!(register(&ed, 'a'), "alpha");
+    assert_eq!(register(&ed, '"'), "gamma\n");
+    assert_eq!(lines(&ed), vec!["alpha beta", "gamma"]);
+}
+
+#[test]
+fn shift_and_format_lines() {
+    let (mut ed, backend) = editor("a\nb\n      c", 40, 20);
+    type_keys(&mut ed, &backend, ">j");
+    assert_eq!(lines(&ed), vec!["    a", "    b", "      c"]);
+    type_keys(&mut ed, &backend, "jj<<");
+    assert_eq!(lines(&ed)[2], "  c");
+    type_keys(&mut ed, &backend, "=k");
+    assert_eq!(lines(&ed), vec!["    a", "    b", "    c"]);
+    type_keys(&mut ed, &backend, "gg3<<");
+    assert_eq!(lines(&ed), vec!["a", "b", "c"]);
+    assert_eq!(backend.row_text(19), "3 lines <ed 1 time");
+}
+
+#[test]
+fn pending_keys_show_in_the_status_line() {
+    let (mut ed, backend) = editor("abc", 60, 10);
+    type_keys(&mut ed, &backend, "\"a2d");
+    assert!(backend.row_text(9).ends_with("\"a2d"));
+    type_keys(&mut ed, &backend, "x");
+    assert_eq!(backend.row_text(9), "Unknown command: \"a2dx");
+    assert_eq!(lines(&ed), vec!["abc"]);
+}
+
+#[test]
+fn operators_reuse_motions() {
+    let (mut ed, backend) = editor("call(a, b, c);\nx", 40, 10);
+    type_keys(&mut ed, &backend, "f(d%");
+    assert_eq!(lines(&ed), vec!["call;", "x"]);
+    type_keys(&mut ed, &backend, "u0dt,");
+    assert_eq!(lines(&ed), vec![", b, c);", "x"]);
+    type_keys(&mut ed, &backend, "u0t,;d,");
+    assert_eq!(lines(&ed), vec!["call(a,b, c);", "x"]);
+    type_keys(&mut ed, &backend, "$dF(");
+    assert_eq!(lines(&ed), vec!["call;", "x"]);
+}
+
+#[test]
+fn paragraph_operators_take_whole_lines() {
+    let (mut ed, backend) = editor("one\ntwo\n\nthree\nfour", 40, 10);
+    type_keys(&mut ed, &backend, "d}");
+    assert_eq!(lines(&ed), vec!["", "three", "four"]);
+    type_keys(&mut ed, &backend, "jld}");
+    assert_eq!(lines(&ed), vec!["", "t"]);
+}
+
+#[test]
+fn paging_moves_the_view_and_cursor() {
+    let text: Vec<String> = (1..=50).map(|n| format!("line {}", n)).collect();
+    let (mut ed, backend) = editor(&text.join("\n"), 40, 11);
+    type_keys(&mut ed, &backend, "<C-d>");
+    assert_eq!(ed.v_draw_region.0, 5);
+    assert_eq!(ed.cursor.line, 5);
+    type_keys(&mut ed, &backend, "<C-f>");
+    assert_eq!(ed.v_draw_region.0, 13);
+    assert_eq!(ed.cursor.line, 13);
+    type_keys(&mut ed, &backend, "L");
+    assert_eq!(ed.cursor.line, 22);
+    type_keys(&mut ed, &backend, "<C-b>");
+    assert_eq!(ed.v_draw_region.0, 5);
+    assert_eq!(ed.cursor.line, 14);
+    type_keys(&mut ed, &backend, "H");
+    assert_eq!(ed.cursor.line, 5);
+}
+
+#[test]
+fn huge_counts_stop_at_the_buffer_edges() {
+    let (mut ed, backend) = editor("one\ntwo\nthree\nfour", 40, 10);
+    let huge = "99999999999999999999";
+    type_keys(&mut ed, &backend, &format!("{}j", huge));
+    assert_eq!(ed.cursor.line, 3);
+    for keys in ["_", "$", "<C-d>", "<C-f>", "H", "<C-o>", "<C-i>", "g;"] {
+        type_keys(&mut ed, &backend, &format!("gg{}{}", huge, keys));
+    }
+    type_keys(&mut ed, &backend, &format!("j{}dd", huge));
+    assert_eq!(lines(&ed), vec!["one"]);
+}
+
+#[test]
+fn text_objects_with_operators_and_visual_mode() {
+    let (mut ed, backend) = editor("let x = f(a, (b));\nnext", 40, 10);
+    type_keys(&mut ed, &backend, "fadi(");
+    assert_eq!(lines(&ed), vec!["let x = f();", "next"]);
+    type_keys(&mut ed, &backend, "u0ciwconst<Esc>");
+    assert_eq!(lines(&ed), vec!["const x = f(a, (b));", "next"]);
+    type_keys(&mut ed, &backend, "fbva(y");
+    assert_eq!(register(&ed, '"'), "(b)");
+    type_keys(&mut ed, &backend, "va(d");
+    assert_eq!(lines(&ed), vec!["const x = f(a, );", "next"]);
+    assert!(matches!(ed.mode, fred::editor::EditorMode::Normal));
+    type_keys(&mut ed, &backend, "0vlld");
+    assert_eq!(lines(&ed), vec!["st x = f(a, );", "next"]);
+}
+
+#[test]
+fn visual_selections_are_highlighted() {
+    let (mut ed, backend) = editor("abcdef\nghijkl\nmnopqr", 40, 10);
+    type_keys(&mut ed, &backend, "lvjl");
+    assert!(backend.row_text(9).starts_with(" VISUAL |"));
+    let selected = |row: usize, col: usize| {
+        let screen = backend.screen();
+        screen.get(3 + col, row).unwrap().style.bg.is_some()
+    };
+    assert!(!selected(0, 0));
+    assert!(selected(0, 1) && selected(0, 5) && selected(0, 6));
+    assert!(selected(1, 0) && selected(1, 2) && !selected(1, 3));
+    type_keys(&mut ed, &backend, "<C-v>");
+    assert!(backend.row_text(9).starts_with(" VISUAL BLOCK |"));
+    assert!(!selected(0, 0) && selected(0, 1) && selected(0, 2) && !selected(0, 3));
+    type_keys(&mut ed, &backend, "V");
+    assert!(selected(0, 0) && selected(1, 5) && !selected(2, 0));
+    type_keys(&mut ed, &backend, "<Esc>");
+    assert!(!selected(0, 1));
+}
+
+#[test]
+fn visual_operators_act_on_the_selection() {
+    let (mut ed, backend) = editor("one two\nthree four\nfive six", 40, 10);
+    type_keys(&mut ed, &backend, "wvjd");
+    assert_eq!(lines(&ed), vec!["one  four", "five six"]);
+    type_keys(&mut ed, &backend, "u0Vjy");
+    assert_eq!(register(&ed, '"'), "one two\nthree four\n");
+    type_keys(&mut ed, &backend, "gvU");
+    assert_eq!(lines(&ed), vec!["ONE TWO", "THREE FOUR", "five six"]);
+    type_keys(&mut ed, &backend, "0veu");
+    assert_eq!(lines(&ed), vec!["one TWO", "THREE FOUR", "five six"]);
+    type_keys(&mut ed, &backend, "0vjJ");
+    assert_eq!(lines(&ed), vec!["one TWO THREE FOUR", "five six"]);
+    type_keys(&mut ed, &backend, "0vlo~");
+    assert_eq!(lines(&ed), vec!["ONe TWO THREE FOUR", "five six"]);
+    type_keys(&mut ed, &backend, "V>");
+    assert_eq!(lines(&ed), vec!["    ONe TWO THREE FOUR", "five six"]);
+}
+
+#[test]
+fn visual_block_edits_every_line() {
+    let (mut ed, backend) = editor("abcd\nefgh\nij\nklmn", 40, 10);
+    type_keys(&mut ed, &backend, "l<C-v>jjjld");
+    assert_eq!(lines(&ed), vec!["ad", "eh", "i", "kn"]);
+    type_keys(&mut ed, &backend, "u<C-v>jjjIX<Esc>");
+    assert_eq!(lines(&ed), vec!["aXbcd", "eXfgh", "iXj", "kXlmn"]);
+    assert_eq!((ed.cursor.line, ed.cursor.col), (0, 1));
+    type_keys(&mut ed, &backend, "u0l<C-v>jjjlAY<Esc>");
+    assert_eq!(lines(&ed), vec!["abcYd", "efgYh", "ij Y", "klmYn"]);
+    type_keys(&mut ed, &backend, "u0<C-v>jj$AZ<Esc>");
+    assert_eq!(lines(&ed), vec!["abcdZ", "efghZ", "ijZ", "klmn"]);
+    type_keys(&mut ed, &backend, "u0l<C-v>jlcQ<Esc>");
+    assert_eq!(lines(&ed), vec!["aQd", "eQh", "ij", "klmn"]);
+    type_keys(&mut ed, &backend, "u");
+    assert_eq!(lines(&ed), vec!["abcd", "efgh", "ij", "klmn"]);
+}
+
+#[test]
+fn deletes_and_yanks_fill_the_numbered_registers() {
+    let (mut ed, backend) = editor("one two\nthree\nfour\nfive", 40, 10);
+    type_keys(&mut ed, &backend, "yyjdddddl");
+    assert_eq!(register(&ed, '0'), "one two\n");
+    assert_eq!(register(&ed, '1'), "four\n");
+    assert_eq!(register(&ed, '2'), "three\n");
+    assert_eq!(register(&ed, '-'), "f");
+    assert_eq!(register(&ed, '"'), "f");
+    type_keys(&mut ed, &backend, "\"_dd");
+    assert_eq!(lines(&ed), vec!["one two"]);
+    assert_eq!(register(&ed, '"'), "f");
+    type_keys(&mut ed, &backend, "\"ayw\"Ayy");
+    let a = ed.read_register('a').unwrap();
+    assert_eq!(a.text, "one \none two\n");
+    assert_eq!(a.kind, RegisterKind::Line);
+    type_keys(&mut ed, &backend, "ihi<Esc>:set ts=4<CR>");
+    assert_eq!(register(&ed, '.'), "hi");
+    assert_eq!(register(&ed, ':'), "set ts=4");
+}
+
+#[test]
+fn put_follows_the_register_type() {
+    let (mut ed, backend) = editor("abc\ndef", 40, 10);
+    type_keys(&mut ed, &backend, "ylp");
+    assert_eq!(lines(&ed), vec!["aabc", "def"]);
+    assert_eq!(ed.cursor.col, 1);
+    type_keys(&mut ed, &backend, "yy2P");
+    assert_eq!(lines(&ed), vec!["aabc", "aabc", "aabc", "def"]);
+    assert_eq!(ed.cursor.line, 0);
+    type_keys(&mut ed, &backend, "Ggp");
+    assert_eq!(lines(&ed), vec!["aabc", "aabc", "aabc", "def", "aabc"]);
+    assert_eq!(ed.cursor.line, 4);
+    type_keys(&mut ed, &backend, "u3G0l<C-v>jly$p");
+    assert_eq!(lines(&ed), vec!["aabc", "aabc", "aabcab", "def ef"]);
+    type_keys(&mut ed, &backend, "\"zp");
+    assert_eq!(ed.message, "E353: Nothing in register z");
+}
+
+#[test]
+fn registers_command_lists_contents() {
+    let (mut ed, backend) = editor("one\ntwo", 40, 10);
+    type_keys(&mut ed, &backend, "\"byyjdd:reg<CR>");
+    assert_eq!(
+        ed.output,
+        vec![
+            "Type Name Content",
+            "  l  \"\"   two^J",
+            "  l  \"1   two^J",
+            "  l  \"b   one^J",
+            "  c  \":   reg",
+        ]
+    );
+    type_keys(&mut ed, &backend, "<CR>:put b<CR>:1y<CR>:0pu<CR>");
+    assert_eq!(lines(&ed), vec!["one", "one", "one"]);
+}
+
+#[test]
+fn dot_repeats_operators_and_inserts() {
+    let (mut ed, backend) = editor("a b c d e f g\nx", 40, 10);
+    type_keys(&mut ed, &backend, "dw.");
+    assert_eq!(lines(&ed)[0], "c d e f g");
+    type_keys(&mut ed, &backend, "2.");
+    assert_eq!(lines(&ed)[0], "e f g");
+    type_keys(&mut ed, &backend, ".");
+    assert_eq!(lines(&ed)[0], "g");
+    let (mut ed, backend) = editor("foo bar baz\none\ntwo", 40, 10);
+    type_keys(&mut ed, &backend, "ciwnew<Esc>w.");
+    assert_eq!(lines(&ed)[0], "new new baz");
+    type_keys(&mut ed, &backend, "jA;<CR>x<Esc>j.");
+    assert_eq!(lines(&ed), vec!["new new baz", "one;", "x", "two;", "x"]);
+    type_keys(&mut ed, &backend, "u");
+    assert_eq!(lines(&ed), vec!["new new baz", "one;", "x", "two"]);
+}
+
+#[test]
+fn control_and_alt_keys_do_not_type() {
+    let (mut ed, backend) = editor("", 40, 10);
+    type_keys(&mut ed, &backend, "ia<C-w>b<C-r><Esc>");
+    assert_eq!(lines(&ed), vec!["ab"]);
+    type_keys(&mut ed, &backend, ":s/b/c<C-u>d/<CR>");
+    assert_eq!(lines(&ed), vec!["acd"]);
+}
+
+#[test]
+fn command_line_edits_whole_graphemes() {
+    let (mut ed, backend) = editor("ab", 40, 10);
+    type_keys(&mut ed, &backend, ":s/b/e\u{301}<BS>x/<CR>");
+    assert_eq!(lines(&ed), vec!["ax"]);
+    type_keys(
+        &mut ed,
+        &backend,
+        ":s/x/e\u{301}y/<Left><Left><Left><Del>z<CR>",
+    );
+    assert_eq!(lines(&ed), vec!["azy"]);
+}
+
+#[test]
+fn dot_repeats_what_backspace_left() {
+    let (mut ed, backend) = editor("xy", 40, 10);
+    type_keys(&mut ed, &backend, "A-e\u{301}<BS>f<Esc>");
+    assert_eq!(lines(&ed), vec!["xy-f"]);
+    assert_eq!(register(&ed, '.'), "-f");
+    type_keys(&mut ed, &backend, ".");
+    assert_eq!(lines(&ed), vec!["xy-f-f"]);
+    type_keys(&mut ed, &backend, "Aq<BS><BS><BS>z<Esc>");
+    assert_eq!(lines(&ed), vec!["xy-fz"]);
+    assert_eq!(register(&ed, '.'), "z");
+}
+
+#[test]
+fn dot_repeats_visual_changes_on_the_same_size() {
+    let (mut ed, backend) = editor("abcdef\nghijkl\nmnopqr\nstuvwx", 40, 10);
+    type_keys(&mut ed, &backend, "vlUj.");
+    assert_eq!(lines(&ed), vec!["ABcdef", "GHijkl", "mnopqr", "stuvwx"]);
+    type_keys(&mut ed, &backend, "gg<C-v>jlI-<Esc>jj0.");
+    assert_eq!(lines(&ed), vec!["-ABcdef", "-GHijkl", "-mnopqr", "-stuvwx"]);
+    type_keys(&mut ed, &backend, "ggVd.");
+    assert_eq!(lines(&ed), vec!["-mnopqr", "-stuvwx"]);
+}
+
+#[test]
+fn search_moves_to_matches_and_wraps() {
+    let (mut ed, backend) = editor("one foo\ntwo\nfoo three\nfour", 40, 10);
+    type_keys(&mut ed, &backend, "/fo");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (0, 4));
+    type_keys(&mut ed, &backend, "o<CR>");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (0, 4));
+    assert_eq!(ed.message, "/foo");
+    type_keys(&mut ed, &backend, "n");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (2, 0));
+    type_keys(&mut ed, &backend, "n");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (0, 4));
+    assert_eq!(ed.message, "search hit BOTTOM, continuing at TOP");
+    type_keys(&mut ed, &backend, "N");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (2, 0));
+    assert_eq!(ed.message, "search hit TOP, continuing at BOTTOM");
+    type_keys(&mut ed, &backend, "?o<CR>");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 2));
+    type_keys(&mut ed, &backend, "/nope<CR>");
+    assert_eq!(ed.message, "E486: Pattern not found: nope");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 2));
+    type_keys(&mut ed, &backend, "/thr<Esc>");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 2));
+    type_keys(&mut ed, &backend, ":set nows<CR>gg/four<CR>n");
+    assert_eq!(
+        ed.message,
+        "E385: Search hit BOTTOM without match for: four"
+    );
+    assert_eq!(register(&ed, '/'), "four");
+}
+
+#[test]
+fn star_searches_for_the_word_under_the_cursor() {
+    let (mut ed, backend) = editor("foo foobar\nbar foo\nfoo", 40, 10);
+    type_keys(&mut ed, &backend, "*");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 4));
+    type_keys(&mut ed, &backend, "#");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (0, 0));
+    type_keys(&mut ed, &backend, "2*");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (2, 0));
+    type_keys(&mut ed, &backend, "ggdn");
+    assert_eq!(lines(&ed), vec!["foo", "foo"]);
+}
+
+#[test]
+fn matches_are_highlighted_until_noh() {
+    let (mut ed, backend) = editor("ab ab\ncd ab", 40, 10);
+    let found = |row: usize, col: usize| {
+        let screen = backend.screen();
+        screen.get(3 + col, row).unwrap().style.bg == Some(Color::Yellow)
+    };
+    type_keys(&mut ed, &backend, "/ab");
+    assert!(found(0, 0) && found(0, 4) && found(1, 3) && !found(0, 2));
+    type_keys(&mut ed, &backend, "<CR>");
+    assert!(found(0, 3) && found(1, 4) && !found(1, 0));
+    type_keys(&mut ed, &backend, ":noh<CR>");
+    assert!(!found(0, 3));
+    type_keys(&mut ed, &backend, "n");
+    assert!(found(0, 3));
+    type_keys(&mut ed, &backend, ":set nohls<CR>");
+    assert!(!found(0, 3));
+}
+
+#[test]
+fn substitute_replaces_in_the_range_as_one_change() {
+    let (mut ed, backend) = editor("a cat\nthe cat cat\n  cat", 40, 10);
+    type_keys(&mut ed, &backend, ":%s/cat/dog/<CR>");
+    assert_eq!(lines(&ed), vec!["a dog", "the dog cat", "  dog"]);
+    assert_eq!(ed.message, "3 substitutions on 3 lines");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (2, 2));
+    type_keys(&mut ed, &backend, "u");
+    assert_eq!(lines(&ed), vec!["a cat", "the cat cat", "  cat"]);
+    type_keys(&mut ed, &backend, ":2s/\\(c\\)\\(a\\)t/\\u\\2\\1/g<CR>");
+    assert_eq!(lines(&ed)[1], "the Ac Ac");
+    assert_eq!(ed.message, "2 substitutions on 1 line");
+    type_keys(&mut ed, &backend, ":s/ /\\r/<CR>");
+    assert_eq!(lines(&ed), vec!["a cat", "the", "Ac Ac", "  cat"]);
+    type_keys(&mut ed, &backend, ":%s/zebra/x/<CR>");
+    assert_eq!(ed.message, "E486: Pattern not found: zebra");
+    type_keys(&mut ed, &backend, ":%s/a/x/gn<CR>");
+    assert_eq!(ed.message, "3 matches on 2 lines");
+    assert_eq!(lines(&ed)[0], "a cat");
+}
+
+#[test]
+fn substitute_confirm_asks_at_each_match() {
+    let (mut ed, backend) = editor("x x x\nx", 40, 10);
+    type_keys(&mut ed, &backend, ":%s/x/y/gc<CR>");
+    assert_eq!(ed.message, "replace with y (y/n/a/q/l)?");
+    let screen = backend.screen();
+    assert_eq!(screen.get(3, 0).unwrap().style.bg, Some(Color::Cyan));
+    type_keys(&mut ed, &backend, "yn");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (0, 4));
+    type_keys(&mut ed, &backend, "q");
+    assert_eq!(lines(&ed), vec!["y x x", "x"]);
+    assert_eq!(ed.message, "1 substitution on 1 line");
+    type_keys(&mut ed, &backend, "u");
+    type_keys(&mut ed, &backend, ":%s/x/z/gc<CR>na");
+    assert_eq!(lines(&ed), vec!["x z z", "z"]);
+    type_keys(&mut ed, &backend, "u:%s/x/w/c<CR>l");
+    assert_eq!(lines(&ed), vec!["w x x", "x"]);
+}
+
+#[test]
+fn substitutions_repeat_with_ampersand() {
+    let (mut ed, backend) = editor("a a\na a\na a", 40, 10);
+    type_keys(&mut ed, &backend, ":s/a/b/g<CR>j:&&<CR>");
+    assert_eq!(lines(&ed), vec!["b b", "b b", "a a"]);
+    type_keys(&mut ed, &backend, "j&");
+    assert_eq!(lines(&ed), vec!["b b", "b b", "b a"]);
+    type_keys(&mut ed, &backend, "u:1s/b/c/<CR>/a<CR>g&");
+    assert_eq!(lines(&ed), vec!["c b", "b b", "c a"]);
+    assert_eq!(ed.message, "1 substitution on 1 line");
+}
+
+#[test]
+fn global_runs_a_command_on_marked_lines() {
+    let (mut ed, backend) = editor("err a\nerr b\nok c\nerr d\nok e", 40, 10);
+    type_keys(&mut ed, &backend, ":g/err/d<CR>");
+    assert_eq!(lines(&ed), vec!["ok c", "ok e"]);
+    type_keys(&mut ed, &backend, "u");
+    assert_eq!(lines(&ed).len(), 5);
+    type_keys(&mut ed, &backend, ":v/err/s//X/<CR>");
+    assert_eq!(lines(&ed)[2], "ok c");
+    assert_eq!(ed.message, "");
+    type_keys(&mut ed, &backend, ":g/^/m0<CR>");
+    assert_eq!(lines(&ed), vec!["ok e", "err d", "ok c", "err b", "err a"]);
+    type_keys(&mut ed, &backend, ":2,4g!/err/s/ok/fine/<CR>");
+    assert_eq!(
+        lines(&ed),
+        vec!["ok e", "err d", "fine c", "err b", "err a"]
+    );
+    type_keys(&mut ed, &backend, ":g/err/s/r/R/g<CR>");
+    assert_eq!(lines(&ed)[1], "eRR d");
+    type_keys(&mut ed, &backend, ":g/a$/<CR>");
+    assert_eq!(ed.output, vec!["eRR a"]);
+    type_keys(&mut ed, &backend, "<CR>:g/e/g/e/d<CR>");
+    assert_eq!(ed.message, "E147: Cannot do :global recursive");
+}
+
+#[test]
+fn move_puts_lines_below_an_address() {
+    let (mut ed, backend) = editor("1\n2\n3\n4", 40, 10);
+    type_keys(&mut ed, &backend, ":1,2m$<CR>");
+    assert_eq!(lines(&ed), vec!["3", "4", "1", "2"]);
+    assert_eq!(ed.cursor.line, 3);
+    type_keys(&mut ed, &backend, ":m0<CR>");
+    assert_eq!(lines(&ed), vec!["2", "3", "4", "1"]);
+    type_keys(&mut ed, &backend, ":1,3m2<CR>");
+    assert_eq!(ed.message, "E134: Cannot move a range of lines into itself");
+}
+
+#[test]
+fn macros_record_and_replay_keys() {
+    let (mut ed, backend) = editor("a\nb\nc\nd", 60, 10);
+    type_keys(&mut ed, &backend, "qaA!<Esc>j");
+    assert!(backend.row_text(9).contains("recording @a"));
+    type_keys(&mut ed, &backend, "q");
+    assert!(!backend.row_text(9).contains("recording"));
+    assert_eq!(register(&ed, 'a'), "A!<Esc>j");
+    type_keys(&mut ed, &backend, "@a");
+    assert_eq!(lines(&ed), vec!["a!", "b!", "c", "d"]);
+    type_keys(&mut ed, &backend, "5@@");
+    assert_eq!(lines(&ed), vec!["a!", "b!", "c!", "d!"]);
+    type_keys(&mut ed, &backend, "qAxq");
+    assert_eq!(register(&ed, 'a'), "A!<Esc>jx");
+}
+
+#[test]
+fn recursive_macros_stop_at_a_failing_motion() {
+    let (mut ed, backend) = editor("1a\n2b\n3c", 40, 10);
+    type_keys(&mut ed, &backend, "qaqqa0dlj@aq@a");
+    assert_eq!(lines(&ed), vec!["a", "b", "c"]);
+    type_keys(&mut ed, &backend, "qbqqb@bq@b");
+    assert_eq!(ed.message, "E169: Command too recursive");
+}
+
+#[test]
+fn normal_runs_keys_on_lines() {
+    let (mut ed, backend) = editor("one\ntwo\nthree", 40, 10);
+    type_keys(&mut ed, &backend, ":%normal A;<CR>");
+    assert_eq!(lines(&ed), vec!["one;", "two;", "three;"]);
+    assert!(matches!(ed.mode, fred::editor::EditorMode::Normal));
+    type_keys(&mut ed, &backend, "ggqq0iX<Esc>q:g/t/normal @q<CR>");
+    assert_eq!(lines(&ed), vec!["Xone;", "Xtwo;", "Xthree;"]);
+    type_keys(&mut ed, &backend, "u");
+    assert_eq!(lines(&ed), vec!["Xone;", "two;", "three;"]);
+}
+
+#[test]
+fn marks_jump_to_lines_that_moved() {
+    let (mut ed, backend) = editor("one\n  two\nthree\nfour", 40, 10);
+    type_keys(&mut ed, &backend, "jllmaG'a");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 2));
+    type_keys(&mut ed, &backend, "gg`a");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (1, 2));
+    type_keys(&mut ed, &backend, "gg0izero<CR><Esc>G'a");
+    assert_eq!(ed.cursor.line, 2);
+    type_keys(&mut ed, &backend, "``");
+    assert_eq!(ed.cursor.line, 4);
+    type_keys(&mut ed, &backend, "gg'.");
+    assert_eq!(ed.cursor.line, 0);
+    type_keys(&mut ed, &backend, "gg:2,'ad<CR>");
+    assert_eq!(lines(&ed), vec!["zero", "three", "four"]);
+    type_keys(&mut ed, &backend, "'a");
+    assert!(backend.row_text(9).starts_with("E20"));
+}
+
+#[test]
+fn ctrl_o_and_tab_go_through_the_jump_list() {
+    let (mut ed, backend) = editor("a\nb\nc\nd\ne", 40, 10);
+    type_keys(&mut ed, &backend, "G:2<CR>");
+    assert_eq!(ed.cursor.line, 1);
+    type_keys(&mut ed, &backend, "<C-o>");
+    assert_eq!(ed.cursor.line, 4);
+    type_keys(&mut ed, &backend, "<C-o>");
+    assert_eq!(ed.cursor.line, 0);
+    type_keys(&mut ed, &backend, "<Tab><Tab>");
+    assert_eq!(ed.cursor.line, 1);
+}
+
+#[test]
+fn g_semicolon_goes_back_through_changes() {
+    let (mut ed, backend) = editor("a\nb\nc\nd", 40, 10);
+    type_keys(&mut ed, &backend, "dljjdl");
+    type_keys(&mut ed, &backend, "Gg;");
+    assert_eq!(ed.cursor.line, 2);
+    type_keys(&mut ed, &backend, "g;");
+    assert_eq!(ed.cursor.line, 0);
+    type_keys(&mut ed, &backend, "g;");
+    assert!(backend.row_text(9).starts_with("E662"));
+    type_keys(&mut ed, &backend, "g,");
+    assert_eq!(ed.cursor.line, 2);
+}
+
+#[test]
+fn automatic_marks_track_visual_yank_and_insert() {
+    let (mut ed, backend) = editor("one\ntwo\nthree\nfour", 40, 10);
+    type_keys(&mut ed, &backend, "jVj:");
+    assert_eq!(ed.command_line.text(), "'<,'>");
+    type_keys(&mut ed, &backend, "d<CR>");
+    assert_eq!(lines(&ed), vec!["one", "four"]);
+    type_keys(&mut ed, &backend, "ggyj");
+    assert_eq!(ed.buffer.marks.get(']'), Some((1, 4)));
+    type_keys(&mut ed, &backend, "Ax<Esc>j`^");
+    assert_eq!((ed.cursor.line, ed.cursor.col), (0, 3));
+    type_keys(&mut ed, &backend, ":marks a^<CR>");
+    assert_eq!(backend.row_text(7), "mark line  col file/text");
+    assert_eq!(backend.row_text(8), " ^      1    4 onex");
+    type_keys(&mut ed, &backend, "<CR>mb:delm b<CR>'b");
+    assert!(backend.row_text(9).starts_with("E20"));
+}
+
+#[test]
+fn uppercase_marks_open_their_file() {
+    let dir = std::env::temp_dir();
+    let first = dir.join(format!("fred-mark-a-{}.txt", std::process::id()));
+    let second = dir.join(format!("fred-mark-b-{}.txt", std::process::id()));
+    std::fs::write(&first, "a1\na2\na3").unwrap();
+    std::fs::write(&second, "b1\nb2").unwrap();
+    let (mut ed, backend) = editor("", 40, 10);
+    ed.read_from_file(first.display().to_string()).unwrap();
+    type_keys(&mut ed, &backend, "jjmA");
+    type_keys(&mut ed, &backend, &format!(":e {}<CR>", second.display()));
+    assert_eq!(lines(&ed), vec!["b1", "b2"]);
+    type_keys(&mut ed, &backend, "'A");
+    std::fs::remove_file(&first).unwrap();
+    std::fs::remove_file(&second).unwrap();
+    assert_eq!(lines(&ed), vec!["a1", "a2", "a3"]);
+    assert_eq!(ed.cursor.line, 2);
+}
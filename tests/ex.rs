@@ -0,0 +1,76 @@
+use fred::ex::{parse, Address, AddressBase, LineRange};
+
+fn addr(base: AddressBase, offset: isize) -> Address {
+    Address { base, offset }
+}
+
+#[test]
+fn parses_ranges() {
+    let cmd = parse("3,10d").unwrap();
+    assert_eq!(cmd.name, "d");
+    assert_eq!(
+        cmd.range,
+        Some(LineRange {
+            start: addr(AddressBase::Line(3), 0),
+            end: addr(AddressBase::Line(10), 0),
+        })
+    );
+
+    let cmd = parse(".,$-1s/a/b/").unwrap();
+    assert_eq!(cmd.name, "s");
+    assert_eq!(cmd.args, "/a/b/");
+    let range = cmd.range.unwrap();
+    assert_eq!(range.start, addr(AddressBase::Current, 0));
+    assert_eq!(range.end, addr(AddressBase::Last, -1));
+
+    let range = parse("'<,'>d").unwrap().range.unwrap();
+    assert_eq!(range.start, addr(AddressBase::Mark('<'), 0));
+    assert_eq!(range.end, addr(AddressBase::Mark('>'), 0));
+
+    let range = parse("%").unwrap().range.unwrap();
+    assert_eq!(range.start, addr(AddressBase::Line(1), 0));
+    assert_eq!(range.end, addr(AddressBase::Last, 0));
+}
+
+#[test]
+fn parses_bang_and_arguments() {
+    let cmd = parse("w! out.txt").unwrap();
+    assert_eq!(cmd.name, "w");
+    assert!(cmd.bang);
+    assert_eq!(cmd.args, "out.txt");
+    assert!(cmd.range.is_none());
+
+    let cmd = parse("m0").unwrap();
+    assert_eq!(cmd.name, "m");
+    assert_eq!(cmd.args, "0");
+}
+
+#[test]
+fn rejects_garbage() {
+    assert!(parse("3,5#").is_err());
+}
+
+#[test]
+fn parses_lone_addresses() {
+    use fred::ex::parse_address;
+    assert_eq!(parse_address("0"), Ok(addr(AddressBase::Line(0), 0)));
+    assert_eq!(parse_address(" $-2"), Ok(addr(AddressBase::Last, -2)));
+    assert!(parse_address("").is_err());
+    assert!(parse_address("3x").is_err());
+    assert_eq!(
+        parse_address(".+9223372036854775807"),
+        Ok(addr(AddressBase::Current, isize::MAX))
+    );
+    for address in &[
+        "99999999999999999999",
+        ".+99999999999999999999",
+        ".+9223372036854775808",
+        ".+9223372036854775807+1",
+        ".-9223372036854775807-2",
+    ] {
+        assert_eq!(
+            parse_address(address),
+            Err("E16: Invalid range".to_string())
+        );
+    }
+}
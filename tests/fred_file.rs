@@ -0,0 +1,26 @@
+use fred::fred_file::{read_buffer, write_buffer};
+
+#[test]
+fn files_are_written_back_byte_for_byte() {
+    let path = std::env::temp_dir().join(format!("fred-round-trip-{}.txt", std::process::id()));
+    let files = [
+        "",
+        "\n",
+        "plain\nlines\n",
+        "no newline\nat the end",
+        "lone\rcarriage\rreturns\r\n",
+        "form\x0cfeed\nline\u{2028}separator\n",
+        "crlf\r\nwith a lone\r and a \x0c\r\nand\u{2028}\r\n",
+        "crlf\r\nwithout a trailing one",
+        "lf first\nthen a stray\r\n",
+        "\r",
+    ];
+    for text in &files {
+        std::fs::write(&path, text).unwrap();
+        let buffer = read_buffer(&path).unwrap();
+        let written = write_buffer(&path, &buffer).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), text.as_bytes(), "{:?}", text);
+        assert_eq!(written, text.len());
+    }
+    std::fs::remove_file(&path).unwrap();
+}
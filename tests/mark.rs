@@ -0,0 +1,65 @@
+use fred::buffer::Buffer;
+use fred::mark::{LineShift, Marks};
+
+#[test]
+fn marks_follow_lines_inserted_and_deleted_above() {
+    let mut buffer = Buffer::from_string("one\ntwo\nthree\nfour".to_string());
+    buffer.marks.set('a', (2, 3));
+    buffer.marks.set('b', (1, 0));
+    buffer.insert(0, "new\n");
+    assert_eq!(buffer.marks.get('a'), Some((3, 3)));
+    let start = buffer.line_to_char(1);
+    buffer.remove(start..start + 4);
+    assert_eq!(buffer.marks.get('a'), Some((2, 3)));
+    let start = buffer.line_to_char(1);
+    buffer.remove(start..start + 4);
+    assert_eq!(buffer.marks.get('b'), None);
+    assert_eq!(buffer.marks.get('a'), Some((1, 3)));
+}
+
+#[test]
+fn joined_lines_carry_their_marks() {
+    let shift = LineShift::Join {
+        line: 1,
+        col: 3,
+        joined: 3,
+        skip: 2,
+    };
+    assert_eq!(shift.pos((3, 5)), Some((1, 6)));
+    assert_eq!(shift.pos((2, 0)), None);
+    assert_eq!(shift.pos((4, 1)), Some((2, 1)));
+    assert_eq!(shift.line(3), None);
+    let mut buffer = Buffer::from_string("ab\ncd".to_string());
+    buffer.marks.set('a', (1, 1));
+    buffer.remove(2..3);
+    assert_eq!(buffer.marks.get('a'), Some((0, 3)));
+}
+
+#[test]
+fn jump_list_goes_back_and_forward() {
+    let mut marks = Marks::new();
+    marks.push_jump((5, 0));
+    marks.push_jump((10, 2));
+    assert_eq!(marks.get('\''), Some((10, 2)));
+    assert_eq!(marks.step_jump((20, 0), 1, false), Some((10, 2)));
+    assert_eq!(marks.step_jump((10, 2), 1, false), Some((5, 0)));
+    assert_eq!(marks.step_jump((5, 0), 1, false), None);
+    assert_eq!(marks.step_jump((5, 0), 2, true), Some((20, 0)));
+    assert_eq!(marks.step_jump((20, 0), 1, true), None);
+    marks.push_jump((5, 4));
+    assert_eq!(marks.step_jump((0, 0), 3, false), Some((10, 2)));
+}
+
+#[test]
+fn change_list_keeps_one_entry_per_line() {
+    let mut marks = Marks::new();
+    assert!(marks.step_change(1, false).unwrap_err().starts_with("E664"));
+    marks.push_change((1, 0));
+    marks.push_change((1, 4));
+    marks.push_change((3, 2));
+    assert_eq!(marks.get('.'), Some((3, 2)));
+    assert_eq!(marks.step_change(1, false), Ok((3, 2)));
+    assert_eq!(marks.step_change(1, false), Ok((1, 4)));
+    assert!(marks.step_change(1, false).unwrap_err().starts_with("E662"));
+    assert!(marks.step_change(2, true).unwrap_err().starts_with("E663"));
+}
@@ -0,0 +1,129 @@
+use fred::buffer::Buffer;
+use fred::motion::{self, Find, ScreenLine, Scroll};
+
+fn buffer(text: &str) -> Buffer {
+    Buffer::from_string(text.to_string())
+}
+
+#[test]
+fn word_motions() {
+    let b = buffer("foo.bar baz\n\n  qux-quux");
+    assert_eq!(motion::word_start(&b, (0, 0), 1, false), Some((0, 3)));
+    assert_eq!(motion::word_start(&b, (0, 0), 1, true), Some((0, 8)));
+    assert_eq!(motion::word_start(&b, (0, 8), 1, false), Some((1, 0)));
+    assert_eq!(motion::word_start(&b, (2, 9), 1, false), Some((2, 10)));
+    assert_eq!(motion::word_start(&b, (2, 10), 1, false), None);
+    assert_eq!(motion::word_back(&b, (2, 2), 1, false), Some((1, 0)));
+    assert_eq!(motion::word_back(&b, (0, 8), 2, false), Some((0, 3)));
+    assert_eq!(motion::word_back(&b, (0, 8), 1, true), Some((0, 0)));
+    assert_eq!(motion::word_back(&b, (0, 0), 1, false), None);
+    assert_eq!(motion::word_end(&b, (2, 2), 1, true), Some((2, 9)));
+    assert_eq!(motion::word_end_back(&b, (0, 8), 1, false), Some((0, 6)));
+    assert_eq!(motion::word_end_back(&b, (2, 2), 1, false), Some((1, 0)));
+    assert_eq!(motion::word_end_back(&b, (2, 6), 1, true), Some((1, 0)));
+}
+
+#[test]
+fn line_motions() {
+    let b = buffer("  foo bar\nbaz");
+    assert_eq!(motion::first_non_blank(&b, 0), (0, 2));
+    assert_eq!(motion::line_end(&b, (0, 0), 2), Some((1, 2)));
+    assert_eq!(motion::line_end(&b, (0, 0), 3), None);
+    assert_eq!(motion::first_non_blank_down(&b, 0, 2), Some((1, 0)));
+    assert_eq!(motion::right(&b, (1, 1), 5), Some((1, 3)));
+    assert_eq!(motion::left(&b, (1, 0), 1), None);
+}
+
+#[test]
+fn find_char_in_line() {
+    let b = buffer("a,b,c,d");
+    let f = Find {
+        ch: ',',
+        forward: true,
+        till: false,
+    };
+    assert_eq!(motion::find_char(&b, (0, 0), 2, f, false), Some((0, 3)));
+    assert_eq!(motion::find_char(&b, (0, 0), 4, f, false), None);
+    let t = Find { till: true, ..f };
+    assert_eq!(motion::find_char(&b, (0, 0), 1, t, false), Some((0, 0)));
+    // a repeated `t` doesn't stay stuck in front of the same char
+    assert_eq!(motion::find_char(&b, (0, 0), 1, t, true), Some((0, 2)));
+    assert_eq!(
+        motion::find_char(&b, (0, 6), 1, t.reversed(), false),
+        Some((0, 6))
+    );
+    assert_eq!(
+        motion::find_char(&b, (0, 6), 1, t.reversed(), true),
+        Some((0, 4))
+    );
+}
+
+#[test]
+fn sentences_and_paragraphs() {
+    let b = buffer("One two. Three (four.)  Five\nsix.\n\nSeven");
+    assert_eq!(motion::sentence_forward(&b, (0, 0), 1), Some((0, 9)));
+    assert_eq!(motion::sentence_forward(&b, (0, 9), 1), Some((0, 24)));
+    assert_eq!(motion::sentence_forward(&b, (0, 24), 1), Some((2, 0)));
+    assert_eq!(motion::sentence_forward(&b, (2, 0), 1), Some((3, 0)));
+    assert_eq!(motion::sentence_forward(&b, (3, 0), 1), Some((3, 4)));
+    assert_eq!(motion::sentence_backward(&b, (1, 2), 1), Some((0, 24)));
+    assert_eq!(motion::sentence_backward(&b, (0, 24), 2), Some((0, 0)));
+
+    let b = buffer("a\nb\n\n\nc\nd");
+    assert_eq!(motion::paragraph_forward(&b, (0, 0), 1), Some((2, 0)));
+    assert_eq!(motion::paragraph_forward(&b, (2, 0), 1), Some((5, 1)));
+    assert_eq!(motion::paragraph_forward(&b, (5, 0), 1), None);
+    assert_eq!(motion::paragraph_backward(&b, (5, 0), 1), Some((3, 0)));
+    assert_eq!(motion::paragraph_backward(&b, (3, 0), 1), Some((0, 0)));
+}
+
+#[test]
+fn matching_brackets() {
+    let b = buffer("if (a[1] == (b)) {\n    x\n}");
+    assert_eq!(motion::matching_pair(&b, (0, 0)), Some((0, 15)));
+    assert_eq!(motion::matching_pair(&b, (0, 15)), Some((0, 3)));
+    assert_eq!(motion::matching_pair(&b, (0, 16)), Some((2, 0)));
+    assert_eq!(motion::matching_pair(&b, (2, 0)), Some((0, 17)));
+    assert_eq!(motion::matching_pair(&b, (1, 0)), None);
+    assert_eq!(motion::percent_line(&b, 50), Some((1, 4)));
+}
+
+#[test]
+fn screen_lines_and_scrolling() {
+    let text: Vec<String> = (0..100).map(|n| n.to_string()).collect();
+    let b = buffer(&text.join("\n"));
+    let view = (10, 30);
+    assert_eq!(
+        motion::screen_line(&b, view, 0, ScreenLine::Top, 1),
+        (10, 0)
+    );
+    assert_eq!(
+        motion::screen_line(&b, view, 3, ScreenLine::Top, 1),
+        (13, 0)
+    );
+    assert_eq!(
+        motion::screen_line(&b, view, 0, ScreenLine::Middle, 1),
+        (19, 0)
+    );
+    assert_eq!(
+        motion::screen_line(&b, view, 0, ScreenLine::Bottom, 2),
+        (28, 0)
+    );
+    assert_eq!(
+        motion::scroll(&b, (10, 20), 15, Scroll::HalfDown, None),
+        Some((20, 25))
+    );
+    assert_eq!(
+        motion::scroll(&b, (10, 20), 15, Scroll::HalfUp, Some(3)),
+        Some((7, 12))
+    );
+    assert_eq!(
+        motion::scroll(&b, (10, 20), 15, Scroll::PageDown, None),
+        Some((28, 28))
+    );
+    assert_eq!(
+        motion::scroll(&b, (10, 20), 15, Scroll::PageUp, None),
+        Some((0, 15))
+    );
+    assert_eq!(motion::scroll(&b, (0, 20), 0, Scroll::PageUp, None), None);
+}
@@ -0,0 +1,193 @@
+use fred::keys::parse_keys;
+use fred::normal::{
+    parse, Action, CommandKind, Motion, NormalCommand, Operator, Parse, Target, MAX_COUNT,
+};
+
+fn parsed(keys: &str) -> Parse {
+    parse(&parse_keys(keys), false)
+}
+
+#[test]
+fn counts_and_motions() {
+    assert_eq!(
+        parsed("5j"),
+        Parse::Done(NormalCommand {
+            register: None,
+            count: Some(5),
+            kind: CommandKind::Motion(Motion::Down),
+        })
+    );
+    assert_eq!(
+        parsed("0"),
+        Parse::Done(NormalCommand {
+            register: None,
+            count: None,
+            kind: CommandKind::Motion(Motion::LineStart),
+        })
+    );
+    assert_eq!(parsed("10"), Parse::Pending);
+    assert_eq!(parsed("g"), Parse::Pending);
+    assert_eq!(
+        parsed("3gg"),
+        Parse::Done(NormalCommand {
+            register: None,
+            count: Some(3),
+            kind: CommandKind::Motion(Motion::FirstLine),
+        })
+    );
+    let Parse::Done(cmd) = parsed("99999999999999999999j") else {
+        panic!("not parsed");
+    };
+    assert_eq!(cmd.count, Some(MAX_COUNT));
+    let Parse::Done(cmd) = parsed("99999\"a99999d99999w") else {
+        panic!("not parsed");
+    };
+    assert_eq!(cmd.motion_count(), Some(MAX_COUNT));
+}
+
+#[test]
+fn operators_take_a_motion_or_repeat_for_lines() {
+    let Parse::Done(cmd) = parsed("2d3w") else {
+        panic!("2d3w should parse");
+    };
+    assert_eq!(
+        cmd.kind,
+        CommandKind::Operator(
+            Operator::Delete,
+            Target::Motion(Motion::WordStart { big: false }, Some(3))
+        )
+    );
+    assert_eq!(cmd.motion_count(), Some(6));
+    assert_eq!(
+        parsed("3dd"),
+        Parse::Done(NormalCommand {
+            register: None,
+            count: Some(3),
+            kind: CommandKind::Operator(Operator::Delete, Target::Lines),
+        })
+    );
+    assert_eq!(
+        parsed("\"ayy"),
+        Parse::Done(NormalCommand {
+            register: Some('a'),
+            count: Some(1),
+            kind: CommandKind::Operator(Operator::Yank, Target::Lines),
+        })
+    );
+    assert_eq!(parsed("c"), Parse::Pending);
+    assert_eq!(parsed("cg"), Parse::Pending);
+    assert_eq!(
+        parsed("zl"),
+        Parse::Done(NormalCommand {
+            register: None,
+            count: None,
+            kind: CommandKind::Action(Action::ScrollRight),
+        })
+    );
+}
+
+#[test]
+fn invalid_and_cancelled_sequences() {
+    assert_eq!(parsed("dx"), Parse::Invalid);
+    assert_eq!(parsed("zq"), Parse::Invalid);
+    assert_eq!(parsed("["), Parse::Invalid);
+    assert_eq!(parsed("\"<Esc>"), Parse::Cancelled);
+    assert_eq!(parsed("\"<CR>"), Parse::Invalid);
+    assert_eq!(parsed("2d<Esc>"), Parse::Cancelled);
+}
+
+#[test]
+fn find_motions_take_a_char() {
+    use fred::motion::Find;
+    assert_eq!(parsed("f"), Parse::Pending);
+    assert_eq!(parsed("dt"), Parse::Pending);
+    let find = Find {
+        ch: ')',
+        forward: true,
+        till: true,
+    };
+    assert_eq!(
+        parsed("2dt)"),
+        Parse::Done(NormalCommand {
+            register: None,
+            count: Some(2),
+            kind: CommandKind::Operator(Operator::Delete, Target::Motion(Motion::Find(find), None)),
+        })
+    );
+    assert_eq!(parsed("f<C-a>"), Parse::Invalid);
+}
+
+#[test]
+fn dot_replaces_counts_of_changes() {
+    let Parse::Done(cmd) = parsed("2d3w") else {
+        panic!("2d3w should parse");
+    };
+    assert!(cmd.is_change());
+    assert_eq!(cmd.with_count(4).motion_count(), Some(4));
+    let Parse::Done(yank) = parsed("yy") else {
+        panic!("yy should parse");
+    };
+    assert!(!yank.is_change());
+    assert_eq!(
+        parsed("."),
+        Parse::Done(NormalCommand {
+            register: None,
+            count: None,
+            kind: CommandKind::Action(Action::Repeat),
+        })
+    );
+}
+
+#[test]
+fn q_and_at_take_a_register() {
+    assert_eq!(parsed("q"), Parse::Pending);
+    assert_eq!(
+        parsed("qA"),
+        Parse::Done(NormalCommand {
+            register: None,
+            count: None,
+            kind: CommandKind::Action(Action::Record('A')),
+        })
+    );
+    assert_eq!(
+        parsed("3@@"),
+        Parse::Done(NormalCommand {
+            register: None,
+            count: Some(3),
+            kind: CommandKind::Action(Action::Play('@')),
+        })
+    );
+    assert_eq!(parsed("q-"), Parse::Invalid);
+}
+
+#[test]
+fn marks_are_set_with_m_and_jumped_to_with_quotes() {
+    assert_eq!(
+        parsed("mx"),
+        Parse::Done(NormalCommand {
+            register: None,
+            count: None,
+            kind: CommandKind::Action(Action::SetMark('x')),
+        })
+    );
+    assert_eq!(
+        parsed("d`a"),
+        Parse::Done(NormalCommand {
+            register: None,
+            count: None,
+            kind: CommandKind::Operator(
+                Operator::Delete,
+                Target::Motion(
+                    Motion::Mark {
+                        name: 'a',
+                        exact: true
+                    },
+                    None
+                )
+            ),
+        })
+    );
+    assert_eq!(parsed("'"), Parse::Pending);
+    assert_eq!(parsed("m."), Parse::Invalid);
+    assert_eq!(parsed("'!"), Parse::Invalid);
+}
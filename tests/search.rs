@@ -0,0 +1,86 @@
+use fred::buffer::Buffer;
+use fred::search::{compile, find, line_matches, word_pattern};
+
+#[test]
+fn case_follows_ignorecase_smartcase_and_flags() {
+    assert!(!compile("foo", false, false).unwrap().is_match("FOO"));
+    assert!(compile("foo", true, false).unwrap().is_match("FOO"));
+    assert!(compile("foo", true, true).unwrap().is_match("FOO"));
+    assert!(!compile("Foo", true, true).unwrap().is_match("FOO"));
+    assert!(compile("Foo\\c", false, false).unwrap().is_match("FOO"));
+    assert!(!compile("\\Cfoo", true, false).unwrap().is_match("FOO"));
+    assert!(compile("\\Sfoo", true, true).unwrap().is_match("XFOO"));
+    assert!(compile("\\(", false, false).is_err());
+}
+
+#[test]
+fn patterns_use_vim_syntax() {
+    let matches =
+        |pattern: &str, text: &str| compile(pattern, false, false).unwrap().is_match(text);
+    assert!(matches("f(o+)?", "f(o+)?"));
+    assert!(!matches("f(o+)?", "foo"));
+    assert!(matches("^a\\(b\\|c\\)\\+$", "abcb"));
+    assert!(matches("x^$y", "x^$y"));
+    assert!(matches("\\<ab\\{2,}\\>", "x abbb y"));
+    assert!(!matches("\\<ab\\{2,}\\>", "xabbb"));
+    assert!(matches("co\\=l\\%(or\\)\\?$", "cl"));
+    assert!(matches("[]a-c]\\{,2}\\u", "]bX"));
+    assert!(matches("\\a\\+\\d", "{ab1}"));
+    assert!(compile("\\zsa", false, false).is_err());
+    assert!(compile("a\\{x}", false, false).is_err());
+    assert!(compile("\\(\\w\\+\\)-\\1", false, false).is_err());
+}
+
+#[test]
+fn matches_are_char_ranges() {
+    let regex = compile("\\<é\\w*", false, false).unwrap();
+    assert_eq!(line_matches(&regex, "aé éa éb"), vec![3..5, 6..8]);
+}
+
+#[test]
+fn find_wraps_around_the_buffer() {
+    let buffer = Buffer::from_string("foo x\nbar\nfoo".to_string());
+    let regex = compile("foo", false, false).unwrap();
+    assert_eq!(
+        find(&buffer, &regex, (0, 0), true, true),
+        Some(((2, 0), false))
+    );
+    assert_eq!(
+        find(&buffer, &regex, (2, 0), true, true),
+        Some(((0, 0), true))
+    );
+    assert_eq!(find(&buffer, &regex, (2, 0), true, false), None);
+    assert_eq!(
+        find(&buffer, &regex, (1, 0), false, true),
+        Some(((0, 0), false))
+    );
+    assert_eq!(
+        find(&buffer, &regex, (0, 0), false, true),
+        Some(((2, 0), true))
+    );
+    let none = compile("nothing", false, false).unwrap();
+    assert_eq!(find(&buffer, &none, (0, 0), true, true), None);
+}
+
+#[test]
+fn word_under_the_cursor() {
+    assert_eq!(
+        word_pattern("let foo_bar = 1;", 6),
+        Some(("\\<foo_bar\\>".to_string(), 4))
+    );
+    assert_eq!(word_pattern("  x.y", 0), Some(("\\<x\\>".to_string(), 2)));
+    assert_eq!(word_pattern("a += 1", 1), Some(("\\<1\\>".to_string(), 5)));
+    assert_eq!(word_pattern("a += ", 1), Some(("+=".to_string(), 2)));
+    assert_eq!(word_pattern("abc   ", 3), None);
+}
+
+#[test]
+fn delimited_parts_unescape_the_delimiter() {
+    use fred::search::split_delimited;
+    assert_eq!(
+        split_delimited("a\\/b/rest", '/'),
+        ("a/b".to_string(), "rest")
+    );
+    assert_eq!(split_delimited("a\\db", '/'), ("a\\db".to_string(), ""));
+    assert_eq!(split_delimited("x#y#", '#'), ("x".to_string(), "y#"));
+}
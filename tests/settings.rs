@@ -0,0 +1,75 @@
+use fred::settings::{Settings, Value};
+
+#[test]
+fn set_arguments() {
+    let mut s = Settings::default();
+    assert_eq!(s.apply("ts=8"), Ok(None));
+    assert_eq!(s.tabstop, 8);
+    assert_eq!(s.apply("sw+=2"), Ok(None));
+    assert_eq!(s.shiftwidth, 6);
+    let huge = format!("sw^={}", usize::MAX);
+    assert!(s.apply(&huge).unwrap_err().starts_with("E521"));
+    assert!(s.apply("sw-=7").unwrap_err().starts_with("E521"));
+    assert_eq!(s.shiftwidth, 6);
+    assert_eq!(s.apply("nonu"), Ok(None));
+    assert!(!s.number);
+    assert_eq!(s.apply("wrap!"), Ok(None));
+    assert!(s.wrap);
+    assert_eq!(s.apply("invwrap"), Ok(None));
+    assert!(!s.wrap);
+    assert_eq!(s.apply("ts?"), Ok(Some("tabstop=8".into())));
+    assert_eq!(s.apply("number?"), Ok(Some("nonumber".into())));
+    assert_eq!(s.apply("so"), Ok(Some("scrolloff=0".into())));
+    assert_eq!(s.apply("ts&"), Ok(None));
+    assert_eq!(s.get("ts"), Some(Value::Number(4)));
+    assert_eq!(s.changed(), vec!["shiftwidth=6", "nonumber"]);
+}
+
+#[test]
+fn bad_set_arguments() {
+    let mut s = Settings::default();
+    assert_eq!(s.apply("foo"), Err("E518: Unknown option: foo".into()));
+    assert_eq!(
+        s.apply("ts=x"),
+        Err("E521: Number required after =: ts=x".into())
+    );
+    assert_eq!(
+        s.apply("wrap=1"),
+        Err("E474: Invalid argument: wrap=1".into())
+    );
+    assert_eq!(
+        s.apply("ts=0"),
+        Err("E487: Argument must be positive".into())
+    );
+    assert!(s.apply("statusbg=nope").is_err());
+    assert_eq!(
+        s.apply("ts=99999999999"),
+        Err("E474: Invalid argument: tabstop=99999999999".into())
+    );
+    assert!(s.apply("sw=10000").is_err() && s.apply("so+=10000").is_err());
+    assert_eq!(s, Settings::default());
+    assert_eq!(s.apply("ts=9999"), Ok(None));
+}
+
+#[test]
+fn check_rejects_bad_config_values() {
+    let mut s = Settings::default();
+    assert_eq!(s.check(), Ok(()));
+    s.statusfg = "#00ff00".into();
+    assert_eq!(s.check(), Ok(()));
+    s.statusbg = "nope".into();
+    assert_eq!(
+        s.check(),
+        Err("statusbg: E254: Cannot allocate color nope".into())
+    );
+}
+
+#[test]
+fn config_files_may_use_the_old_tab_spaces_name() {
+    let path = std::env::temp_dir().join(format!("fred-legacy-{}.toml", std::process::id()));
+    std::fs::write(&path, "tab_spaces = 8\n").unwrap();
+    let settings: Settings = confy::load_path(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(settings.tabstop, 8);
+    assert_eq!(settings.check(), Ok(()));
+}
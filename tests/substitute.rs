@@ -0,0 +1,90 @@
+use fred::buffer::Buffer;
+use fred::search::compile;
+use fred::substitute::{expand, parse, resolve, Flags, Run, Substitute};
+
+fn replaced(pattern: &str, replacement: &str, text: &str) -> String {
+    let regex = compile(pattern, false, false).unwrap();
+    let caps = regex.captures(text).unwrap();
+    expand(replacement, &caps)
+}
+
+#[test]
+fn arguments_take_any_delimiter_flags_and_count() {
+    let args = parse("#a/b#c\\#d#gI 3").unwrap();
+    assert_eq!(args.pattern, Some(("a/b".to_string(), "c#d".to_string())));
+    assert_eq!(
+        args.flags,
+        Flags {
+            global: true,
+            ignore_case: Some(false),
+            ..Flags::default()
+        }
+    );
+    assert_eq!(args.count, Some(3));
+    assert_eq!(
+        parse("/x").unwrap().pattern,
+        Some(("x".to_string(), String::new()))
+    );
+    let repeat = parse("&c").unwrap();
+    assert!(repeat.pattern.is_none() && repeat.keep_flags && repeat.flags.confirm);
+    assert!(parse("/a/b/gz").is_err());
+}
+
+#[test]
+fn resolving_fills_in_the_last_substitution_and_search() {
+    let last = Substitute {
+        pattern: "a".into(),
+        replacement: "b".into(),
+        flags: Flags {
+            global: true,
+            ..Flags::default()
+        },
+    };
+    let sub = resolve(&parse("//<~>/&").unwrap(), Some(&last), Some("s")).unwrap();
+    assert_eq!(
+        (sub.pattern.as_str(), sub.replacement.as_str()),
+        ("s", "<b>")
+    );
+    assert!(sub.flags.global);
+    let sub = resolve(&parse("").unwrap(), Some(&last), None).unwrap();
+    assert_eq!(sub.pattern, "a");
+    assert!(!sub.flags.global);
+    assert!(resolve(&parse("").unwrap(), None, None).is_err());
+    assert!(resolve(&parse("//x/").unwrap(), None, None).is_err());
+}
+
+#[test]
+fn replacements_expand_groups_and_case() {
+    assert_eq!(
+        replaced("\\(\\w\\+\\) \\(\\w\\+\\)", "\\2 \\1", "hello world"),
+        "world hello"
+    );
+    assert_eq!(replaced("o\\+", "[&]\\&", "foo"), "[oo]&");
+    assert_eq!(replaced("\\(\\w\\+\\)", "\\u&", "word"), "Word");
+    assert_eq!(
+        replaced("\\(\\w\\+\\) \\(\\w\\+\\)", "\\U\\1\\E \\2", "ab cd"),
+        "AB cd"
+    );
+    assert_eq!(replaced("\\w\\+", "\\L\\u&", "hELLO"), "Hello");
+    assert_eq!(replaced(",", "\\r", "a,b"), "\n");
+}
+
+#[test]
+fn runs_continue_after_the_replacement() {
+    let mut buffer = Buffer::from_string("aaa\nbab\nc".to_string());
+    let regex = compile("a", false, false).unwrap();
+    let mut run = Run::new(regex, "aa".to_string(), true, (0, 2));
+    while let Some(m) = run.find(&buffer) {
+        run.replace(&mut buffer, &m);
+    }
+    assert_eq!(buffer.slice(0..buffer.len_chars()), "aaaaaa\nbaab\nc");
+    assert_eq!((run.substitutions, run.lines), (4, 2));
+
+    let mut buffer = Buffer::from_string("baaac".to_string());
+    let regex = compile("a*", false, false).unwrap();
+    let mut run = Run::new(regex, "-".to_string(), true, (0, 0));
+    while let Some(m) = run.find(&buffer) {
+        run.replace(&mut buffer, &m);
+    }
+    assert_eq!(buffer.slice(0..buffer.len_chars()), "-b-c-");
+}
@@ -0,0 +1,142 @@
+use fred::buffer::Buffer;
+use fred::text_object::{select, Selection, TextObject};
+
+fn selected(text: &str, pos: (usize, usize), count: usize, keys: &str) -> Option<String> {
+    let buffer = Buffer::from_string(text.to_string());
+    let mut keys = keys.chars();
+    let inner = keys.next() == Some('i');
+    let object = TextObject::from_key(inner, keys.next().unwrap()).unwrap();
+    match select(&buffer, pos, count, object)? {
+        Selection::Chars(range) => Some(buffer.slice(range)),
+        Selection::Lines(first, last) => Some(format!("lines {}-{}", first, last)),
+    }
+}
+
+#[test]
+fn words() {
+    let text = "foo bar.baz  qux";
+    assert_eq!(selected(text, (0, 5), 1, "iw").as_deref(), Some("bar"));
+    assert_eq!(selected(text, (0, 5), 1, "aw").as_deref(), Some(" bar"));
+    assert_eq!(selected(text, (0, 0), 1, "aw").as_deref(), Some("foo "));
+    assert_eq!(selected(text, (0, 5), 1, "iW").as_deref(), Some("bar.baz"));
+    assert_eq!(
+        selected(text, (0, 5), 1, "aW").as_deref(),
+        Some("bar.baz  ")
+    );
+    assert_eq!(selected(text, (0, 14), 1, "aw").as_deref(), Some("  qux"));
+    assert_eq!(selected(text, (0, 0), 3, "iw").as_deref(), Some("foo bar"));
+    assert_eq!(selected(text, (0, 11), 1, "aw").as_deref(), Some("  qux"));
+}
+
+#[test]
+fn sentences_and_paragraphs() {
+    let text = "One two.  Three four.\n\nFive.\nsix\n\n";
+    assert_eq!(
+        selected(text, (0, 12), 1, "is").as_deref(),
+        Some("Three four.")
+    );
+    assert_eq!(
+        selected(text, (0, 2), 1, "as").as_deref(),
+        Some("One two.  ")
+    );
+    assert_eq!(
+        selected(text, (0, 2), 1, "ip").as_deref(),
+        Some("lines 0-0")
+    );
+    assert_eq!(
+        selected(text, (0, 2), 1, "ap").as_deref(),
+        Some("lines 0-1")
+    );
+    assert_eq!(
+        selected(text, (3, 0), 1, "ap").as_deref(),
+        Some("lines 2-4")
+    );
+    assert_eq!(
+        selected(text, (1, 0), 1, "ap").as_deref(),
+        Some("lines 1-3")
+    );
+}
+
+#[test]
+fn quotes() {
+    let text = r#"say "hi \"there\"" and 'x'"#;
+    assert_eq!(
+        selected(text, (0, 6), 1, "i\"").as_deref(),
+        Some(r#"hi \"there\""#)
+    );
+    assert_eq!(
+        selected(text, (0, 0), 1, "a\"").as_deref(),
+        Some(r#""hi \"there\"" "#)
+    );
+    assert_eq!(selected(text, (0, 20), 1, "a'").as_deref(), Some(" 'x'"));
+    assert_eq!(selected(text, (0, 20), 1, "i`"), None);
+}
+
+#[test]
+fn brackets_skip_strings() {
+    let text = "f(a, \")\", g(b))";
+    assert_eq!(
+        selected(text, (0, 3), 1, "i(").as_deref(),
+        Some("a, \")\", g(b)")
+    );
+    assert_eq!(selected(text, (0, 12), 1, "ab").as_deref(), Some("(b)"));
+    assert_eq!(
+        selected(text, (0, 12), 2, "a)").as_deref(),
+        Some("(a, \")\", g(b))")
+    );
+    assert_eq!(selected(text, (0, 6), 1, "i(").as_deref(), None);
+    let block = "fn x() {\n    a;\n    b;\n}";
+    assert_eq!(
+        selected(block, (1, 4), 1, "i{").as_deref(),
+        Some("lines 1-2")
+    );
+    assert_eq!(
+        selected(block, (1, 4), 1, "a{").as_deref(),
+        Some("{\n    a;\n    b;\n}")
+    );
+    assert_eq!(selected("<a<b>>", (0, 3), 1, "i<").as_deref(), Some("b"));
+}
+
+#[test]
+fn tags() {
+    let text = "<div><p>one <b>two</b></p><br/></div>";
+    assert_eq!(selected(text, (0, 16), 1, "it").as_deref(), Some("two"));
+    assert_eq!(
+        selected(text, (0, 16), 2, "it").as_deref(),
+        Some("one <b>two</b>")
+    );
+    assert_eq!(
+        selected(text, (0, 9), 1, "at").as_deref(),
+        Some("<p>one <b>two</b></p>")
+    );
+    assert_eq!(
+        selected(text, (0, 27), 1, "it").as_deref(),
+        Some("<p>one <b>two</b></p><br/>")
+    );
+    let nested = "<i>a<i>b</i>c</i>";
+    assert_eq!(selected(nested, (0, 7), 1, "it").as_deref(), Some("b"));
+    assert_eq!(
+        selected(nested, (0, 12), 1, "it").as_deref(),
+        Some("a<i>b</i>c")
+    );
+    assert_eq!(
+        selected(nested, (0, 4), 1, "at").as_deref(),
+        Some("<i>b</i>")
+    );
+    assert_eq!(
+        selected(nested, (0, 9), 1, "at").as_deref(),
+        Some("<i>b</i>")
+    );
+    assert_eq!(selected("<p>a</p> b", (0, 9), 1, "it"), None);
+}
+
+#[test]
+fn tags_read_past_many_unclosed_ones() {
+    let before = "<br>".repeat(20_000);
+    let after = "<p>y</p>".repeat(20_000);
+    let text = format!("<div><ul>{}x{}</div>", before, after);
+    let inner = selected(&text, (0, 9 + before.len()), 1, "it").unwrap();
+    assert_eq!(inner.len(), before.len() + 1 + after.len() + 4);
+    assert!(inner.starts_with("<ul><br>") && inner.ends_with("</p>"));
+    assert_eq!(selected(&text, (0, 9 + before.len()), 2, "it"), None);
+}